@@ -0,0 +1,44 @@
+use touhou_formats::th06::anm0::Anm0;
+use touhou_formats::th06::anm0_script;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} c <ANM file> <script file>", program);
+    eprintln!("       {} x <ANM file>", program);
+    std::process::exit(1);
+}
+
+fn main() -> io::Result<()> {
+    // Parse arguments.
+    let args: Vec<_> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("c") if args.len() == 4 => {
+            let text = fs::read_to_string(&args[3])?;
+            let anms = match anm0_script::parse(&text) {
+                Ok(anms) => anms,
+                Err(err) => {
+                    eprintln!("{}:{}", args[3], err);
+                    std::process::exit(1);
+                }
+            };
+            let mut output = BufWriter::new(File::create(&args[2])?);
+            Anm0::write_entries(&anms, &mut output)?;
+        }
+        Some("x") if args.len() == 3 => {
+            let buf = fs::read(&args[2])?;
+            let anms = match Anm0::from_slice(&buf) {
+                Ok(anms) => anms,
+                Err(err) => {
+                    eprintln!("{}: {}", args[2], err);
+                    std::process::exit(1);
+                }
+            };
+            print!("{}", anm0_script::dump(&anms));
+        }
+        _ => usage(&args[0]),
+    }
+
+    Ok(())
+}
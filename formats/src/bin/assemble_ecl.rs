@@ -0,0 +1,47 @@
+use touhou_formats::th06::ecl::Ecl;
+use touhou_formats::th06::ecl_script;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+
+fn main() -> io::Result<()> {
+    // Parse arguments.
+    let args: Vec<_> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <text or JSON file> <ECL file>", args[0]);
+        std::process::exit(1);
+    }
+
+    let text = fs::read_to_string(&args[1])?;
+    let ecl = if args[1].ends_with(".json") {
+        parse_json(&args[1], &text)
+    } else {
+        match ecl_script::parse(&text) {
+            Ok(ecl) => ecl,
+            Err(err) => {
+                eprintln!("{}:{}", args[1], err);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let mut output = BufWriter::new(File::create(&args[2])?);
+    ecl.write(&mut output)
+}
+
+#[cfg(feature = "serde")]
+fn parse_json(filename: &str, text: &str) -> Ecl {
+    match serde_json::from_str(text) {
+        Ok(ecl) => ecl,
+        Err(err) => {
+            eprintln!("{}: {}", filename, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn parse_json(_filename: &str, _text: &str) -> Ecl {
+    eprintln!("JSON input requires the serde feature.");
+    std::process::exit(1);
+}
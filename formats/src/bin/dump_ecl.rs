@@ -1,4 +1,5 @@
-use touhou_formats::th06::ecl::{Ecl, CallMain, CallSub, Rank};
+use touhou_formats::th06::ecl::Ecl;
+use touhou_formats::th06::ecl_script;
 use std::env;
 use std::path::Path;
 use std::fs::File;
@@ -12,51 +13,43 @@ pub fn load_file_into_vec<P: AsRef<Path>>(filename: P) -> io::Result<Vec<u8>> {
     Ok(buf)
 }
 
-fn format_rank(rank: &Rank) -> String {
-    format!("{}{}{}{}", if rank.contains(Rank::EASY) { 'E' } else { ' ' },
-                        if rank.contains(Rank::NORMAL) { 'N' } else { ' ' },
-                        if rank.contains(Rank::HARD) { 'H' } else { ' ' },
-                        if rank.contains(Rank::LUNATIC) { 'L' } else { ' ' })
-}
-
-fn print_sub_instruction(call: &CallSub) {
-    let CallSub { time, rank_mask, param_mask: _, instr } = call;
-    println!("    {:>5}: {}: {:?}", time, format_rank(rank_mask), instr);
-}
-
-fn print_main_instruction(call: &CallMain) {
-    let CallMain { time, sub, instr } = call;
-    println!("    {:>5}: sub {:>2}: {:?}", time, sub, instr);
-}
-
 fn main() {
     // Parse arguments.
     let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <ECL file>", args[0]);
-        return;
-    }
-    let ecl_filename = Path::new(&args[1]);
+    let (json, ecl_filename) = match args.as_slice() {
+        [_, filename] => (false, filename),
+        [_, flag, filename] if flag == "--json" => (true, filename),
+        _ => {
+            eprintln!("Usage: {} [--json] <ECL file>", args[0]);
+            return;
+        }
+    };
+    let ecl_filename = Path::new(ecl_filename);
 
     // Open the ECL file.
     let buf = load_file_into_vec(ecl_filename).unwrap();
-    let (_, ecl) = Ecl::from_slice(&buf).unwrap();
-
-    for (i, main) in ecl.mains.iter().enumerate() {
-        println!("Main {} {{", i);
-        for call in main.instructions.iter() {
-            print_main_instruction(call);
+    let ecl = match Ecl::from_slice(&buf) {
+        Ok(ecl) => ecl,
+        Err(err) => {
+            eprintln!("{}: {}", ecl_filename.display(), err);
+            std::process::exit(1);
         }
-        println!("}}");
-        println!();
-    }
+    };
 
-    for (i, sub) in ecl.subs.iter().enumerate() {
-        println!("Sub {} {{", i);
-        for call in sub.instructions.iter() {
-            print_sub_instruction(call);
-        }
-        println!("}}");
-        println!();
+    if json {
+        print_json(&ecl);
+    } else {
+        print!("{}", ecl_script::dump(&ecl));
     }
 }
+
+#[cfg(feature = "serde")]
+fn print_json(ecl: &Ecl) {
+    println!("{}", serde_json::to_string_pretty(ecl).unwrap());
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json(_ecl: &Ecl) {
+    eprintln!("JSON output requires the serde feature.");
+    std::process::exit(1);
+}
@@ -0,0 +1,237 @@
+//! Command-line tool to inspect and build the files of the game.
+//!
+//! Every command writes its results to stdout as one line per item, with tab-separated fields,
+//! while errors and summaries go to stderr.  The order of the fields won’t change, new ones can
+//! only be appended at the end of a line.
+//!
+//! `dump` is the exception: ANM and ECL files are written in their text formats, the ones read
+//! by `anm_script` and `assemble_ecl`.  STD and MSG files have no text format, so they can only
+//! be dumped with `--json`, which works for every file type and is what `import` reads back.
+
+use touhou_formats::th06::anm0::Anm0;
+use touhou_formats::th06::anm0_script;
+use touhou_formats::th06::ecl::Ecl;
+use touhou_formats::th06::ecl_script;
+use touhou_formats::th06::pbg3::{self, PBG3Writer};
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File, create_dir_all};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} list <DAT file>", program);
+    eprintln!("       {} extract <DAT file> <output dir> [pattern…]", program);
+    eprintln!("       {} verify <DAT file>", program);
+    eprintln!("       {} pack <DAT file> <file…>", program);
+    eprintln!("       {} dump <ANM or ECL file>", program);
+    eprintln!("       {} dump --json <ANM, ECL, STD or MSG file>", program);
+    eprintln!("       {} import <JSON file> <ANM, ECL or STD file>", program);
+    std::process::exit(1);
+}
+
+/// Match a file name against a pattern, where `*` matches any number of characters and `?`
+/// matches exactly one.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => glob_match(rest, name) || (!name.is_empty() && glob_match(pattern, &name[1..])),
+        (Some((b'?', rest)), Some((_, name))) => glob_match(rest, name),
+        (Some((a, rest)), Some((b, name))) => a.eq_ignore_ascii_case(b) && glob_match(rest, name),
+        _ => false,
+    }
+}
+
+/// List name, size, compressed size, checksum, offset and both unknowns of every entry.
+fn list(filename: &str) -> io::Result<()> {
+    let pbg3 = pbg3::from_path_buffered(filename)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for (name, entry) in pbg3.entries() {
+        writeln!(stdout, "{}\t{}\t{}\t0x{:08x}\t0x{:08x}\t{}\t{}", name, entry.size, entry.compressed_size,
+                 entry.checksum, entry.offset, entry.unknown_1, entry.unknown_2)?;
+    }
+    Ok(())
+}
+
+/// Turn the name of an entry into a relative path, keeping its directories but never letting it
+/// escape the output directory.
+fn entry_path(name: &str) -> io::Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in name.split(['/', '\\']) {
+        match component {
+            "" | "." => (),
+            ".." => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid file name in PBG3: {}", name))),
+            component => path.push(component),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid file name in PBG3: {}", name)));
+    }
+    Ok(path)
+}
+
+/// Extract every entry matching any of these patterns, or all of them if there is none.
+fn extract(filename: &str, output_dir: &str, patterns: &[String]) -> io::Result<()> {
+    let mut pbg3 = pbg3::from_path_buffered(filename)?;
+    let names: Vec<String> = pbg3.entries()
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| patterns.is_empty() || patterns.iter().any(|pattern| glob_match(pattern.as_bytes(), name.as_bytes())))
+        .map(String::from)
+        .collect();
+
+    let output_dir = Path::new(output_dir);
+    create_dir_all(output_dir)?;
+    let mut extracted = HashSet::new();
+    for name in names {
+        let path = output_dir.join(entry_path(&name)?);
+        if !extracted.insert(path.clone()) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("Two entries would be extracted to {}", path.display())));
+        }
+        let data = pbg3.get_file(&name, true)?;
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        fs::write(&path, &data)?;
+        println!("{}\t{}", name, path.display());
+    }
+    Ok(())
+}
+
+/// Check every entry, reporting each of them instead of stopping at the first error.
+fn verify(filename: &str) -> io::Result<bool> {
+    let mut pbg3 = pbg3::from_path_buffered(filename)?;
+    let names: Vec<String> = pbg3.entries().into_iter().map(|(name, _)| String::from(name)).collect();
+    let mut failed = 0;
+    for name in names.iter() {
+        match pbg3.get_file(name, true) {
+            Ok(_) => println!("{}\tok", name),
+            Err(err) => {
+                println!("{}\terror\t{}", name, err);
+                failed += 1;
+            }
+        }
+    }
+    eprintln!("{} files checked, {} failed", names.len(), failed);
+    Ok(failed == 0)
+}
+
+/// Build an archive from these files, named after their basename.
+fn pack(filename: &str, inputs: &[String]) -> io::Result<()> {
+    let output = BufWriter::new(File::create(filename)?);
+    let mut writer = PBG3Writer::new(output);
+    for input in inputs {
+        let name = Path::new(input)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid file name: {}", input)))?;
+        let data = fs::read(input)?;
+        writer.add_file(name, &data)?;
+        println!("{}\t{}", name, data.len());
+    }
+    writer.finish()?.flush()
+}
+
+/// Return the lowercase extension of this file name, which tells its format.
+fn file_type(filename: &str) -> String {
+    Path::new(filename)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default()
+}
+
+fn unknown_file_type(filename: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown file type: {}", filename))
+}
+
+/// Print a file in its text format.
+fn dump(filename: &str) -> io::Result<()> {
+    let buf = fs::read(filename)?;
+    match file_type(filename).as_str() {
+        "anm" => print!("{}", anm0_script::dump(&Anm0::from_slice(&buf)?)),
+        "ecl" => print!("{}", ecl_script::dump(&Ecl::from_slice(&buf)?)),
+        "std" | "msg" => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("No text format for {}, use --json", filename))),
+        _ => return Err(unknown_file_type(filename)),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+mod json {
+    use super::*;
+    use touhou_formats::th06::msg::Msg;
+    use touhou_formats::th06::std::Stage;
+
+    fn print<T: serde::Serialize>(value: &T) -> io::Result<()> {
+        println!("{}", serde_json::to_string_pretty(value)?);
+        Ok(())
+    }
+
+    /// Print a file as JSON.
+    pub fn dump(filename: &str) -> io::Result<()> {
+        let buf = fs::read(filename)?;
+        match file_type(filename).as_str() {
+            "anm" => print(&Anm0::from_slice(&buf)?),
+            "ecl" => print(&Ecl::from_slice(&buf)?),
+            "std" => print(&Stage::from_slice(&buf)?),
+            "msg" => print(&Msg::from_slice(&buf)?),
+            _ => Err(unknown_file_type(filename)),
+        }
+    }
+
+    /// Build a file from the JSON output of `dump`.
+    pub fn import(input: &str, output: &str) -> io::Result<()> {
+        let text = fs::read_to_string(input)?;
+        let mut data = vec![];
+        match file_type(output).as_str() {
+            "anm" => Anm0::write_entries(&serde_json::from_str::<Vec<Anm0>>(&text)?, &mut data)?,
+            "ecl" => serde_json::from_str::<Ecl>(&text)?.write(&mut data)?,
+            "std" => serde_json::from_str::<Stage>(&text)?.write(&mut data)?,
+            _ => return Err(unknown_file_type(output)),
+        }
+        fs::write(output, &data)
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+mod json {
+    use std::io;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "JSON support requires the serde feature.")
+    }
+
+    pub fn dump(_filename: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn import(_input: &str, _output: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
+
+fn main() {
+    // Parse arguments.
+    let args: Vec<_> = env::args().collect();
+    let result = match args.get(1).map(String::as_str) {
+        Some("list") if args.len() == 3 => list(&args[2]),
+        Some("extract") if args.len() >= 4 => extract(&args[2], &args[3], &args[4..]),
+        Some("verify") if args.len() == 3 => match verify(&args[2]) {
+            Ok(true) => Ok(()),
+            Ok(false) => std::process::exit(2),
+            Err(err) => Err(err),
+        },
+        Some("pack") if args.len() >= 4 => pack(&args[2], &args[3..]),
+        Some("dump") if args.len() == 3 => dump(&args[2]),
+        Some("dump") if args.len() == 4 && args[2] == "--json" => json::dump(&args[3]),
+        Some("import") if args.len() == 4 => json::import(&args[2], &args[3]),
+        _ => usage(&args[0]),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,222 @@
+//! Errors returned when parsing any of the supported formats.
+
+use core::fmt;
+use nom::error::{ErrorKind, ParseError};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io;
+
+/// The format of the file which failed to parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// An ANM0 animation file.
+    Anm0,
+
+    /// An ECL enemy script.
+    Ecl,
+
+    /// An STD stage background.
+    Std,
+
+    /// A MSG dialog script.
+    Msg,
+
+    /// A T6RP replay.
+    T6rp,
+
+    /// The score.dat file.
+    Score,
+
+    /// A SHT player shot file.
+    Sht,
+
+    /// The thbgm.fmt music table.
+    Fmt,
+
+    /// A .pos music loop file.
+    Pos,
+
+    /// A THTX texture.
+    Thtx,
+
+    /// A PBG3 archive.
+    Pbg3,
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FileKind::Anm0 => "ANM0",
+            FileKind::Ecl => "ECL",
+            FileKind::Std => "STD",
+            FileKind::Msg => "MSG",
+            FileKind::T6rp => "T6RP",
+            FileKind::Score => "score.dat",
+            FileKind::Sht => "SHT",
+            FileKind::Fmt => "thbgm.fmt",
+            FileKind::Pos => "pos",
+            FileKind::Thtx => "THTX",
+            FileKind::Pbg3 => "PBG3",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What went wrong while parsing a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// A magic value or a field with a fixed value didn’t match.
+    BadMagic,
+
+    /// An instruction uses an opcode which doesn’t exist.
+    UnknownOpcode(u16),
+
+    /// The size of an entry doesn’t match the size of its contents.
+    SizeMismatch {
+        /// The size declared in the file.
+        expected: usize,
+
+        /// The size actually read.
+        found: usize,
+    },
+
+    /// An offset points outside of the file, or to something which doesn’t exist.
+    BadOffset(u32),
+
+    /// A string isn’t valid in its encoding.
+    BadEncoding,
+
+    /// The checksum doesn’t match the contents.
+    BadChecksum,
+
+    /// A field contains a value which isn’t allowed.
+    InvalidValue,
+
+    /// The file ended in the middle of an entry.
+    Truncated,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::BadMagic => write!(f, "bad magic"),
+            Reason::UnknownOpcode(opcode) => write!(f, "unknown opcode {}", opcode),
+            Reason::SizeMismatch { expected, found } => write!(f, "size mismatch, expected {} bytes but found {}", expected, found),
+            Reason::BadOffset(offset) => write!(f, "bad offset 0x{:x}", offset),
+            Reason::BadEncoding => write!(f, "bad string encoding"),
+            Reason::BadChecksum => write!(f, "bad checksum"),
+            Reason::InvalidValue => write!(f, "invalid value"),
+            Reason::Truncated => write!(f, "truncated file"),
+        }
+    }
+}
+
+/// Error returned by every `from_slice` function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// Offset in the file at which the error happened.
+    pub offset: usize,
+
+    /// The format being parsed.
+    pub kind: FileKind,
+
+    /// What went wrong.
+    pub reason: Reason,
+}
+
+impl Error {
+    /// Convert an error from the parser of this file into an `Error`.
+    pub(crate) fn from_nom(kind: FileKind, data: &[u8], err: nom::Err<NomError<&[u8]>>) -> Error {
+        match err {
+            nom::Err::Incomplete(_) => Error {
+                offset: data.len(),
+                kind,
+                reason: Reason::Truncated,
+            },
+            nom::Err::Error(err) | nom::Err::Failure(err) => Error {
+                offset: data.len().saturating_sub(err.input.len()),
+                kind,
+                reason: err.reason,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {} file: {} at offset 0x{:x}", self.kind, self.reason, self.offset)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Error type used by all of our nom parsers, remembering why they failed.
+#[derive(Debug)]
+pub(crate) struct NomError<I> {
+    pub(crate) input: I,
+    pub(crate) reason: Reason,
+}
+
+impl<I> NomError<I> {
+    pub(crate) fn new(input: I, reason: Reason) -> NomError<I> {
+        NomError { input, reason }
+    }
+}
+
+impl<I> ParseError<I> for NomError<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> NomError<I> {
+        let reason = match kind {
+            ErrorKind::Eof | ErrorKind::Complete => Reason::Truncated,
+            ErrorKind::Tag => Reason::BadMagic,
+            _ => Reason::InvalidValue,
+        };
+        NomError { input, reason }
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: NomError<I>) -> NomError<I> {
+        other
+    }
+}
+
+/// Result type of all of our nom parsers.
+pub(crate) type IResult<I, O> = nom::IResult<I, O, NomError<I>>;
+
+/// Abort parsing at this input, for this reason.
+pub(crate) fn failure<I, O>(input: I, reason: Reason) -> IResult<I, O> {
+    Err(nom::Err::Failure(NomError::new(input, reason)))
+}
+
+/// Return the data starting at this offset of the file.
+pub(crate) fn at_offset(input: &[u8], offset: u32) -> Result<&[u8], nom::Err<NomError<&[u8]>>> {
+    input.get(offset as usize..).ok_or_else(|| nom::Err::Failure(NomError::new(input, Reason::BadOffset(offset))))
+}
+
+/// Parse items until `parser` returns `None` on the list’s terminator.
+///
+/// Any error in between is a hard failure, a list is never ended by bad or missing data.
+pub(crate) fn until_terminator<'a, T, F>(parser: F) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Vec<T>>
+where F: Fn(&'a [u8]) -> IResult<&'a [u8], Option<T>>
+{
+    move |mut i| {
+        let mut items = Vec::new();
+        loop {
+            match parser(i) {
+                Ok((i2, Some(item))) => {
+                    items.push(item);
+                    i = i2;
+                }
+                Ok((i2, None)) => return Ok((i2, items)),
+                Err(nom::Err::Error(err)) => return Err(nom::Err::Failure(err)),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
@@ -1,6 +1,14 @@
 #![feature(concat_idents)]
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
 //! Touhou formats.
+//!
+//! Without the default `std` feature, only the parsers are available, using `core` and `alloc`.
 
+extern crate alloc;
+
+pub mod error;
 pub mod th06;
+
+pub use error::Error;
@@ -1,16 +1,22 @@
 //! ANM0 animation format support.
 
+use crate::error::{Error, FileKind, Reason, IResult, failure, at_offset};
+use crate::th06::thtx::{Format, Texture, parse_texture};
+#[cfg(feature = "std")]
+use crate::th06::thtx::write_texture;
 use nom::{
-    IResult,
-    bytes::complete::{tag, take_while_m_n},
+    bytes::complete::{tag, take, take_while_m_n},
     number::complete::{le_u8, le_u16, le_u32, le_i32, le_f32},
     sequence::tuple,
-    multi::{many_m_n, many0},
+    multi::many_m_n,
 };
-use std::collections::BTreeMap;
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::io;
 
 /// Coordinates of a sprite into the image.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sprite {
     /// Index inside the anm0.
     pub index: u32,
@@ -30,6 +36,7 @@ pub struct Sprite {
 
 /// A single instruction, part of a `Script`.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Call {
     /// Time at which this instruction will be called.
     pub time: u16,
@@ -40,23 +47,44 @@ pub struct Call {
 
 /// Script driving an animation.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Script {
     /// List of instructions in this script.
     pub instructions: Vec<Call>,
 
     /// List of interrupts in this script.
-    pub interrupts: BTreeMap<i32, u8>
+    pub interrupts: BTreeMap<i32, usize>
+}
+
+impl Script {
+    /// Create a script from its instructions, each `InterruptLabel` pointing its interrupt to the
+    /// instruction following it.
+    pub fn new(instructions: Vec<Call>) -> Script {
+        let interrupts = find_interrupts(&instructions);
+        Script {
+            instructions,
+            interrupts,
+        }
+    }
+}
+
+/// Map every interrupt to the instruction following its label.
+pub(crate) fn find_interrupts(instructions: &[Call]) -> BTreeMap<i32, usize> {
+    instructions.iter().enumerate().filter_map(|(index, call)| match call.instr {
+        Instruction::InterruptLabel(label) => Some((label, index + 1)),
+        _ => None,
+    }).collect()
 }
 
 /// Main struct of the ANM0 animation format.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Anm0 {
     /// Resolution of the image used by this ANM.
     pub size: (u32, u32),
 
-    /// Format of this ANM.
-    // TODO: use an enum for that.
-    pub format: u32,
+    /// Pixel format of the image used by this ANM.
+    pub format: Format,
 
     /// Color key, probably used for transparency.
     pub color_key: u32,
@@ -72,12 +100,41 @@ pub struct Anm0 {
 
     /// A map of scripts.
     pub scripts: BTreeMap<u8, Script>,
+
+    /// Texture embedded in this ANM, if any.
+    pub texture: Option<Texture>,
 }
 
 impl Anm0 {
     /// Parse a slice of bytes into an `Anm0` struct.
-    pub fn from_slice(data: &[u8]) -> IResult<&[u8], Vec<Anm0>> {
-        many0(parse_anm0)(data)
+    pub fn from_slice(data: &[u8]) -> Result<Vec<Anm0>, Error> {
+        match parse_anm0s(data) {
+            Ok((_, anms)) => Ok(anms),
+            Err(err) => Err(Error::from_nom(FileKind::Anm0, data, err)),
+        }
+    }
+
+    /// Serialize this `Anm0` into a file, as a single entry.
+    #[cfg(feature = "std")]
+    pub fn write<W: io::Write>(&self, file: &mut W) -> io::Result<()> {
+        file.write_all(&write_anm0(self)?)
+    }
+
+    /// Serialize a list of `Anm0` into a file, each entry pointing to the next one.
+    #[cfg(feature = "std")]
+    pub fn write_entries<W: io::Write>(anms: &[Anm0], file: &mut W) -> io::Result<()> {
+        if anms.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "An ANM file needs at least one entry"));
+        }
+        for (index, anm0) in anms.iter().enumerate() {
+            let mut data = write_anm0(anm0)?;
+            if index + 1 < anms.len() {
+                let next_offset = data.len() as u32;
+                data[56..60].copy_from_slice(&next_offset.to_le_bytes());
+            }
+            file.write_all(&data)?;
+        }
+        Ok(())
     }
 
     /// TODO
@@ -91,8 +148,7 @@ fn parse_name(i: &[u8]) -> IResult<&[u8], String> {
     let (_, slice) = take_while_m_n(0, 32, |c| c != 0)(i)?;
     let string = match String::from_utf8(slice.to_vec()) {
         Ok(string) => string,
-        // XXX: use a more specific error instead.
-        Err(_) => return Err(nom::Err::Failure(nom::error::Error::new(i, nom::error::ErrorKind::Eof)))
+        Err(_) => return failure(i, Reason::BadEncoding),
     };
     Ok((i, string))
 }
@@ -112,14 +168,24 @@ macro_rules! declare_anm_instructions {
     ($($opcode:tt => fn $name:ident($($arg:ident: $arg_type:ident),*)),*,) => {
         /// Available instructions in an `Anm0`.
         #[allow(missing_docs)]
-        #[derive(Debug, Clone, Copy)]
+        #[derive(Debug, Clone)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum Instruction {
             $(
-                $name($($arg_type),*)
-            ),*
+                $name($($arg_type),*),
+            )*
+
+            /// An instruction whose opcode isn’t known, kept as is.
+            Unknown {
+                /// Its opcode.
+                opcode: u8,
+
+                /// Its raw arguments.
+                args: Vec<u8>,
+            },
         }
 
-        fn parse_instruction_args(mut i: &[u8], opcode: u8) -> IResult<&[u8], Instruction> {
+        pub(crate) fn parse_instruction_args(mut i: &[u8], opcode: u8) -> IResult<&[u8], Instruction> {
             let instr = match opcode {
                 $(
                     $opcode => {
@@ -130,11 +196,47 @@ macro_rules! declare_anm_instructions {
                         Instruction::$name($($arg),*)
                     }
                 )*
-                // XXX: use a more specific error instead.
-                _ => return Err(nom::Err::Failure(nom::error::Error::new(i, nom::error::ErrorKind::Eof)))
+                _ => {
+                    let args = i.to_vec();
+                    i = &i[i.len()..];
+                    Instruction::Unknown { opcode, args }
+                }
             };
             Ok((i, instr))
         }
+
+        impl Instruction {
+            pub(crate) fn opcode(&self) -> u8 {
+                match self {
+                    $(
+                        Instruction::$name(..) => $opcode,
+                    )*
+                    Instruction::Unknown { opcode, .. } => *opcode,
+                }
+            }
+
+            pub(crate) fn arg_types(opcode: u8) -> Option<&'static [&'static str]> {
+                match opcode {
+                    $(
+                        $opcode => Some(&[$(stringify!($arg_type)),*]),
+                    )*
+                    _ => None
+                }
+            }
+        }
+
+        pub(crate) fn write_instruction_args(data: &mut Vec<u8>, instr: &Instruction) {
+            match instr {
+                $(
+                    Instruction::$name($($arg),*) => {
+                        $(
+                            data.extend_from_slice(&$arg.to_le_bytes());
+                        )*
+                    }
+                )*
+                Instruction::Unknown { args, .. } => data.extend_from_slice(args),
+            }
+        }
     };
 }
 
@@ -143,7 +245,7 @@ declare_anm_instructions!{
     1 => fn LoadSprite(sprite_number: u32),
     2 => fn SetScale(sx: f32, sy: f32),
     3 => fn SetAlpha(alpha: u32),
-    4 => fn SetColor(red: u8, green: u8, blue: u8/*, XXX: x8*/),
+    4 => fn SetColor(red: u8, green: u8, blue: u8, padding: u8),
     5 => fn Jump(instruction: u32),
     7 => fn ToggleMirrored(),
     9 => fn SetRotations3d(x: f32, y: f32, z: f32),
@@ -171,16 +273,34 @@ declare_anm_instructions!{
     31 => fn Todo(todo: u32),
 }
 
-fn parse_anm0(input: &[u8]) -> IResult<&[u8], Anm0> {
+fn parse_anm0s(input: &[u8]) -> IResult<&[u8], Vec<Anm0>> {
+    let mut anms = vec![];
+    let mut i = input;
+    loop {
+        let (_, (anm0, next_offset)) = parse_anm0(i)?;
+        anms.push(anm0);
+        if next_offset == 0 {
+            break;
+        }
+        i = at_offset(i, next_offset)?;
+    }
+    Ok((i, anms))
+}
+
+fn parse_anm0(input: &[u8]) -> IResult<&[u8], (Anm0, u32)> {
     let (i, (num_sprites, num_scripts, _, width, height, format, color_key,
              first_name_offset, _, second_name_offset, version, _,
-             _texture_offset, has_data, _next_offset, _)) =
+             texture_offset, has_data, next_offset, _)) =
         tuple((le_u32, le_u32, tag(b"\0\0\0\0"), le_u32, le_u32, le_u32, le_u32, le_u32,
                tag(b"\0\0\0\0"), le_u32, le_u32, tag(b"\0\0\0\0"), le_u32, le_u32, le_u32,
                tag(b"\0\0\0\0")))(input)?;
 
-    assert_eq!(version, 0);
-    assert_eq!(has_data, 0);
+    if version != 0 {
+        return failure(input, Reason::BadMagic);
+    }
+    if has_data > 1 {
+        return failure(input, Reason::InvalidValue);
+    }
     let num_sprites = num_sprites as usize;
     let num_scripts = num_scripts as usize;
 
@@ -188,10 +308,7 @@ fn parse_anm0(input: &[u8]) -> IResult<&[u8], Anm0> {
     let (_, script_offsets) = many_m_n(num_scripts, num_scripts, tuple((le_u32, le_u32)))(i)?;
 
     let png_filename = if first_name_offset > 0 {
-        if input.len() < first_name_offset as usize {
-            return Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Eof)));
-        }
-        let i = &input[first_name_offset as usize..];
+        let i = at_offset(input, first_name_offset)?;
         let (_, name) = parse_name(i)?;
         name
     } else {
@@ -199,86 +316,210 @@ fn parse_anm0(input: &[u8]) -> IResult<&[u8], Anm0> {
     };
 
     let alpha_filename = if second_name_offset > 0 {
-        if input.len() < second_name_offset as usize {
-            return Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Eof)));
-        }
-        let i = &input[second_name_offset as usize..];
+        let i = at_offset(input, second_name_offset)?;
         let (_, name) = parse_name(i)?;
         Some(name)
     } else {
         None
     };
 
+    let texture = if has_data == 1 {
+        let i = at_offset(input, texture_offset)?;
+        let (_, texture) = parse_texture(i)?;
+        Some(texture)
+    } else {
+        None
+    };
+
     let mut sprites = vec![];
     let mut i = &input[..];
-    for offset in sprite_offsets.into_iter().map(|x| x as usize) {
-        if input.len() < offset {
-            return Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Eof)));
-        }
-        i = &input[offset..];
+    for &offset in sprite_offsets.iter() {
+        i = at_offset(input, offset)?;
         let (_, sprite) = parse_sprite(i)?;
         sprites.push(sprite);
     }
 
+    // Scripts don’t always end with a Delete, for instance when they KeepStill, so each of them
+    // stops at the next known block of this entry, or at the end of the data.
+    let mut boundaries: Vec<usize> = sprite_offsets.iter()
+        .chain(script_offsets.iter().map(|(_, offset)| offset))
+        .chain([first_name_offset, second_name_offset, texture_offset, next_offset].iter())
+        .filter(|&&offset| offset > 0)
+        .map(|&offset| offset as usize)
+        .collect();
+    boundaries.sort_unstable();
+
     let mut scripts = BTreeMap::new();
-    for (index, offset) in script_offsets.into_iter().map(|(index, offset)| (index as u8, offset as usize)) {
-        if input.len() < offset {
-            return Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Eof)));
-        }
-        i = &input[offset..];
+    for (index, offset) in script_offsets {
+        i = at_offset(input, offset)?;
+        let index = index as u8;
+        let offset = offset as usize;
+        let end = boundaries.iter().copied().find(|&boundary| boundary > offset).unwrap_or(input.len());
         let mut instruction_offsets = vec![];
 
         let mut instructions = vec![];
         loop {
             let tell = input.len() - i.len();
+            if tell >= end {
+                break;
+            }
             instruction_offsets.push(tell - offset);
-            // TODO: maybe check against the size of parsed data?
-            let (i2, (time, opcode, _size)) = tuple((le_u16, le_u8, le_u8))(i)?;
-            let (i2, instr) = parse_instruction_args(i2, opcode)?;
+            let (i2, (time, opcode, size)) = tuple((le_u16, le_u8, le_u8))(i)?;
+            // Some instructions are padded, so always skip the entire size.
+            let (i2, data) = take(size as usize)(i2)?;
+            if input.len() - i2.len() > end {
+                return failure(i, Reason::SizeMismatch { expected: end - tell, found: 4 + size as usize });
+            }
+            let instr = match parse_instruction_args(data, opcode)? {
+                (b"", instr) => instr,
+                // Keep instructions with more data than their arguments as is, so that they get
+                // written back unchanged.
+                _ => Instruction::Unknown { opcode, args: data.to_vec() },
+            };
             instructions.push(Call { time, instr });
             i = i2;
             if opcode == 0 {
                 break;
             }
         }
-        let mut interrupts = BTreeMap::new();
-        let mut j = 0;
-        for Call { time: _, instr } in &mut instructions {
+        if instructions.is_empty() {
+            return failure(i, Reason::Truncated);
+        }
+        for Call { time: _, instr } in instructions.iter_mut() {
             match instr {
                 Instruction::Jump(ref mut offset) => {
                     let result = instruction_offsets.binary_search(&(*offset as usize));
                     match result {
                         Ok(ptr) => *offset = ptr as u32,
-                        Err(ptr) => {
-                            // XXX: use a more specific error instead.
-                            return Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Eof)));
-                            //println!("Instruction offset not found for pointer: {}", ptr);
-                        }
+                        Err(_) => return failure(input, Reason::BadOffset(*offset)),
                     }
                 }
-                Instruction::InterruptLabel(interrupt) => {
-                    interrupts.insert(*interrupt, j + 1);
-                }
                 _ => ()
             }
-            j += 1;
         }
-        scripts.insert(index, Script {
-            instructions,
-            interrupts,
-        });
+        scripts.insert(index, Script::new(instructions));
     }
 
     let anm0 = Anm0 {
         size: (width, height),
-        format,
+        format: Format::from(format),
         color_key,
         png_filename,
         alpha_filename,
         sprites,
         scripts,
+        texture,
     };
-    Ok((i, anm0))
+    Ok((i, (anm0, next_offset)))
+}
+
+#[cfg(feature = "std")]
+fn write_name(data: &mut Vec<u8>, name: &str) {
+    // Names are always terminated, then padded to 16 bytes, so one whose length is already a
+    // multiple of 16 still gets a whole block of zeroes for its terminator.
+    data.extend_from_slice(name.as_bytes());
+    data.resize(data.len() + (name.len() + 1).next_multiple_of(16) - name.len(), 0);
+}
+
+#[cfg(feature = "std")]
+fn write_script(data: &mut Vec<u8>, script: &Script) -> io::Result<()> {
+    // Interrupts are only stored as labels in the instructions.
+    if script.interrupts != find_interrupts(&script.instructions) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Interrupts don’t match the InterruptLabel instructions of their script"));
+    }
+
+    let mut args = vec![];
+    let mut instruction_offsets = vec![];
+    let mut offset = 0;
+    for Call { time: _, instr } in script.instructions.iter() {
+        instruction_offsets.push(offset as u32);
+        args.clear();
+        write_instruction_args(&mut args, instr);
+        offset += 4 + args.len().next_multiple_of(4);
+    }
+
+    for Call { time, instr } in script.instructions.iter() {
+        let instr = match *instr {
+            Instruction::Jump(index) => match instruction_offsets.get(index as usize) {
+                Some(&offset) => Instruction::Jump(offset),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Jump to nonexistent instruction {}", index))),
+            },
+            ref instr => instr.clone(),
+        };
+        args.clear();
+        write_instruction_args(&mut args, &instr);
+        args.resize(args.len().next_multiple_of(4), 0);
+        data.extend_from_slice(&time.to_le_bytes());
+        data.push(instr.opcode());
+        data.push(args.len() as u8);
+        data.extend_from_slice(&args);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn write_anm0(anm0: &Anm0) -> io::Result<Vec<u8>> {
+    let tables_size = 64 + 4 * anm0.sprites.len() + 8 * anm0.scripts.len();
+    let mut data = vec![0u8; tables_size];
+
+    let first_name_offset = if anm0.png_filename.is_empty() {
+        0
+    } else {
+        let offset = data.len();
+        write_name(&mut data, &anm0.png_filename);
+        offset
+    };
+    let second_name_offset = match anm0.alpha_filename {
+        Some(ref name) => {
+            let offset = data.len();
+            write_name(&mut data, name);
+            offset
+        }
+        None => 0,
+    };
+
+    let mut sprite_offsets = vec![];
+    for sprite in anm0.sprites.iter() {
+        sprite_offsets.push(data.len() as u32);
+        data.extend_from_slice(&sprite.index.to_le_bytes());
+        for value in [sprite.x, sprite.y, sprite.width, sprite.height] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    let mut script_offsets = vec![];
+    for (&index, script) in anm0.scripts.iter() {
+        script_offsets.push((index as u32, data.len() as u32));
+        write_script(&mut data, script)?;
+    }
+
+    let (texture_offset, has_data) = match anm0.texture {
+        Some(ref texture) => {
+            let offset = data.len();
+            write_texture(&mut data, texture);
+            (offset as u32, 1)
+        }
+        None => (0, 0),
+    };
+
+    let (width, height) = anm0.size;
+    let header = [
+        anm0.sprites.len() as u32, anm0.scripts.len() as u32, 0,
+        width, height, u32::from(anm0.format), anm0.color_key,
+        first_name_offset as u32, 0, second_name_offset as u32,
+        // version, unknown, texture_offset, has_data, next_offset, unknown
+        0, 0, texture_offset, has_data, 0, 0,
+    ];
+    let mut tables = Vec::with_capacity(tables_size);
+    for value in header.iter().chain(sprite_offsets.iter()) {
+        tables.extend_from_slice(&value.to_le_bytes());
+    }
+    for (index, offset) in script_offsets {
+        tables.extend_from_slice(&index.to_le_bytes());
+        tables.extend_from_slice(&offset.to_le_bytes());
+    }
+    data[..tables_size].copy_from_slice(&tables);
+    Ok(data)
 }
 
 #[cfg(test)]
@@ -293,10 +534,314 @@ mod tests {
         let mut file = io::BufReader::new(file);
         let mut buf = vec![];
         file.read_to_end(&mut buf).unwrap();
-        let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
+        let mut anms = Anm0::from_slice(&buf).unwrap();
         assert_eq!(anms.len(), 1);
         let anm0 = anms.pop().unwrap();
         assert_eq!(anm0.size, (256, 256));
-        assert_eq!(anm0.format, 5);
+        assert_eq!(anm0.format, Format::Argb4444);
+        assert_eq!(anm0.texture, None);
+
+        let mut data = vec![];
+        anm0.write(&mut data).unwrap();
+        assert_eq!(data, buf);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut scripts = BTreeMap::new();
+        scripts.insert(3, Script::new(vec![
+            Call { time: 0, instr: Instruction::LoadSprite(1) },
+            Call { time: 0, instr: Instruction::SetColor(255, 128, 0, 0) },
+            Call { time: 0, instr: Instruction::InterruptLabel(1) },
+            Call { time: 10, instr: Instruction::MoveToLinear(1., 2., 3., 10) },
+            Call { time: 20, instr: Instruction::Jump(2) },
+            Call { time: 20, instr: Instruction::Delete() },
+        ]));
+        let anm0 = Anm0 {
+            size: (256, 128),
+            format: Format::Rgb565,
+            color_key: 0,
+            png_filename: String::from("data/test.png"),
+            alpha_filename: Some(String::from("data/test_a.png")),
+            sprites: vec![
+                Sprite { index: 0, x: 0., y: 0., width: 32., height: 32. },
+                Sprite { index: 1, x: 32., y: 0., width: 32., height: 64. },
+            ],
+            scripts,
+            texture: None,
+        };
+
+        let mut data = vec![];
+        anm0.write(&mut data).unwrap();
+        let mut anms = Anm0::from_slice(&data).unwrap();
+        let parsed = anms.remove(0);
+        assert_eq!(parsed.png_filename, "data/test.png");
+        assert_eq!(parsed.alpha_filename.as_deref(), Some("data/test_a.png"));
+        assert_eq!(parsed.sprites.len(), 2);
+        let script = &parsed.scripts[&3];
+        assert!(matches!(script.instructions[1].instr, Instruction::SetColor(255, 128, 0, 0)));
+        assert!(matches!(script.instructions[4].instr, Instruction::Jump(2)));
+        assert_eq!(script.interrupts[&1], 3);
+
+        let mut data2 = vec![];
+        parsed.write(&mut data2).unwrap();
+        assert_eq!(data, data2);
+    }
+
+    #[test]
+    fn byte_identical() {
+        let mut scripts = BTreeMap::new();
+        scripts.insert(0, Script::new(vec![
+            Call { time: 0, instr: Instruction::SetColor(255, 128, 0, 0xaa) },
+            Call { time: 0, instr: Instruction::InterruptLabel(2) },
+            Call { time: 5, instr: Instruction::InterruptLabel(-1) },
+            // A LoadSprite with a word too many.
+            Call { time: 5, instr: Instruction::Unknown { opcode: 1, args: vec![1, 0, 0, 0, 0xbb, 0, 0, 0] } },
+            Call { time: 10, instr: Instruction::Delete() },
+        ]));
+        let anm0 = Anm0 {
+            size: (256, 256),
+            format: Format::Argb4444,
+            color_key: 0,
+            // Both names are a multiple of 16 bytes long.
+            png_filename: String::from("data/sixteen.png"),
+            alpha_filename: Some(String::from("data/thirty-two_bytes_long_a.png")),
+            sprites: vec![],
+            scripts,
+            texture: None,
+        };
+        let mut data = vec![];
+        anm0.write(&mut data).unwrap();
+        assert_eq!(&data[28..32], &72u32.to_le_bytes());
+        assert_eq!(&data[72..104], b"data/sixteen.png\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0");
+        assert_eq!(&data[36..40], &104u32.to_le_bytes());
+        assert_eq!(&data[152..160], &[0, 0, 4, 4, 255, 128, 0, 0xaa]);
+
+        let parsed = Anm0::from_slice(&data).unwrap();
+        assert_eq!(parsed[0].png_filename, anm0.png_filename);
+        assert_eq!(parsed[0].alpha_filename, anm0.alpha_filename);
+        let script = &parsed[0].scripts[&0];
+        assert!(matches!(script.instructions[0].instr, Instruction::SetColor(255, 128, 0, 0xaa)));
+        assert!(matches!(script.instructions[3].instr, Instruction::Unknown { opcode: 1, .. }));
+        assert_eq!(script.interrupts, [(2, 2), (-1, 3)].iter().copied().collect());
+        let mut data2 = vec![];
+        parsed[0].write(&mut data2).unwrap();
+        assert_eq!(data, data2);
+
+        // Interrupts can’t point anywhere else than after their label.
+        let mut anm0 = anm0;
+        anm0.scripts.get_mut(&0).unwrap().interrupts.insert(2, 4);
+        assert_eq!(anm0.write(&mut vec![]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn errors() {
+        use crate::error::{FileKind, Reason};
+
+        let mut scripts = BTreeMap::new();
+        scripts.insert(0, Script {
+            instructions: vec![Call { time: 0, instr: Instruction::Delete() }],
+            interrupts: BTreeMap::new(),
+        });
+        let anm0 = Anm0 {
+            size: (256, 256),
+            format: Format::Argb4444,
+            color_key: 0,
+            png_filename: String::from("data/test.png"),
+            alpha_filename: None,
+            sprites: vec![Sprite { index: 0, x: 0., y: 0., width: 32., height: 32. }],
+            scripts,
+            texture: None,
+        };
+        let mut data = vec![];
+        anm0.write(&mut data).unwrap();
+
+        // Truncated files must be rejected, never parsed as a shorter file nor make the parser panic.
+        for len in 0..data.len() {
+            assert!(Anm0::from_slice(&data[..len]).is_err(), "prefix of {} bytes was accepted", len);
+        }
+
+        let mut corrupted = data.clone();
+        corrupted[40..44].copy_from_slice(&1u32.to_le_bytes());
+        let err = Anm0::from_slice(&corrupted).unwrap_err();
+        assert_eq!(err.kind, FileKind::Anm0);
+        assert_eq!(err.reason, Reason::BadMagic);
+        assert_eq!(err.offset, 0);
+
+        let mut corrupted = data.clone();
+        corrupted[52..56].copy_from_slice(&2u32.to_le_bytes());
+        assert_eq!(Anm0::from_slice(&corrupted).unwrap_err().reason, Reason::InvalidValue);
+
+        let mut corrupted = data;
+        corrupted[64..68].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert_eq!(Anm0::from_slice(&corrupted).unwrap_err().reason, Reason::BadOffset(0x1000));
+    }
+
+    #[test]
+    fn many_interrupts() {
+        // Scripts can have more labels than fit in a byte.
+        let mut instructions: Vec<_> = (0..300).map(|label| Call { time: 0, instr: Instruction::InterruptLabel(label) }).collect();
+        instructions.push(Call { time: 0, instr: Instruction::Delete() });
+        let mut scripts = BTreeMap::new();
+        scripts.insert(0, Script::new(instructions));
+        let anm0 = Anm0 {
+            size: (256, 256),
+            format: Format::Argb4444,
+            color_key: 0,
+            png_filename: String::new(),
+            alpha_filename: None,
+            sprites: vec![],
+            scripts,
+            texture: None,
+        };
+        let mut data = vec![];
+        anm0.write(&mut data).unwrap();
+        let parsed = Anm0::from_slice(&data).unwrap();
+        let interrupts = &parsed[0].scripts[&0].interrupts;
+        assert_eq!(interrupts.len(), 300);
+        assert_eq!(interrupts[&299], 300);
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let mut scripts = BTreeMap::new();
+        scripts.insert(0, Script {
+            instructions: vec![
+                Call { time: 0, instr: Instruction::Unknown { opcode: 200, args: vec![1, 2, 3, 4] } },
+                Call { time: 1, instr: Instruction::Delete() },
+            ],
+            interrupts: BTreeMap::new(),
+        });
+        let anm0 = Anm0 {
+            size: (256, 256),
+            format: Format::Argb4444,
+            color_key: 0,
+            png_filename: String::new(),
+            alpha_filename: None,
+            sprites: vec![],
+            scripts,
+            texture: None,
+        };
+        let mut data = vec![];
+        anm0.write(&mut data).unwrap();
+        let mut anms = Anm0::from_slice(&data).unwrap();
+        let parsed = anms.remove(0);
+        match &parsed.scripts[&0].instructions[0].instr {
+            Instruction::Unknown { opcode: 200, args } => assert_eq!(args, &[1, 2, 3, 4]),
+            instr => panic!("Wrong instruction {:?}", instr),
+        }
+        let mut data2 = vec![];
+        parsed.write(&mut data2).unwrap();
+        assert_eq!(data, data2);
+    }
+
+    #[test]
+    fn texture() {
+        let anm0 = Anm0 {
+            size: (2, 2),
+            format: Format::Gray8,
+            color_key: 0,
+            png_filename: String::new(),
+            alpha_filename: None,
+            sprites: vec![],
+            scripts: BTreeMap::new(),
+            texture: Some(Texture {
+                format: Format::Gray8,
+                width: 2,
+                height: 2,
+                data: vec![0, 64, 128, 255],
+            }),
+        };
+        let mut data = vec![];
+        anm0.write(&mut data).unwrap();
+        assert_eq!(&data[52..56], &1u32.to_le_bytes());
+        let mut anms = Anm0::from_slice(&data).unwrap();
+        let parsed = anms.remove(0);
+        let texture = parsed.texture.as_ref().unwrap();
+        assert_eq!(texture, anm0.texture.as_ref().unwrap());
+        assert_eq!(&texture.to_rgba8().unwrap()[4..8], &[64, 64, 64, 255]);
+
+        let mut data2 = vec![];
+        parsed.write(&mut data2).unwrap();
+        assert_eq!(data, data2);
+
+        let mut corrupted = data;
+        let offset = u32::from_le_bytes([corrupted[48], corrupted[49], corrupted[50], corrupted[51]]) as usize;
+        corrupted[offset] = b'X';
+        assert_eq!(Anm0::from_slice(&corrupted).unwrap_err().reason, crate::error::Reason::BadMagic);
+    }
+
+    #[test]
+    fn entries() {
+        let entry = |png_filename: &str, index| {
+            let mut scripts = BTreeMap::new();
+            scripts.insert(index, Script {
+                instructions: vec![Call { time: 0, instr: Instruction::Delete() }],
+                interrupts: BTreeMap::new(),
+            });
+            Anm0 {
+                size: (256, 256),
+                format: Format::Argb4444,
+                color_key: 0,
+                png_filename: String::from(png_filename),
+                alpha_filename: None,
+                sprites: vec![Sprite { index: 0, x: 0., y: 0., width: 32., height: 32. }],
+                scripts,
+                texture: None,
+            }
+        };
+        let anms = [entry("data/a.png", 0), entry("data/b.png", 1), entry("data/c.png", 2)];
+        let mut data = vec![];
+        Anm0::write_entries(&anms, &mut data).unwrap();
+
+        let parsed = Anm0::from_slice(&data).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[1].png_filename, "data/b.png");
+        assert!(parsed[2].scripts.contains_key(&2));
+
+        let mut data2 = vec![];
+        Anm0::write_entries(&parsed, &mut data2).unwrap();
+        assert_eq!(data, data2);
+
+        Anm0::write_entries(&[], &mut vec![]).unwrap_err();
+    }
+
+    #[test]
+    fn keep_still() {
+        let mut scripts = BTreeMap::new();
+        for index in 0..2 {
+            scripts.insert(index, Script {
+                instructions: vec![
+                    Call { time: 0, instr: Instruction::LoadSprite(index as u32) },
+                    Call { time: 0, instr: Instruction::KeepStill() },
+                ],
+                interrupts: BTreeMap::new(),
+            });
+        }
+        let anm0 = Anm0 {
+            size: (256, 256),
+            format: Format::Argb4444,
+            color_key: 0,
+            png_filename: String::new(),
+            alpha_filename: None,
+            sprites: vec![],
+            scripts,
+            texture: None,
+        };
+        let mut data = vec![];
+        anm0.write(&mut data).unwrap();
+
+        // Neither script runs into the next one, nor past the end of the file.
+        let mut anms = Anm0::from_slice(&data).unwrap();
+        let parsed = anms.remove(0);
+        for script in parsed.scripts.values() {
+            assert_eq!(script.instructions.len(), 2);
+            assert!(matches!(script.instructions[1].instr, Instruction::KeepStill()));
+        }
+        assert!(matches!(parsed.scripts[&1].instructions[0].instr, Instruction::LoadSprite(1)));
+
+        let mut data2 = vec![];
+        parsed.write(&mut data2).unwrap();
+        assert_eq!(data, data2);
     }
 }
@@ -0,0 +1,407 @@
+//! Text format for ANM0 files, compatible with the one used by thanm.
+//!
+//! A file is made of one or more entries, each looking like this:
+//!
+//! ```text
+//! ENTRY 0
+//! Name: data/player/player00.png
+//! Format: 1
+//! Width: 256
+//! Height: 256
+//!
+//! Sprite: 0 32*48+0+0
+//!
+//! Script: 0
+//! Instruction: 0 0 1 0
+//! Instruction: 0 0 15
+//! ```
+//!
+//! Instructions are written as `time param_mask opcode args…`, where each argument is a 32-bit
+//! integer (decimal or hexadecimal) or a float suffixed with `f`, and jumps use byte offsets.
+//! Unknown opcodes are kept, their arguments being written as integers.
+//! Everything following a `#` is a comment.
+
+use crate::th06::anm0::{Anm0, Call, Instruction, Script, Sprite, parse_instruction_args, write_instruction_args};
+use crate::th06::thtx::Format;
+use alloc::{borrow::ToOwned, collections::BTreeMap, format, string::String, vec, vec::Vec};
+use core::fmt::{self, Write};
+
+/// Error happening while parsing the text format.
+#[derive(Debug)]
+pub struct ParseError {
+    /// Line at which this error happened, starting from 1.
+    pub line: usize,
+
+    /// Explanation of what went wrong.
+    pub reason: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+fn parse_u32(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse::<i64>().ok().filter(|&v| v >= i32::MIN as i64 && v <= u32::MAX as i64).map(|v| v as u32),
+    }
+}
+
+fn parse_arg(value: &str) -> Option<[u8; 4]> {
+    if value.starts_with("0x") {
+        parse_u32(value).map(u32::to_le_bytes)
+    } else if value.ends_with('f') || value.contains('.') {
+        value.trim_end_matches('f').parse::<f32>().ok().map(f32::to_le_bytes)
+    } else {
+        parse_u32(value).map(u32::to_le_bytes)
+    }
+}
+
+fn parse_sprite(value: &str) -> Option<Sprite> {
+    let (index, geometry) = value.split_once(' ')?;
+    let (width, geometry) = geometry.trim().split_once('*')?;
+    let mut geometry = geometry.split('+');
+    let height = geometry.next()?.parse().ok()?;
+    let x = geometry.next()?.parse().ok()?;
+    let y = geometry.next()?.parse().ok()?;
+    if geometry.next().is_some() {
+        return None;
+    }
+    Some(Sprite {
+        index: index.parse().ok()?,
+        x,
+        y,
+        width: width.parse().ok()?,
+        height,
+    })
+}
+
+fn instruction_size(instr: &Instruction) -> usize {
+    let mut args = vec![];
+    write_instruction_args(&mut args, instr);
+    4 + args.len().next_multiple_of(4)
+}
+
+/// Resolve jump offsets into instruction indices, and find the interrupt labels.
+fn finish_script(script: &mut Script) -> Result<(), String> {
+    let mut offsets = vec![];
+    let mut offset = 0;
+    for call in script.instructions.iter() {
+        offsets.push(offset as u32);
+        offset += instruction_size(&call.instr);
+    }
+    for (index, call) in script.instructions.iter_mut().enumerate() {
+        match call.instr {
+            Instruction::Jump(ref mut offset) => {
+                *offset = match offsets.binary_search(offset) {
+                    Ok(index) => index as u32,
+                    Err(_) => return Err(format!("jump to offset {} which isn’t an instruction", offset)),
+                };
+            }
+            Instruction::InterruptLabel(label) => {
+                script.interrupts.insert(label, index + 1);
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+struct Parser {
+    anms: Vec<Anm0>,
+    script: Option<(u8, Script)>,
+}
+
+impl Parser {
+    fn current(&mut self) -> Result<&mut Anm0, String> {
+        self.anms.last_mut().ok_or_else(|| String::from("missing ENTRY line"))
+    }
+
+    fn finish_script(&mut self) -> Result<(), String> {
+        if let Some((index, mut script)) = self.script.take() {
+            finish_script(&mut script)?;
+            if self.current()?.scripts.insert(index, script).is_some() {
+                return Err(format!("script {} defined twice", index));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        if let Some(version) = line.strip_prefix("ENTRY") {
+            if version.trim() != "0" {
+                return Err(format!("unsupported version {}", version.trim()));
+            }
+            self.finish_script()?;
+            self.anms.push(Anm0 {
+                size: (0, 0),
+                format: Format::from(0),
+                color_key: 0,
+                png_filename: String::new(),
+                alpha_filename: None,
+                sprites: vec![],
+                scripts: BTreeMap::new(),
+                texture: None,
+            });
+            return Ok(());
+        }
+
+        let (key, value) = line.split_once(':').ok_or_else(|| format!("invalid line “{}”", line))?;
+        let value = value.trim();
+        let invalid = || format!("invalid value “{}” for {}", value, key);
+        match key.to_ascii_lowercase().as_str() {
+            "name" => self.current()?.png_filename = value.to_owned(),
+            "name2" => self.current()?.alpha_filename = Some(value.to_owned()),
+            "format" => self.current()?.format = Format::from(parse_u32(value).ok_or_else(invalid)?),
+            "width" => self.current()?.size.0 = parse_u32(value).ok_or_else(invalid)?,
+            "height" => self.current()?.size.1 = parse_u32(value).ok_or_else(invalid)?,
+            "colorkey" => self.current()?.color_key = parse_u32(value).ok_or_else(invalid)?,
+            "sprite" => {
+                let sprite = parse_sprite(value).ok_or_else(invalid)?;
+                self.current()?.sprites.push(sprite);
+            }
+            "script" => {
+                self.finish_script()?;
+                let index = value.parse().map_err(|_| invalid())?;
+                self.current()?;
+                self.script = Some((index, Script {
+                    instructions: vec![],
+                    interrupts: BTreeMap::new(),
+                }));
+            }
+            "instruction" => {
+                let (_, script) = self.script.as_mut().ok_or_else(|| String::from("instruction outside of a script"))?;
+                let mut values = value.split_whitespace();
+                let mut next = || values.next().ok_or_else(invalid);
+                let time = next()?.parse().map_err(|_| invalid())?;
+                let _param_mask: u16 = next()?.parse().map_err(|_| invalid())?;
+                let opcode = next()?.parse().map_err(|_| invalid())?;
+                let mut args = vec![];
+                for arg in values {
+                    args.extend_from_slice(&parse_arg(arg).ok_or_else(|| format!("invalid argument “{}”", arg))?);
+                }
+                let instr = match parse_instruction_args(&args, opcode) {
+                    Ok((_, instr)) => instr,
+                    Err(_) => return Err(format!("invalid arguments for opcode {}", opcode)),
+                };
+                if instruction_size(&instr) != 4 + args.len() {
+                    return Err(format!("too many arguments for opcode {}", opcode));
+                }
+                script.instructions.push(Call { time, instr });
+            }
+            _ => return Err(format!("unknown key “{}”", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Parse the text format into a list of `Anm0`.
+pub fn parse(text: &str) -> Result<Vec<Anm0>, ParseError> {
+    let mut parser = Parser {
+        anms: vec![],
+        script: None,
+    };
+    let mut last_line = 0;
+    for (i, line) in text.lines().enumerate() {
+        last_line = i + 1;
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        parser.parse_line(line).map_err(|reason| ParseError { line: i + 1, reason })?;
+    }
+    parser.finish_script().map_err(|reason| ParseError { line: last_line, reason })?;
+    Ok(parser.anms)
+}
+
+fn format_number(value: f32) -> String {
+    // f32::fract() isn’t available in core.
+    if value == value as i64 as f32 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn dump_instruction(out: &mut String, time: u16, instr: &Instruction, offsets: &[usize]) {
+    let instr = match *instr {
+        Instruction::Jump(index) => Instruction::Jump(offsets.get(index as usize).copied().unwrap_or(index as usize) as u32),
+        ref instr => instr.clone(),
+    };
+    let opcode = instr.opcode();
+    let mut args = vec![];
+    write_instruction_args(&mut args, &instr);
+    args.resize(args.len().next_multiple_of(4), 0);
+
+    write!(out, "Instruction: {} 0 {}", time, opcode).unwrap();
+    // Arguments of unknown instructions are written as integers.
+    let types = Instruction::arg_types(opcode).unwrap_or(&[]);
+    let mut types = types.iter().peekable();
+    for word in args.chunks(4) {
+        let word = [word[0], word[1], word[2], word[3]];
+        match types.next() {
+            Some(&"f32") => write!(out, " {:?}f", f32::from_le_bytes(word)).unwrap(),
+            Some(&"i32") => write!(out, " {}", i32::from_le_bytes(word)).unwrap(),
+            Some(&"u8") => {
+                // Bytes are packed together into a single word, like colours.
+                while types.peek() == Some(&&"u8") {
+                    types.next();
+                }
+                write!(out, " 0x{:08x}", u32::from_le_bytes(word)).unwrap();
+            }
+            _ => write!(out, " {}", u32::from_le_bytes(word)).unwrap(),
+        }
+    }
+    out.push('\n');
+}
+
+/// Write a list of `Anm0` into the text format.
+pub fn dump(anms: &[Anm0]) -> String {
+    let mut out = String::new();
+    for anm0 in anms {
+        writeln!(out, "ENTRY 0").unwrap();
+        if !anm0.png_filename.is_empty() {
+            writeln!(out, "Name: {}", anm0.png_filename).unwrap();
+        }
+        if let Some(alpha_filename) = &anm0.alpha_filename {
+            writeln!(out, "Name2: {}", alpha_filename).unwrap();
+        }
+        writeln!(out, "Format: {}", u32::from(anm0.format)).unwrap();
+        writeln!(out, "Width: {}", anm0.size.0).unwrap();
+        writeln!(out, "Height: {}", anm0.size.1).unwrap();
+        if anm0.color_key != 0 {
+            writeln!(out, "ColorKey: 0x{:08x}", anm0.color_key).unwrap();
+        }
+
+        out.push('\n');
+        for sprite in anm0.sprites.iter() {
+            writeln!(out, "Sprite: {} {}*{}+{}+{}", sprite.index, format_number(sprite.width),
+                     format_number(sprite.height), format_number(sprite.x), format_number(sprite.y)).unwrap();
+        }
+
+        for (index, script) in anm0.scripts.iter() {
+            writeln!(out, "\nScript: {}", index).unwrap();
+            let mut offsets = vec![];
+            let mut offset = 0;
+            for call in script.instructions.iter() {
+                offsets.push(offset);
+                offset += instruction_size(&call.instr);
+            }
+            for call in script.instructions.iter() {
+                dump_instruction(&mut out, call.time, &call.instr, &offsets);
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYER00: &str = include_str!("../../../data/ST/player00.script");
+
+    #[test]
+    fn sample() {
+        let anms = parse(PLAYER00).unwrap();
+        assert_eq!(anms.len(), 1);
+        let anm0 = &anms[0];
+        assert_eq!(anm0.png_filename, "data/player/player00.png");
+        assert_eq!(anm0.size, (256, 256));
+        assert_eq!(anm0.sprites.len(), 3);
+        assert_eq!(anm0.sprites[0].width, 15.);
+        assert_eq!(anm0.sprites[0].y, 1.);
+        assert_eq!(anm0.scripts.len(), 9);
+        assert!(matches!(anm0.scripts[&64].instructions[0].instr, Instruction::LoadSprite(1)));
+    }
+
+    #[test]
+    fn compile_samples() {
+        let samples = [
+            include_str!("../../../data/ST/eff01.script"),
+            include_str!("../../../data/ST/etama3.script"),
+            include_str!("../../../data/ST/etama4.script"),
+            include_str!("../../../data/ST/face00a.script"),
+            include_str!("../../../data/ST/face03a.script"),
+            PLAYER00,
+            include_str!("../../../data/ST/stg1bg.script"),
+            include_str!("../../../data/ST/stg1enm.script"),
+            include_str!("../../../data/ST/stg1enm2.script"),
+        ];
+        for sample in samples.iter() {
+            let anms = parse(sample).unwrap();
+            let mut data = vec![];
+            anms[0].write(&mut data).unwrap();
+            let parsed = Anm0::from_slice(&data).unwrap();
+            assert_eq!(parsed.len(), 1);
+            for (index, script) in anms[0].scripts.iter() {
+                assert_eq!(parsed[0].scripts[index].instructions.len(), script.instructions.len());
+            }
+            assert_eq!(dump(&parsed), dump(&anms));
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let text = "\
+ENTRY 0
+Name: data/test.png
+Format: 1
+Width: 256
+Height: 128 # Half height.
+
+Sprite: 0 16*16+0+0
+Sprite: 1 16*16+16+0.5
+
+Script: 3
+Instruction: 0 0 1 0
+Instruction: 0 0 4 0xff102030
+Instruction: 0 0 22 1
+Instruction: 10 0 18 1.5f -2.0f 0.0f 10
+Instruction: 20 0 5 16
+Instruction: 20 0 0
+";
+        let anms = parse(text).unwrap();
+        let script = &anms[0].scripts[&3];
+        assert!(matches!(script.instructions[1].instr, Instruction::SetColor(0x30, 0x20, 0x10, 0xff)));
+        assert!(matches!(script.instructions[4].instr, Instruction::Jump(2)));
+        assert_eq!(script.interrupts[&1], 3);
+
+        let dumped = dump(&anms);
+        let anms2 = parse(&dumped).unwrap();
+        // The padding byte of SetColor is kept.
+        assert!(dumped.contains("Instruction: 0 0 4 0xff102030\n"));
+        assert!(dumped.contains("Instruction: 10 0 18 1.5f -2.0f 0.0f 10\n"));
+        assert!(dumped.contains("Instruction: 20 0 5 16\n"));
+        assert_eq!(dumped, dump(&anms2));
+    }
+
+    #[test]
+    fn errors() {
+        let err = parse("Name: foo.png\n").unwrap_err();
+        assert_eq!(err.line, 1);
+        let err = parse("ENTRY 0\nScript: 0\nInstruction: 0 0 5 2\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        let err = parse("ENTRY 0\nScript: 0\nInstruction: 0 0 1 0 0\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        let err = parse("ENTRY 0\nScript: 0\n\nInstruction: 0 0 5 3\n").unwrap_err();
+        assert_eq!(err.line, 4);
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let text = "ENTRY 0\nScript: 0\nInstruction: 0 0 200 7 0x00000001\nInstruction: 1 0 0\n";
+        let anms = parse(text).unwrap();
+        match &anms[0].scripts[&0].instructions[0].instr {
+            Instruction::Unknown { opcode: 200, args } => assert_eq!(args, &[7, 0, 0, 0, 1, 0, 0, 0]),
+            instr => panic!("Wrong instruction {:?}", instr),
+        }
+        assert!(dump(&anms).contains("Instruction: 0 0 200 7 1\n"));
+    }
+}
@@ -1,15 +1,18 @@
 //! ECL enemy script format support.
 
+use crate::error::{Error, FileKind, Reason, IResult, failure, at_offset, until_terminator};
 use nom::{
-    IResult,
+    bytes::complete::take,
     number::complete::{le_u8, le_u16, le_u32, le_i16, le_i32, le_f32},
     sequence::tuple,
-    multi::{count, many0},
-    error::ErrorKind,
+    multi::count,
     Err,
 };
 use encoding_rs::SHIFT_JIS;
 use bitflags::bitflags;
+use alloc::{format, string::{String, ToString}, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::io;
 
 bitflags! {
     /// Bit flags describing the current difficulty level.
@@ -31,7 +34,7 @@ bitflags! {
     }
 }
 
-impl std::str::FromStr for Rank {
+impl core::str::FromStr for Rank {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Rank, Self::Err> {
@@ -45,8 +48,24 @@ impl std::str::FromStr for Rank {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rank {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.bits())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Rank {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Rank, D::Error> {
+        let bits = u16::deserialize(deserializer)?;
+        Rank::from_bits(bits).ok_or_else(|| serde::de::Error::custom(format!("invalid rank mask 0x{:04x}", bits)))
+    }
+}
+
 /// A single instruction, part of a `Script`.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CallSub {
     /// Time at which this instruction will be called.
     pub time: i32,
@@ -75,13 +94,17 @@ impl CallSub {
 
 /// Script driving an animation.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sub {
     /// List of instructions in this script.
+    ///
+    /// Jumps target an index in this list, the one past its end ending the sub.
     pub instructions: Vec<CallSub>,
 }
 
 /// A single instruction, part of a `Script`.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CallMain {
     /// Time at which this instruction will be called.
     pub time: u16,
@@ -95,6 +118,7 @@ pub struct CallMain {
 
 /// Script driving an animation.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Main {
     /// List of instructions in this script.
     pub instructions: Vec<CallMain>,
@@ -102,6 +126,7 @@ pub struct Main {
 
 /// Main struct of the ANM0 animation format.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ecl {
     /// A list of subs.
     pub subs: Vec<Sub>,
@@ -112,8 +137,17 @@ pub struct Ecl {
 
 impl Ecl {
     /// Parse a slice of bytes into an `Ecl` struct.
-    pub fn from_slice(data: &[u8]) -> IResult<&[u8], Ecl> {
-        parse_ecl(data)
+    pub fn from_slice(data: &[u8]) -> Result<Ecl, Error> {
+        match parse_ecl(data) {
+            Ok((_, ecl)) => Ok(ecl),
+            Err(err) => Err(Error::from_nom(FileKind::Ecl, data, err)),
+        }
+    }
+
+    /// Serialize this `Ecl` into a file.
+    #[cfg(feature = "std")]
+    pub fn write<W: io::Write>(&self, file: &mut W) -> io::Result<()> {
+        file.write_all(&write_ecl(self)?)
     }
 }
 
@@ -121,14 +155,25 @@ macro_rules! declare_main_instructions {
     ($($opcode:tt => fn $name:ident($($arg:ident: $arg_type:ident),*)),*,) => {
         /// Available instructions in an `Ecl`.
         #[allow(missing_docs)]
-        #[derive(Debug, Clone, Copy)]
+        #[derive(Debug, Clone)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum MainInstruction {
             $(
-                $name($($arg_type),*)
-            ),*
+                $name($($arg_type),*),
+            )*
+
+            /// An instruction whose opcode isn’t known, kept as is.
+            Unknown {
+                /// Its opcode.
+                opcode: u16,
+
+                /// Its raw arguments.
+                args: Vec<u8>,
+            },
         }
 
-        fn parse_main_instruction_args(input: &[u8], opcode: u16) -> IResult<&[u8], MainInstruction> {
+        /// Parse the arguments of this opcode, `size` only being used for unknown ones.
+        fn parse_main_instruction_args(input: &[u8], opcode: u16, size: usize) -> IResult<&[u8], MainInstruction> {
             let mut i = &input[..];
             let instr = match opcode {
                 $(
@@ -140,19 +185,235 @@ macro_rules! declare_main_instructions {
                         MainInstruction::$name($($arg),*)
                     }
                 )*
-                _ => unreachable!()
+                _ => {
+                    let (i2, args) = take(size)(i)?;
+                    i = i2;
+                    MainInstruction::Unknown { opcode, args: args.to_vec() }
+                }
             };
             Ok((i, instr))
         }
+
+        impl MainInstruction {
+            #[cfg(feature = "std")]
+            pub(crate) fn opcode(&self) -> u16 {
+                match self {
+                    $(
+                        MainInstruction::$name(..) => $opcode,
+                    )*
+                    MainInstruction::Unknown { opcode, .. } => *opcode,
+                }
+            }
+
+            pub(crate) fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        MainInstruction::$name(..) => stringify!($name),
+                    )*
+                    MainInstruction::Unknown { .. } => "Unknown",
+                }
+            }
+
+            #[cfg(feature = "std")]
+            pub(crate) fn write_args(&self, data: &mut Vec<u8>) -> io::Result<()> {
+                match self {
+                    $(
+                        MainInstruction::$name($($arg),*) => {
+                            $(
+                                $arg.write_le(data)?;
+                            )*
+                        }
+                    )*
+                    MainInstruction::Unknown { args, .. } => data.extend_from_slice(args),
+                }
+                Ok(())
+            }
+
+            pub(crate) fn format_args(&self) -> Vec<String> {
+                match self {
+                    $(
+                        MainInstruction::$name($($arg),*) => vec![$($arg.to_text()),*],
+                    )*
+                    MainInstruction::Unknown { opcode, args } => unknown_to_text(*opcode, args),
+                }
+            }
+
+            pub(crate) fn from_text(name: &str, args: &[&str]) -> Result<MainInstruction, String> {
+                match name {
+                    $(
+                        stringify!($name) => {
+                            let expected: &[&str] = &[$(stringify!($arg)),*];
+                            if args.len() != expected.len() {
+                                return Err(format!("{} expects {} arguments, got {}", name, expected.len(), args.len()));
+                            }
+                            // Instructions without any argument never use this iterator.
+                            #[allow(unused_mut, unused_variables)]
+                            let mut args = args.iter();
+                            $(
+                                let arg = args.next().unwrap();
+                                let $arg = <$arg_type as Arg>::from_text(arg).ok_or_else(|| format!("invalid value “{}” for {}", arg, stringify!($arg)))?;
+                            )*
+                            Ok(MainInstruction::$name($($arg),*))
+                        }
+                    )*
+                    "Unknown" => {
+                        let (opcode, args) = unknown_from_text(args)?;
+                        Ok(MainInstruction::Unknown { opcode, args })
+                    }
+                    _ => Err(format!("unknown instruction {}", name))
+                }
+            }
+        }
     };
 }
 
-/// Parse a SHIFT_JIS byte string of length 34 into a String.
+/// Raw bytes ending an instruction, such as the NUL padding following a spell card name.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Padding(pub Vec<u8>);
+
+/// Parse a SHIFT_JIS string up to its first NUL byte, or to the end of the arguments.
+#[allow(non_snake_case)]
+fn le_String(i: &[u8]) -> IResult<&[u8], String> {
+    let len = i.iter().position(|&byte| byte == 0).unwrap_or(i.len());
+    let (string, _encoding, _replaced) = SHIFT_JIS.decode(&i[..len]);
+    Ok((&i[len..], string.into_owned()))
+}
+
+/// Parse the rest of the arguments as raw bytes, so that they get written back unchanged.
 #[allow(non_snake_case)]
-pub fn le_String(i: &[u8]) -> IResult<&[u8], String> {
-    let data = i.splitn(2, |c| *c == b'\0').nth(0).unwrap();
-    let (string, _encoding, _replaced) = SHIFT_JIS.decode(data);
-    Ok((&i[34..], string.into_owned()))
+fn le_Padding(i: &[u8]) -> IResult<&[u8], Padding> {
+    Ok((&i[i.len()..], Padding(i.to_vec())))
+}
+
+fn bytes_to_hex(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("\"{}\"", hex)
+}
+
+fn bytes_from_hex(text: &str) -> Option<Vec<u8>> {
+    let hex = text.strip_prefix('"')?.strip_suffix('"')?;
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+/// Write an unknown instruction as its opcode followed by its arguments in hexadecimal.
+fn unknown_to_text(opcode: u16, args: &[u8]) -> Vec<String> {
+    vec![opcode.to_string(), bytes_to_hex(args)]
+}
+
+fn unknown_from_text(args: &[&str]) -> Result<(u16, Vec<u8>), String> {
+    let (opcode, hex) = match args {
+        [opcode, hex] => (opcode, hex),
+        _ => return Err(format!("Unknown expects 2 arguments, got {}", args.len())),
+    };
+    let opcode = opcode.parse().map_err(|_| format!("invalid opcode “{}”", opcode))?;
+    let args = bytes_from_hex(hex).ok_or_else(|| format!("invalid arguments {}", hex))?;
+    Ok((opcode, args))
+}
+
+/// Conversions of an instruction argument to and from its binary and text representations.
+pub(crate) trait Arg: Sized {
+    /// Minimum size of this argument in an instruction.
+    const MIN_SIZE: usize;
+
+    #[cfg(feature = "std")]
+    fn write_le(&self, data: &mut Vec<u8>) -> io::Result<()>;
+    fn to_text(&self) -> String;
+    fn from_text(text: &str) -> Option<Self>;
+}
+
+macro_rules! impl_arg {
+    ($($type:ty),*) => {
+        $(
+            impl Arg for $type {
+                const MIN_SIZE: usize = core::mem::size_of::<$type>();
+
+                #[cfg(feature = "std")]
+                fn write_le(&self, data: &mut Vec<u8>) -> io::Result<()> {
+                    data.extend_from_slice(&self.to_le_bytes());
+                    Ok(())
+                }
+
+                fn to_text(&self) -> String {
+                    format!("{:?}", self)
+                }
+
+                fn from_text(text: &str) -> Option<Self> {
+                    text.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_arg!(u8, i16, u16, i32, u32, f32);
+
+impl Arg for String {
+    const MIN_SIZE: usize = 0;
+
+    #[cfg(feature = "std")]
+    fn write_le(&self, data: &mut Vec<u8>) -> io::Result<()> {
+        let (string, _encoding, _replaced) = SHIFT_JIS.encode(self);
+        data.extend_from_slice(&string);
+        Ok(())
+    }
+
+    fn to_text(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn from_text(text: &str) -> Option<Self> {
+        let text = text.strip_prefix('"')?.strip_suffix('"')?;
+        let mut string = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '"' {
+                return None;
+            }
+            if c != '\\' {
+                string.push(c);
+                continue;
+            }
+            string.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                'u' => {
+                    let rest = chars.as_str().strip_prefix('{')?;
+                    let (hex, rest) = rest.split_once('}')?;
+                    chars = rest.chars();
+                    char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+                }
+                c @ ('\\' | '"' | '\'') => c,
+                _ => return None,
+            });
+        }
+        Some(string)
+    }
+}
+
+impl Arg for Padding {
+    const MIN_SIZE: usize = 0;
+
+    #[cfg(feature = "std")]
+    fn write_le(&self, data: &mut Vec<u8>) -> io::Result<()> {
+        data.extend_from_slice(&self.0);
+        Ok(())
+    }
+
+    fn to_text(&self) -> String {
+        bytes_to_hex(&self.0)
+    }
+
+    fn from_text(text: &str) -> Option<Self> {
+        bytes_from_hex(text).map(Padding)
+    }
 }
 
 macro_rules! declare_sub_instructions {
@@ -160,13 +421,24 @@ macro_rules! declare_sub_instructions {
         /// Available instructions in an `Ecl`.
         #[allow(missing_docs)]
         #[derive(Debug, Clone)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum SubInstruction {
             $(
-                $name($($arg_type),*)
-            ),*
+                $name($($arg_type),*),
+            )*
+
+            /// An instruction whose opcode isn’t known, kept as is.
+            Unknown {
+                /// Its opcode.
+                opcode: u16,
+
+                /// Its raw arguments.
+                args: Vec<u8>,
+            },
         }
 
-        fn parse_sub_instruction_args(input: &[u8], opcode: u16) -> IResult<&[u8], SubInstruction> {
+        /// Parse the arguments of this opcode, `size` only being used for unknown ones.
+        fn parse_sub_instruction_args(input: &[u8], opcode: u16, size: usize) -> IResult<&[u8], SubInstruction> {
             let mut i = &input[..];
             let instr = match opcode {
                 $(
@@ -178,10 +450,95 @@ macro_rules! declare_sub_instructions {
                         SubInstruction::$name($($arg),*)
                     }
                 )*
-                _ => unreachable!()
+                _ => {
+                    let (i2, args) = take(size)(i)?;
+                    i = i2;
+                    SubInstruction::Unknown { opcode, args: args.to_vec() }
+                }
             };
             Ok((i, instr))
         }
+
+        impl SubInstruction {
+            /// Minimum size of the arguments of this opcode, if it is known.
+            fn min_args_size(opcode: u16) -> Option<usize> {
+                match opcode {
+                    $(
+                        $opcode => Some(0 $(+ <$arg_type as Arg>::MIN_SIZE)*),
+                    )*
+                    _ => None,
+                }
+            }
+
+            #[cfg(feature = "std")]
+            pub(crate) fn opcode(&self) -> u16 {
+                match self {
+                    $(
+                        SubInstruction::$name(..) => $opcode,
+                    )*
+                    SubInstruction::Unknown { opcode, .. } => *opcode,
+                }
+            }
+
+            pub(crate) fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        SubInstruction::$name(..) => stringify!($name),
+                    )*
+                    SubInstruction::Unknown { .. } => "Unknown",
+                }
+            }
+
+            #[cfg(feature = "std")]
+            pub(crate) fn write_args(&self, data: &mut Vec<u8>) -> io::Result<()> {
+                match self {
+                    $(
+                        SubInstruction::$name($($arg),*) => {
+                            $(
+                                $arg.write_le(data)?;
+                            )*
+                        }
+                    )*
+                    SubInstruction::Unknown { args, .. } => data.extend_from_slice(args),
+                }
+                Ok(())
+            }
+
+            pub(crate) fn format_args(&self) -> Vec<String> {
+                match self {
+                    $(
+                        SubInstruction::$name($($arg),*) => vec![$($arg.to_text()),*],
+                    )*
+                    SubInstruction::Unknown { opcode, args } => unknown_to_text(*opcode, args),
+                }
+            }
+
+            pub(crate) fn from_text(name: &str, args: &[&str]) -> Result<SubInstruction, String> {
+                match name {
+                    $(
+                        stringify!($name) => {
+                            let expected: &[&str] = &[$(stringify!($arg)),*];
+                            if args.len() != expected.len() {
+                                return Err(format!("{} expects {} arguments, got {}", name, expected.len(), args.len()));
+                            }
+                            // Instructions without any argument never use this iterator.
+                            #[allow(unused_mut, unused_variables)]
+                            let mut args = args.iter();
+                            $(
+                                let arg = args.next().unwrap();
+                                let $arg = <$arg_type as Arg>::from_text(arg).ok_or_else(|| format!("invalid value “{}” for {}", arg, stringify!($arg)))?;
+                            )*
+                            Ok(SubInstruction::$name($($arg),*))
+                        }
+                    )*
+                    "Unknown" => {
+                        let (opcode, args) = unknown_from_text(args)?;
+                        Ok(SubInstruction::Unknown { opcode, args })
+                    }
+                    _ => Err(format!("unknown instruction {}", name))
+                }
+            }
+        }
     };
 }
 
@@ -279,7 +636,7 @@ declare_sub_instructions!{
     90 => fn RepositionLaser(id: u32, ox: f32, oy: f32, oz: f32),
     91 => fn LaserSetCompare(id: u32),
     92 => fn CancelLaser(id: u32),
-    93 => fn SetSpellcard(face: i16, number: i16, name: String),
+    93 => fn SetSpellcard(face: i16, number: i16, name: String, padding: Padding),
     94 => fn EndSpellcard(),
     95 => fn SpawnEnemy(sub: i32, x: f32, y: f32, z: f32, life: i16, bonus_dropped: i16, die_score: i32),
     96 => fn KillAllEnemies(),
@@ -327,44 +684,113 @@ declare_sub_instructions!{
     135 => fn EnableSpellcardBonus(UNK1: i32),
 }
 
-fn parse_sub_instruction(input: &[u8]) -> IResult<&[u8], CallSub> {
+impl SubInstruction {
+    /// Return the instruction this one may jump to, as an index in its `Sub`.
+    pub(crate) fn jump_target_mut(&mut self) -> Option<&mut i32> {
+        match self {
+            SubInstruction::RelativeJump(_, ip)
+            | SubInstruction::RelativeJumpEx(_, ip, _)
+            | SubInstruction::RelativeJumpIfLowerThan(_, ip)
+            | SubInstruction::RelativeJumpIfLowerOrEqual(_, ip)
+            | SubInstruction::RelativeJumpIfEqual(_, ip)
+            | SubInstruction::RelativeJumpIfGreaterThan(_, ip)
+            | SubInstruction::RelativeJumpIfGreaterOrEqual(_, ip)
+            | SubInstruction::RelativeJumpIfNotEqual(_, ip) => Some(ip),
+            _ => None,
+        }
+    }
+}
+
+fn parse_sub_instruction(input: &[u8]) -> IResult<&[u8], Option<CallSub>> {
     let i = &input[..];
-    let (i, (time, opcode)) = tuple((le_i32, le_u16))(i)?;
+    let (i, (time, opcode, size, rank_mask, param_mask)) = tuple((le_i32, le_u16, le_u16, le_u16, le_u16))(i)?;
     if time == -1 || opcode == 0xffff {
-        return Err(Err::Error(nom::error::Error::new(i, ErrorKind::Eof)));
+        return Ok((i, None));
     }
 
-    let (i, (size, rank_mask, param_mask)) = tuple((le_u16, le_u16, le_u16))(i)?;
-    let rank_mask = Rank::from_bits(rank_mask).unwrap();
-    let (i, instr) = parse_sub_instruction_args(i, opcode)?;
-    assert_eq!(input.len() - i.len(), size as usize);
+    let rank_mask = match Rank::from_bits(rank_mask) {
+        Some(rank_mask) => rank_mask,
+        None => return failure(input, Reason::InvalidValue),
+    };
+    let args_size = match (size as usize).checked_sub(12) {
+        Some(args_size) => args_size,
+        None => return failure(input, Reason::SizeMismatch { expected: size as usize, found: 12 }),
+    };
+    // Arguments are only parsed from the size of the instruction, as a string takes whatever is
+    // left of it.
+    let min_size = 12 + SubInstruction::min_args_size(opcode).unwrap_or(args_size);
+    if (size as usize) < min_size {
+        return failure(input, Reason::SizeMismatch { expected: size as usize, found: min_size });
+    }
+    let (i, args) = take(args_size)(i)?;
+    let (rest, instr) = parse_sub_instruction_args(args, opcode, args_size)?;
+    if !rest.is_empty() {
+        return failure(input, Reason::SizeMismatch { expected: size as usize, found: size as usize - rest.len() });
+    }
     let call = CallSub { time, rank_mask, param_mask, instr };
-    Ok((i, call))
+    Ok((i, Some(call)))
 }
 
-fn parse_sub(i: &[u8]) -> IResult<&[u8], Sub> {
-    let (i, instructions) = many0(parse_sub_instruction)(i)?;
+fn parse_sub(input: &[u8]) -> IResult<&[u8], Sub> {
+    let mut i = input;
+    let mut instructions = vec![];
+    let mut instruction_offsets = vec![];
+    loop {
+        // The terminator’s offset is kept too, as jumping to it ends the sub.
+        instruction_offsets.push(input.len() - i.len());
+        match parse_sub_instruction(i) {
+            Ok((i2, Some(call))) => {
+                instructions.push(call);
+                i = i2;
+            }
+            Ok((i2, None)) => {
+                i = i2;
+                break;
+            }
+            Err(Err::Error(err)) => return Err(Err::Failure(err)),
+            Err(err) => return Err(err),
+        }
+    }
+
+    // Jumps are relative to the current instruction and byte-based, convert them into indices.
+    for (call, &offset) in instructions.iter_mut().zip(instruction_offsets.iter()) {
+        if let Some(ip) = call.instr.jump_target_mut() {
+            let target = (offset as i64 + *ip as i64) as usize;
+            match instruction_offsets.binary_search(&target) {
+                Ok(index) => *ip = index as i32,
+                Err(_) => return failure(&input[offset..], Reason::BadOffset(*ip as u32)),
+            }
+        }
+    }
+
     let sub = Sub { instructions };
     Ok((i, sub))
 }
 
-fn parse_main_instruction(input: &[u8]) -> IResult<&[u8], CallMain> {
+fn parse_main_instruction(input: &[u8]) -> IResult<&[u8], Option<CallMain>> {
     let i = &input[..];
     let (i, (time, sub)) = tuple((le_u16, le_u16))(i)?;
     if time == 0xffff && sub == 4 {
-        return Err(Err::Error(nom::error::Error::new(i, ErrorKind::Eof)));
+        return Ok((i, None));
     }
 
     let (i, (opcode, size)) = tuple((le_u16, le_u16))(i)?;
     let size = size as usize;
-    let (i, instr) = parse_main_instruction_args(i, opcode)?;
-    assert_eq!(input.len() - i.len(), size as usize);
+    let args_size = match size.checked_sub(8) {
+        Some(args_size) => args_size,
+        None => return failure(input, Reason::SizeMismatch { expected: size, found: 8 }),
+    };
+    let (i, instr) = parse_main_instruction_args(i, opcode, args_size)?;
+    let found = input.len() - i.len();
+    if found != size {
+        return failure(input, Reason::SizeMismatch { expected: size, found });
+    }
     let call = CallMain { time, sub, instr };
-    Ok((i, call))
+    Ok((i, Some(call)))
 }
 
 fn parse_main(i: &[u8]) -> IResult<&[u8], Main> {
-    let (i, instructions) = many0(parse_main_instruction)(i)?;
+    let (i, instructions) = until_terminator(parse_main_instruction)(i)?;
     let main = Main { instructions };
     Ok((i, main))
 }
@@ -376,8 +802,7 @@ fn parse_ecl(input: &[u8]) -> IResult<&[u8], Ecl> {
     let sub_count = sub_count as usize;
 
     if main_count != 0 {
-        // TODO: use a better error.
-        return Err(Err::Error(nom::error::Error::new(i, ErrorKind::Eof)));
+        return failure(input, Reason::BadMagic);
     }
 
     let (_, (main_offsets, sub_offsets)) = tuple((
@@ -387,18 +812,18 @@ fn parse_ecl(input: &[u8]) -> IResult<&[u8], Ecl> {
 
     // Read all subs.
     let mut subs = Vec::new();
-    for offset in sub_offsets.into_iter().map(|offset| offset as usize) {
-        let (_, sub) = parse_sub(&input[offset..])?;
+    for offset in sub_offsets {
+        let (_, sub) = parse_sub(at_offset(input, offset)?)?;
         subs.push(sub);
     }
 
     // Read all mains (always a single one atm).
     let mut mains = Vec::new();
-    for offset in main_offsets.into_iter().map(|offset| offset as usize) {
+    for offset in main_offsets {
         if offset == 0 {
             break;
         }
-        let (_, main) = parse_main(&input[offset..])?;
+        let (_, main) = parse_main(at_offset(input, offset)?)?;
         mains.push(main);
     }
 
@@ -409,6 +834,80 @@ fn parse_ecl(input: &[u8]) -> IResult<&[u8], Ecl> {
     Ok((b"", ecl))
 }
 
+#[cfg(feature = "std")]
+fn write_sub(data: &mut Vec<u8>, sub: &Sub) -> io::Result<()> {
+    let mut args = vec![];
+    let mut instruction_offsets = vec![];
+    let mut offset = 0;
+    for call in sub.instructions.iter() {
+        instruction_offsets.push(offset as i32);
+        args.clear();
+        call.instr.write_args(&mut args)?;
+        offset += 12 + args.len();
+    }
+    instruction_offsets.push(offset as i32);
+
+    for (CallSub { time, rank_mask, param_mask, instr }, &offset) in sub.instructions.iter().zip(instruction_offsets.iter()) {
+        let mut instr = instr.clone();
+        if let Some(ip) = instr.jump_target_mut() {
+            match instruction_offsets.get(*ip as usize) {
+                Some(&target) if *ip >= 0 => *ip = target - offset,
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Jump to nonexistent instruction {}", ip))),
+            }
+        }
+        args.clear();
+        instr.write_args(&mut args)?;
+        data.extend_from_slice(&time.to_le_bytes());
+        data.extend_from_slice(&instr.opcode().to_le_bytes());
+        data.extend_from_slice(&(12 + args.len() as u16).to_le_bytes());
+        data.extend_from_slice(&rank_mask.bits().to_le_bytes());
+        data.extend_from_slice(&param_mask.to_le_bytes());
+        data.extend_from_slice(&args);
+    }
+    data.extend_from_slice(b"\xff\xff\xff\xff\xff\xff\x0c\x00\x00\xff\xff\x00");
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn write_ecl(ecl: &Ecl) -> io::Result<Vec<u8>> {
+    if ecl.mains.len() > 3 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "At most three mains are supported"));
+    }
+    let header_size = 4 + 4 * 3 + 4 * ecl.subs.len();
+    let mut data = vec![0u8; header_size];
+    let mut args = vec![];
+
+    let mut sub_offsets = vec![];
+    for sub in ecl.subs.iter() {
+        sub_offsets.push(data.len() as u32);
+        write_sub(&mut data, sub)?;
+    }
+
+    let mut main_offsets = [0u32; 3];
+    for (offset, main) in main_offsets.iter_mut().zip(ecl.mains.iter()) {
+        *offset = data.len() as u32;
+        for CallMain { time, sub, instr } in main.instructions.iter() {
+            args.clear();
+            instr.write_args(&mut args)?;
+            data.extend_from_slice(&time.to_le_bytes());
+            data.extend_from_slice(&sub.to_le_bytes());
+            data.extend_from_slice(&instr.opcode().to_le_bytes());
+            data.extend_from_slice(&(8 + args.len() as u16).to_le_bytes());
+            data.extend_from_slice(&args);
+        }
+        data.extend_from_slice(b"\xff\xff\x04\x00");
+    }
+
+    let mut header = Vec::with_capacity(header_size);
+    header.extend_from_slice(&(ecl.subs.len() as u16).to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    for offset in main_offsets.iter().chain(sub_offsets.iter()) {
+        header.extend_from_slice(&offset.to_le_bytes());
+    }
+    data[..header_size].copy_from_slice(&header);
+    Ok(data)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -421,8 +920,178 @@ mod tests {
         let mut file = io::BufReader::new(file);
         let mut buf = vec![];
         file.read_to_end(&mut buf).unwrap();
-        let (_, ecl) = Ecl::from_slice(&buf).unwrap();
+        let ecl = Ecl::from_slice(&buf).unwrap();
         assert_eq!(ecl.subs.len(), 24);
         assert_eq!(ecl.mains.len(), 1);
+
+        let mut data = vec![];
+        ecl.write(&mut data).unwrap();
+        assert_eq!(data, buf);
     }
+
+    #[test]
+    fn roundtrip() {
+        let ecl = Ecl {
+            subs: vec![Sub { instructions: vec![
+                CallSub::new(0, Rank::ALL, SubInstruction::SetInt(-10001, 5)),
+                CallSub { time: 10, rank_mask: Rank::EASY | Rank::NORMAL, param_mask: 2, instr: SubInstruction::SetSpellcard(0, 1, String::from("月符「ムーンライトレイ」"), Padding(vec![0; 8])) },
+                CallSub::new(20, Rank::ALL, SubInstruction::DropParticles(1, 2, 255, 128, 0, 255)),
+                CallSub::new(30, Rank::ALL, SubInstruction::RelativeJump(0, 1)),
+            ]}],
+            mains: vec![Main { instructions: vec![
+                CallMain { time: 60, sub: 0, instr: MainInstruction::SpawnEnemy(192., 64., 0., 100, -1, 1000) },
+                CallMain { time: 120, sub: 0, instr: MainInstruction::CallMessage() },
+            ]}],
+        };
+
+        let mut data = vec![];
+        ecl.write(&mut data).unwrap();
+        let parsed = Ecl::from_slice(&data).unwrap();
+        assert_eq!(parsed.subs[0].instructions.len(), 4);
+        assert_eq!(parsed.subs[0].instructions[1].param_mask, 2);
+        match &parsed.subs[0].instructions[1].instr {
+            SubInstruction::SetSpellcard(0, 1, name, padding) => {
+                assert_eq!(name, "月符「ムーンライトレイ」");
+                assert_eq!(padding.0, [0; 8]);
+            }
+            instr => panic!("Wrong instruction {:?}", instr),
+        }
+        assert_eq!(parsed.mains[0].instructions.len(), 2);
+
+        let mut data2 = vec![];
+        parsed.write(&mut data2).unwrap();
+        assert_eq!(data, data2);
+    }
+
+    #[test]
+    fn errors() {
+        use crate::error::{FileKind, Reason};
+
+        let ecl = Ecl {
+            subs: vec![Sub { instructions: vec![CallSub::new(0, Rank::ALL, SubInstruction::SetInt(-10001, 5))] }],
+            mains: vec![],
+        };
+        let mut data = vec![];
+        ecl.write(&mut data).unwrap();
+
+        // Truncated files must be rejected, never parsed as a shorter file nor make the parser panic.
+        for len in 0..data.len() {
+            assert!(Ecl::from_slice(&data[..len]).is_err(), "prefix of {} bytes was accepted", len);
+        }
+
+        // Unknown opcodes are kept as is.
+        let mut patched = data.clone();
+        patched[24..26].copy_from_slice(&0xfff0u16.to_le_bytes());
+        let ecl = Ecl::from_slice(&patched).unwrap();
+        match &ecl.subs[0].instructions[0].instr {
+            SubInstruction::Unknown { opcode: 0xfff0, args } => assert_eq!(args.len(), 8),
+            instr => panic!("Wrong instruction {:?}", instr),
+        }
+        let mut data2 = vec![];
+        ecl.write(&mut data2).unwrap();
+        assert_eq!(data2, patched);
+
+        let mut corrupted = data.clone();
+        corrupted[26..28].copy_from_slice(&14u16.to_le_bytes());
+        let err = Ecl::from_slice(&corrupted).unwrap_err();
+        assert_eq!(err.kind, FileKind::Ecl);
+        assert_eq!(err.reason, Reason::SizeMismatch { expected: 14, found: 20 });
+        assert_eq!(err.offset, 20);
+
+        let mut corrupted = data.clone();
+        corrupted[28..30].copy_from_slice(&0x0001u16.to_le_bytes());
+        assert_eq!(Ecl::from_slice(&corrupted).unwrap_err().reason, Reason::InvalidValue);
+
+        let mut corrupted = data;
+        corrupted[16..20].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert_eq!(Ecl::from_slice(&corrupted).unwrap_err().reason, Reason::BadOffset(0x1000));
+    }
+
+    #[test]
+    fn spellcard_name() {
+        // Names stop at their first NUL byte, whatever follows it being kept as is.
+        let (encoded, _, _) = SHIFT_JIS.encode("月符「ムーンライトレイ」");
+        for (name, padding) in [(&b"THE END ~"[..], &[][..]), (&encoded[..], &b"\0\0\x12\0\xff\0\0\0"[..])].iter() {
+            let mut args = vec![2, 0, 0, 0];
+            args.extend_from_slice(name);
+            args.extend_from_slice(padding);
+            let ecl = Ecl {
+                subs: vec![Sub { instructions: vec![CallSub::new(0, Rank::ALL, SubInstruction::Unknown { opcode: 93, args })] }],
+                mains: vec![],
+            };
+            let mut data = vec![];
+            ecl.write(&mut data).unwrap();
+            assert_eq!(u16::from_le_bytes([data[26], data[27]]) as usize, 16 + name.len() + padding.len());
+
+            let parsed = Ecl::from_slice(&data).unwrap();
+            match &parsed.subs[0].instructions[0].instr {
+                SubInstruction::SetSpellcard(2, 0, parsed_name, parsed_padding) => {
+                    assert_eq!(parsed_name.as_bytes(), &SHIFT_JIS.decode(name).0.as_bytes()[..]);
+                    assert_eq!(&parsed_padding.0[..], *padding);
+                }
+                instr => panic!("Wrong instruction {:?}", instr),
+            }
+            let mut data2 = vec![];
+            parsed.write(&mut data2).unwrap();
+            assert_eq!(data, data2);
+        }
+    }
+
+    #[test]
+    fn jumps() {
+        use crate::error::Reason;
+
+        let mut ecl = Ecl {
+            subs: vec![Sub { instructions: vec![
+                CallSub::new(0, Rank::ALL, SubInstruction::SetInt(-10001, 5)),
+                CallSub::new(0, Rank::ALL, SubInstruction::Noop()),
+                CallSub::new(10, Rank::ALL, SubInstruction::RelativeJump(0, 0)),
+                CallSub::new(10, Rank::ALL, SubInstruction::RelativeJumpIfEqual(0, 4)),
+            ]}],
+            mains: vec![],
+        };
+        let mut data = vec![];
+        ecl.write(&mut data).unwrap();
+        // Instructions start at 20, 40, 52 and 72, and the terminator at 92.
+        assert_eq!(&data[68..72], &(-32i32).to_le_bytes());
+        assert_eq!(&data[88..92], &20i32.to_le_bytes());
+
+        let parsed = Ecl::from_slice(&data).unwrap();
+        assert!(matches!(parsed.subs[0].instructions[2].instr, SubInstruction::RelativeJump(0, 0)));
+        assert!(matches!(parsed.subs[0].instructions[3].instr, SubInstruction::RelativeJumpIfEqual(0, 4)));
+
+        let mut corrupted = data;
+        corrupted[68..72].copy_from_slice(&(-4i32).to_le_bytes());
+        let err = Ecl::from_slice(&corrupted).unwrap_err();
+        assert_eq!(err.reason, Reason::BadOffset(-4i32 as u32));
+        assert_eq!(err.offset, 52);
+
+        ecl.subs[0].instructions[2].instr = SubInstruction::RelativeJump(0, 5);
+        ecl.write(&mut vec![]).unwrap_err();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        let ecl = Ecl {
+            subs: vec![Sub { instructions: vec![
+                CallSub::new(0, Rank::EASY | Rank::HARD, SubInstruction::SetInt(-10001, 5)),
+                CallSub::new(10, Rank::ALL, SubInstruction::RelativeJump(0, 0)),
+            ]}],
+            mains: vec![],
+        };
+        let json = serde_json::to_string(&ecl).unwrap();
+        let parsed: Ecl = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.subs[0].instructions[0].rank_mask, Rank::EASY | Rank::HARD);
+
+        let mut data = vec![];
+        ecl.write(&mut data).unwrap();
+        let mut data2 = vec![];
+        parsed.write(&mut data2).unwrap();
+        assert_eq!(data, data2);
+
+        let json = json.replacen(&(Rank::EASY | Rank::HARD).bits().to_string(), "1", 1);
+        assert!(serde_json::from_str::<Ecl>(&json).is_err());
+    }
+
 }
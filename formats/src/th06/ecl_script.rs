@@ -0,0 +1,299 @@
+//! Text format for ECL files, as emitted by dump_ecl.
+//!
+//! Subs and mains are written as blocks, each instruction on its own line:
+//!
+//! ```text
+//! Sub 0 {
+//!         0: ENHL: SetInt(-10001, 5)
+//!        60: EN--/2: SetSpellcard(0, 1, "月符「ムーンライトレイ」", "00000000")
+//! }
+//!
+//! Main 0 {
+//!        60: sub  0: SpawnEnemy(192.0, 64.0, 0.0, 100, -1, 1000)
+//! }
+//! ```
+//!
+//! The rank is written as one letter per difficulty, or as an hexadecimal mask when it has
+//! unusual bits set, and is followed by the param mask when it isn’t zero.  Jumps target the
+//! index of an instruction in their sub.  Instructions with an unknown opcode are written as
+//! `Unknown(opcode, "hex")`, their raw arguments being in hexadecimal, like the padding of
+//! spell card names.  Everything following a
+//! `#` outside of a string is a comment.
+
+use crate::th06::ecl::{Ecl, Sub, Main, CallSub, CallMain, Rank, SubInstruction, MainInstruction};
+pub use crate::th06::anm0_script::ParseError;
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt::Write;
+
+/// Bits set on every rank mask written using letters.
+const RANK_PREFIX: u16 = 0xf000;
+
+fn format_rank(rank: Rank) -> String {
+    let bits = rank.bits();
+    if bits & RANK_PREFIX != RANK_PREFIX {
+        return format!("0x{:04x}", bits);
+    }
+    [(Rank::EASY, 'E'), (Rank::NORMAL, 'N'), (Rank::HARD, 'H'), (Rank::LUNATIC, 'L')]
+        .iter()
+        .map(|&(flag, letter)| if rank.contains(flag) { letter } else { '-' })
+        .collect()
+}
+
+fn parse_rank(text: &str) -> Result<Rank, String> {
+    let invalid = || format!("invalid rank “{}”", text);
+    let bits = if let Some(hex) = text.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16).map_err(|_| invalid())?
+    } else {
+        let letters: Vec<char> = text.chars().collect();
+        if letters.len() != 4 {
+            return Err(invalid());
+        }
+        let mut bits = RANK_PREFIX;
+        for (i, (&letter, expected)) in letters.iter().zip("ENHL".chars()).enumerate() {
+            if letter == expected {
+                bits |= 0x100 << i;
+            } else if letter != '-' {
+                return Err(invalid());
+            }
+        }
+        bits
+    };
+    Rank::from_bits(bits).ok_or_else(invalid)
+}
+
+fn format_instruction(name: &str, args: Vec<String>) -> String {
+    format!("{}({})", name, args.join(", "))
+}
+
+/// Remove any comment from this line.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+/// Split `Name(arg, arg)` into its name and arguments.
+fn split_instruction(text: &str) -> Result<(&str, Vec<&str>), String> {
+    let invalid = || format!("invalid instruction “{}”", text);
+    let (name, args) = text.split_once('(').ok_or_else(invalid)?;
+    let args = args.strip_suffix(')').ok_or_else(invalid)?.trim();
+    let mut list = vec![];
+    if !args.is_empty() {
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut start = 0;
+        for (i, c) in args.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                ',' if !in_string => {
+                    list.push(args[start..i].trim());
+                    start = i + 1;
+                }
+                _ => (),
+            }
+        }
+        list.push(args[start..].trim());
+    }
+    Ok((name.trim(), list))
+}
+
+enum Block {
+    None,
+    Sub,
+    Main,
+}
+
+fn parse_block_header(line: &str, keyword: &str, expected: usize) -> Option<Result<(), String>> {
+    let rest = line.strip_prefix(keyword)?;
+    let number = rest.strip_suffix('{')?.trim();
+    Some(match number.parse::<usize>() {
+        Ok(number) if number == expected => Ok(()),
+        _ => Err(format!("expected {} {}, got “{}”", keyword, expected, number)),
+    })
+}
+
+fn parse_line(ecl: &mut Ecl, block: &mut Block, line: &str) -> Result<(), String> {
+    match block {
+        Block::None => {
+            if let Some(result) = parse_block_header(line, "Sub", ecl.subs.len()) {
+                result?;
+                ecl.subs.push(Sub { instructions: vec![] });
+                *block = Block::Sub;
+            } else if let Some(result) = parse_block_header(line, "Main", ecl.mains.len()) {
+                result?;
+                ecl.mains.push(Main { instructions: vec![] });
+                *block = Block::Main;
+            } else {
+                return Err(format!("expected a Sub or Main block, got “{}”", line));
+            }
+            return Ok(());
+        }
+        _ if line == "}" => {
+            *block = Block::None;
+            return Ok(());
+        }
+        _ => (),
+    }
+
+    let mut fields = line.splitn(3, ':');
+    let (time, second, instr) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(time), Some(second), Some(instr)) => (time.trim(), second.trim(), instr.trim()),
+        _ => return Err(format!("invalid line “{}”", line)),
+    };
+    let (name, args) = split_instruction(instr)?;
+
+    match block {
+        Block::Sub => {
+            let time = time.parse().map_err(|_| format!("invalid time “{}”", time))?;
+            let (rank, param_mask) = match second.split_once('/') {
+                Some((rank, param_mask)) => (rank, param_mask.parse().map_err(|_| format!("invalid param mask “{}”", param_mask))?),
+                None => (second, 0),
+            };
+            let rank_mask = parse_rank(rank)?;
+            let instr = SubInstruction::from_text(name, &args)?;
+            ecl.subs.last_mut().unwrap().instructions.push(CallSub { time, rank_mask, param_mask, instr });
+        }
+        Block::Main => {
+            let time = time.parse().map_err(|_| format!("invalid time “{}”", time))?;
+            let sub = second.strip_prefix("sub").and_then(|sub| sub.trim().parse().ok())
+                .ok_or_else(|| format!("invalid sub “{}”", second))?;
+            let instr = MainInstruction::from_text(name, &args)?;
+            ecl.mains.last_mut().unwrap().instructions.push(CallMain { time, sub, instr });
+        }
+        Block::None => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Parse the text format into an `Ecl`.
+pub fn parse(text: &str) -> Result<Ecl, ParseError> {
+    let mut ecl = Ecl {
+        subs: vec![],
+        mains: vec![],
+    };
+    let mut block = Block::None;
+    let mut last_line = 0;
+    for (i, line) in text.lines().enumerate() {
+        last_line = i + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        parse_line(&mut ecl, &mut block, line).map_err(|reason| ParseError { line: i + 1, reason })?;
+    }
+    if let Block::None = block {
+        Ok(ecl)
+    } else {
+        Err(ParseError { line: last_line, reason: String::from("unterminated block") })
+    }
+}
+
+/// Write an `Ecl` into the text format.
+pub fn dump(ecl: &Ecl) -> String {
+    let mut out = String::new();
+    for (i, main) in ecl.mains.iter().enumerate() {
+        writeln!(out, "Main {} {{", i).unwrap();
+        for CallMain { time, sub, instr } in main.instructions.iter() {
+            let instr = format_instruction(instr.name(), instr.format_args());
+            writeln!(out, "    {:>5}: sub {:>2}: {}", time, sub, instr).unwrap();
+        }
+        writeln!(out, "}}\n").unwrap();
+    }
+
+    for (i, sub) in ecl.subs.iter().enumerate() {
+        writeln!(out, "Sub {} {{", i).unwrap();
+        for CallSub { time, rank_mask, param_mask, instr } in sub.instructions.iter() {
+            let mut rank = format_rank(*rank_mask);
+            if *param_mask != 0 {
+                write!(rank, "/{}", param_mask).unwrap();
+            }
+            let instr = format_instruction(instr.name(), instr.format_args());
+            writeln!(out, "    {:>5}: {}: {}", time, rank, instr).unwrap();
+        }
+        writeln!(out, "}}\n").unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+Main 0 {
+       60: sub  1: SpawnEnemy(192.0, 64.0, 0.0, 100, -1, 1000)
+      120: sub  0: CallMessage()
+}
+
+Sub 0 {
+        0: ENHL: SetInt(-10001, 5)
+       10: EN--/2: SetSpellcard(0, 1, "月符 # not a comment", "00ff0000")
+       20: 0x0f00: DropParticles(1, 2, 255, 128, 0, 255) # A comment.
+       30: ENHL: RelativeJump(0, 1)
+       40: ENHL: Unknown(200, "0a000000ff")
+}
+
+Sub 1 {
+        0: --HL: SetFloat(-10005, 0.1)
+}
+"#;
+
+    #[test]
+    fn roundtrip() {
+        let ecl = parse(SAMPLE).unwrap();
+        assert_eq!(ecl.mains[0].instructions.len(), 2);
+        assert_eq!(ecl.subs.len(), 2);
+        let call = &ecl.subs[0].instructions[1];
+        assert_eq!(call.rank_mask, Rank::from_bits(0xf300).unwrap());
+        assert_eq!(call.param_mask, 2);
+        match &call.instr {
+            SubInstruction::SetSpellcard(0, 1, name, padding) => {
+                assert_eq!(name, "月符 # not a comment");
+                assert_eq!(padding.0, [0, 255, 0, 0]);
+            }
+            instr => panic!("Wrong instruction {:?}", instr),
+        }
+        assert_eq!(ecl.subs[0].instructions[2].rank_mask.bits(), 0x0f00);
+        match &ecl.subs[0].instructions[4].instr {
+            SubInstruction::Unknown { opcode: 200, args } => assert_eq!(args, &[10, 0, 0, 0, 255]),
+            instr => panic!("Wrong instruction {:?}", instr),
+        }
+
+        let dumped = dump(&ecl);
+        assert!(dumped.contains("       20: 0x0f00: DropParticles(1, 2, 255, 128, 0, 255)\n"));
+        assert!(dumped.contains("        0: --HL: SetFloat(-10005, 0.1)\n"));
+        let ecl2 = parse(&dumped).unwrap();
+
+        let mut data = vec![];
+        ecl.write(&mut data).unwrap();
+        let mut data2 = vec![];
+        ecl2.write(&mut data2).unwrap();
+        assert_eq!(data, data2);
+        assert_eq!(dumped, dump(&ecl2));
+    }
+
+    #[test]
+    fn errors() {
+        let err = parse("Sub 1 {\n}\n").unwrap_err();
+        assert_eq!(err.line, 1);
+        let err = parse("Sub 0 {\n    0: ENHL: SetInt(1)\n}\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        let err = parse("Sub 0 {\n    0: EHNL: Noop()\n}\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        let err = parse("Sub 0 {\n    0: ENHL: Unknown(200, \"0a0\")\n}\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        let err = parse("Sub 0 {\n    0: ENHL: Noop()\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
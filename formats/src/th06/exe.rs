@@ -0,0 +1,233 @@
+//! Character and shot definitions embedded in the game’s executable.
+//!
+//! No offset is hard-coded here, instead the data section is searched for a
+//! structure looking like four character definitions, which makes it work on
+//! any build of the game without embedding any of its data.
+
+use crate::th06::sht::{Sht, Shot};
+use touhou_utils::pe::PEFile;
+use std::collections::{BTreeMap, btree_map};
+use std::io;
+
+const SQ2: f32 = std::f32::consts::SQRT_2 / 2.;
+
+/// Number of power levels of each shot type.
+const LEVEL_COUNT: usize = 9;
+
+/// Size of a shot definition in the executable.
+const SHOT_SIZE: u32 = 36;
+
+/// Opcode of the x86 “push imm32” instruction.
+const PUSH: u8 = 0x68;
+
+#[derive(Clone, Copy)]
+struct Area {
+    va: u32,
+    size: u32,
+}
+
+impl Area {
+    fn contains(&self, va: u32, margin: u32) -> bool {
+        // Mimics Python’s signed comparison, an underflowing margin makes the area empty.
+        match self.size.checked_sub(margin) {
+            Some(size) => va.wrapping_sub(self.va) < size,
+            None => false,
+        }
+    }
+}
+
+fn invalid_exe() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "No character definitions found in this executable")
+}
+
+fn read_u32s<R: io::Read + io::Seek, const N: usize>(pe_file: &mut PEFile<R>, va: u32) -> io::Result<[u32; N]> {
+    let mut buf = [0u8; 4];
+    let mut values = [0u32; N];
+    for (i, value) in values.iter_mut().enumerate() {
+        pe_file.read_at_va(va + 4 * i as u32, &mut buf)?;
+        *value = u32::from_le_bytes(buf);
+    }
+    Ok(values)
+}
+
+fn read_character<R: io::Read + io::Seek>(pe_file: &mut PEFile<R>, va: u32) -> io::Result<([f32; 4], [u32; 2])> {
+    let [speed1, speed2, speed3, speed4, ptr1, ptr2] = read_u32s::<_, 6>(pe_file, va)?;
+    Ok(([f32::from_bits(speed1), f32::from_bits(speed2), f32::from_bits(speed3), f32::from_bits(speed4)], [ptr1, ptr2]))
+}
+
+/// Search the first 20 bytes of a shot function wrapper for the address it passes to the actual
+/// shot function, and check it looks like a table of shot levels.
+fn find_shots_table<R: io::Read + io::Seek>(pe_file: &mut PEFile<R>, func_va: u32, data: Area) -> Option<u32> {
+    for i in 0..20 {
+        let mut buf = [0u8; 5];
+        if pe_file.read_at_va(func_va + i, &mut buf).is_err() {
+            continue;
+        }
+        let instr = buf[0];
+        let offset = u32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]);
+        if instr != PUSH || !data.contains(offset, 12) {
+            continue;
+        }
+        let [nb_shots, power, shots_ptr] = match read_u32s::<_, 3>(pe_file, offset) {
+            Ok(values) => values,
+            Err(_) => continue,
+        };
+        if 0 < nb_shots && nb_shots <= 1000
+           && power < 1000
+           && data.contains(shots_ptr, SHOT_SIZE.saturating_mul(nb_shots)) {
+            return Some(offset);
+        }
+    }
+    None
+}
+
+fn find_character_defs<R: io::Read + io::Seek>(pe_file: &mut PEFile<R>, text: Area, data: Area) -> Option<u32> {
+    'search: for addr in (data.va..data.va + data.size).step_by(4) {
+        for character_id in 0..4 {
+            let (speeds, ptrs) = match read_character(pe_file, addr + character_id * 24) {
+                Ok(character) => character,
+                Err(_) => continue 'search,
+            };
+
+            // Check whether the character’s speed make sense, and whether the function pointers
+            // point to valid addresses.
+            if !(speeds.iter().all(|&x| 0. < x && x < 10.)
+                 && speeds[1] <= speeds[0]
+                 && text.contains(ptrs[0], 8)
+                 && text.contains(ptrs[1], 8)) {
+                continue 'search;
+            }
+
+            // So far, this character definition seems to be valid.  Now, make sure the shot
+            // function wrappers pass valid addresses.
+            for &ptr in ptrs.iter() {
+                if find_shots_table(pe_file, ptr, data).is_none() {
+                    continue 'search;
+                }
+            }
+        }
+        return Some(addr);
+    }
+    None
+}
+
+fn read_shots<R: io::Read + io::Seek>(pe_file: &mut PEFile<R>, va: u32) -> io::Result<BTreeMap<u32, Vec<Shot>>> {
+    let mut shots = BTreeMap::new();
+    for level in 0..LEVEL_COUNT as u32 {
+        let [shots_count, power, offset] = read_u32s::<_, 3>(pe_file, va + 12 * level)?;
+        let mut level_shots = Vec::with_capacity(shots_count as usize);
+        for i in 0..shots_count {
+            let mut buf = [0u8; SHOT_SIZE as usize];
+            pe_file.read_at_va(offset + SHOT_SIZE * i, &mut buf)?;
+            let u16_at = |i: usize| u16::from_le_bytes([buf[i], buf[i + 1]]);
+            let f32_at = |i: usize| f32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+            level_shots.push(Shot {
+                interval: u16_at(0),
+                delay: u16_at(2),
+                pos: (f32_at(4), f32_at(8)),
+                hitbox: (f32_at(12), f32_at(16)),
+                angle: f32_at(20),
+                speed: f32_at(24),
+                damage: u16_at(28),
+                orb: buf[30],
+                type_: buf[31],
+                sprite: u16_at(32) as i16,
+                sound: u16_at(34) as i16,
+                unknown1: 0,
+                unknown2: 0,
+                unknown3: 0,
+                unknown4: 0,
+            });
+        }
+        shots.insert(power, level_shots);
+    }
+    Ok(shots)
+}
+
+fn default_sht() -> Sht {
+    Sht {
+        unknown1: 0,
+        bombs: 0.,
+        unknown2: 0,
+        hitbox: 2.,
+        graze_hitbox: 21.,
+        autocollection_speed: 8.,
+        item_hitbox: 19.,
+        percentage_of_cherry_loss_on_die: 0.,
+        // TODO: find the real default.
+        point_of_collection: 128.,
+        horizontal_vertical_speed: 0.,
+        horizontal_vertical_focused_speed: 0.,
+        diagonal_speed: 0.,
+        diagonal_focused_speed: 0.,
+        shots: BTreeMap::new(),
+    }
+}
+
+/// Read the definitions of all four characters, as a pair of unfocused and focused `Sht`.
+pub fn read_characters<R: io::Read + io::Seek>(file: R) -> io::Result<Vec<(Sht, Sht)>> {
+    let mut pe_file = PEFile::from_file(file)?;
+    let area = |pe_file: &PEFile<R>, name: &[u8]| {
+        pe_file.find_section(name).map(|section| Area {
+            va: pe_file.image_base.wrapping_add(section.virtual_address),
+            size: section.size_of_raw_data,
+        }).ok_or_else(invalid_exe)
+    };
+    let data = area(&pe_file, b".data")?;
+    let text = area(&pe_file, b".text")?;
+
+    let character_records_va = find_character_defs(&mut pe_file, text, data).ok_or_else(invalid_exe)?;
+
+    let mut characters = vec![];
+    let mut shots_offsets = vec![];
+    for character in 0..4 {
+        let (speeds, ptrs) = read_character(&mut pe_file, character_records_va + 24 * character)?;
+        let mut sht = default_sht();
+        sht.horizontal_vertical_speed = speeds[0];
+        sht.horizontal_vertical_focused_speed = speeds[1];
+        sht.diagonal_speed = speeds[0] * SQ2;
+        sht.diagonal_focused_speed = speeds[1] * SQ2;
+
+        // Characters might have different shot types whether they are focused or not, but
+        // properties read earlier apply to both modes.
+        let unfocused = find_shots_table(&mut pe_file, ptrs[0], data).ok_or_else(invalid_exe)?;
+        let focused = find_shots_table(&mut pe_file, ptrs[1], data).ok_or_else(invalid_exe)?;
+        shots_offsets.push((unfocused, focused));
+        characters.push((sht.clone(), sht));
+    }
+
+    // Many characters share the same shots, only read them once.
+    let mut cache = BTreeMap::new();
+    for ((sht, focused_sht), (unfocused, focused)) in characters.iter_mut().zip(shots_offsets) {
+        for (sht, offset) in [(sht, unfocused), (focused_sht, focused)] {
+            let shots = match cache.entry(offset) {
+                btree_map::Entry::Occupied(entry) => entry.into_mut(),
+                btree_map::Entry::Vacant(entry) => entry.insert(read_shots(&mut pe_file, offset)?),
+            };
+            sht.shots = shots.clone();
+        }
+    }
+
+    Ok(characters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn characters() {
+        let file = File::open("EoSD/東方紅魔郷.exe").unwrap();
+        let file = io::BufReader::new(file);
+        let characters = read_characters(file).unwrap();
+        assert_eq!(characters.len(), 4);
+        for (sht, focused_sht) in characters.iter() {
+            assert!(sht.horizontal_vertical_focused_speed <= sht.horizontal_vertical_speed);
+            assert_eq!(sht.horizontal_vertical_speed, focused_sht.horizontal_vertical_speed);
+            assert_eq!(sht.shots.len(), LEVEL_COUNT);
+            assert!(!sht.shots_for_power(0).is_empty());
+            assert!(!focused_sht.shots_for_power(128).is_empty());
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! thbgm.fmt background music description format support.
+//!
+//! Later games store all of their tracks in a single thbgm.dat file, this format
+//! describes where each of them lives and how it loops.
+
+use crate::error::{Error, FileKind, Reason, IResult, failure};
+use nom::{
+    bytes::complete::{tag, take},
+    number::complete::{le_u16, le_u32},
+    sequence::tuple,
+};
+use alloc::{string::String, vec, vec::Vec};
+
+/// A single track of the thbgm.dat file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Track {
+    /// Name of the original file of this track.
+    pub name: String,
+
+    /// Offset of this track in thbgm.dat, in bytes.
+    pub intro: u32,
+
+    /// TODO: find what that is.
+    pub unknown: u32,
+
+    /// Offset of the loop from the start of this track, in bytes.
+    pub start: u32,
+
+    /// Total size of this track, in bytes.
+    pub duration: u32,
+
+    /// Format of the samples, only PCM (1) is supported.
+    pub format_tag: u16,
+
+    /// Number of channels.
+    pub channels: u16,
+
+    /// Number of samples per second.
+    pub samples_per_sec: u32,
+
+    /// Number of bytes per second.
+    pub avg_bytes_per_sec: u32,
+
+    /// Size of a sample across all channels, in bytes.
+    pub block_align: u16,
+
+    /// Size of a sample of a single channel, in bits.
+    pub bits_per_sample: u16,
+}
+
+impl Track {
+    /// Return the loop points of this track, in samples.
+    pub fn loop_points(&self) -> (u32, u32) {
+        let block_align = self.block_align as u32;
+        (self.start / block_align, self.duration / block_align)
+    }
+}
+
+/// Main struct of the thbgm.fmt format.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fmt {
+    /// All tracks, in file order.
+    pub tracks: Vec<Track>,
+}
+
+impl Fmt {
+    /// Parse a slice of bytes into a `Fmt` struct.
+    pub fn from_slice(data: &[u8]) -> Result<Fmt, Error> {
+        match parse_fmt(data) {
+            Ok((_, fmt)) => Ok(fmt),
+            Err(err) => Err(Error::from_nom(FileKind::Fmt, data, err)),
+        }
+    }
+
+    /// Return the track coming from this file, if any.
+    pub fn get_track(&self, name: &str) -> Option<&Track> {
+        self.tracks.iter().find(|track| track.name == name)
+    }
+}
+
+fn parse_track(input: &[u8]) -> IResult<&[u8], Option<Track>> {
+    let (i, name) = take(16usize)(input)?;
+    if name[0] == 0 {
+        return Ok((i, None));
+    }
+    let name = name.split(|c| *c == b'\0').next().unwrap();
+    let name = String::from_utf8_lossy(name).into_owned();
+
+    let (i, (intro, unknown, start, duration)) = tuple((le_u32, le_u32, le_u32, le_u32))(i)?;
+    let (i, (format_tag, channels, samples_per_sec, avg_bytes_per_sec, block_align, bits_per_sample)) =
+        tuple((le_u16, le_u16, le_u32, le_u32, le_u16, le_u16))(i)?;
+    let (i, _) = tag(b"\0\0\0\0")(i)?;
+
+    // We don’t support non-PCM formats.
+    if format_tag != 1
+       || block_align == 0
+       || Some(avg_bytes_per_sec) != samples_per_sec.checked_mul(block_align as u32)
+       || Some(block_align) != channels.checked_mul(bits_per_sample).map(|bits| bits / 8) {
+        return failure(input, Reason::InvalidValue);
+    }
+
+    let track = Track {
+        name,
+        intro,
+        unknown,
+        start,
+        duration,
+        format_tag,
+        channels,
+        samples_per_sec,
+        avg_bytes_per_sec,
+        block_align,
+        bits_per_sample,
+    };
+    Ok((i, Some(track)))
+}
+
+fn parse_fmt(mut i: &[u8]) -> IResult<&[u8], Fmt> {
+    let mut tracks = vec![];
+    loop {
+        let (i2, track) = parse_track(i)?;
+        i = i2;
+        match track {
+            Some(track) => tracks.push(track),
+            None => break,
+        }
+    }
+    Ok((i, Fmt { tracks }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_track(buf: &mut Vec<u8>, name: &[u8], start: u32, duration: u32, samples_per_sec: u32) {
+        let mut padded = [0u8; 16];
+        padded[..name.len()].copy_from_slice(name);
+        buf.extend(&padded);
+        for value in [0, 0, start, duration] {
+            buf.extend(&value.to_le_bytes());
+        }
+        buf.extend(&1u16.to_le_bytes());
+        buf.extend(&2u16.to_le_bytes());
+        buf.extend(&samples_per_sec.to_le_bytes());
+        buf.extend(&(samples_per_sec * 4).to_le_bytes());
+        buf.extend(&4u16.to_le_bytes());
+        buf.extend(&16u16.to_le_bytes());
+        buf.extend(&[0; 4]);
+    }
+
+    #[test]
+    fn fmt() {
+        let mut buf = vec![];
+        push_track(&mut buf, b"th07_01.wav", 4000, 8000, 44100);
+        push_track(&mut buf, b"th07_02.wav", 400, 800, 44100);
+        buf.extend(&[0; 16]);
+
+        let fmt = Fmt::from_slice(&buf).unwrap();
+        assert_eq!(fmt.tracks.len(), 2);
+        let track = fmt.get_track("th07_01.wav").unwrap();
+        assert_eq!(track.loop_points(), (1000, 2000));
+        assert!(fmt.get_track("th07_03.wav").is_none());
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut buf = vec![];
+        push_track(&mut buf, b"th07_01.wav", 4000, 8000, 44100);
+        buf[40..44].copy_from_slice(&1234u32.to_le_bytes());
+        buf.extend(&[0; 16]);
+        Fmt::from_slice(&buf).unwrap_err();
+    }
+}
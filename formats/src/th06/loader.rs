@@ -0,0 +1,284 @@
+//! Resource loading from PBG3 archives and directories.
+//!
+//! Files are searched in every mounted source, the last one mounted taking precedence, so that a
+//! mod directory mounted after the game archives can override any of their files.
+
+use crate::th06::anm0::Anm0;
+use crate::th06::ecl::Ecl;
+use crate::th06::pbg3::{self, SharedPBG3};
+use crate::th06::std::Stage;
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Suffixes of the archives of the game, in the order they get mounted by `from_game_dir`.
+///
+/// Depending on the edition, these are prefixed by the name of the game, for instance
+/// `紅魔郷CM.DAT`.
+const GAME_ARCHIVES: [&str; 5] = ["CM.DAT", "ST.DAT", "IN.DAT", "MD.DAT", "TL.DAT"];
+
+#[derive(Clone)]
+enum Source {
+    Directory(PathBuf),
+    Archive(SharedPBG3),
+}
+
+impl Source {
+    fn get_file(&self, name: &str) -> Option<io::Result<Vec<u8>>> {
+        match self {
+            Source::Directory(directory) => {
+                let path = directory.join(name);
+                if path.is_file() {
+                    Some(fs::read(path))
+                } else {
+                    None
+                }
+            }
+            Source::Archive(pbg3) => pbg3.get_entry(name).map(|_| pbg3.get_file(name, true)),
+        }
+    }
+
+    fn list_files(&self) -> io::Result<Vec<String>> {
+        match self {
+            Source::Directory(directory) => {
+                let mut names = vec![];
+                for entry in fs::read_dir(directory)? {
+                    let entry = entry?;
+                    if entry.file_type()?.is_file() {
+                        if let Some(name) = entry.file_name().to_str() {
+                            names.push(name.to_owned());
+                        }
+                    }
+                }
+                Ok(names)
+            }
+            Source::Archive(pbg3) => Ok(pbg3.list_files().cloned().collect()),
+        }
+    }
+}
+
+/// Loader of the files of the game, from any number of archives and directories.
+///
+/// Parsed files are cached, so loading the same one twice only parses it once.
+#[derive(Clone, Default)]
+pub struct ResourceLoader {
+    sources: Vec<Source>,
+    anms: HashMap<String, Arc<Vec<Anm0>>>,
+    ecls: HashMap<String, Arc<Ecl>>,
+    stages: HashMap<String, Arc<Stage>>,
+}
+
+impl ResourceLoader {
+    /// Create a loader without any source.
+    pub fn new() -> ResourceLoader {
+        ResourceLoader::default()
+    }
+
+    /// Create a loader for a game directory, either a stock install or one with all of the
+    /// archives already extracted.
+    ///
+    /// Every archive of the game found in this directory gets mounted, followed by the directory
+    /// itself so that loose files override archived ones.
+    pub fn from_game_dir<P: AsRef<Path>>(directory: P) -> io::Result<ResourceLoader> {
+        let directory = directory.as_ref();
+        let mut names = vec![];
+        for entry in fs::read_dir(directory)? {
+            if let Some(name) = entry?.file_name().to_str() {
+                names.push(name.to_owned());
+            }
+        }
+        names.sort();
+
+        let mut loader = ResourceLoader::new();
+        for suffix in GAME_ARCHIVES.iter() {
+            let found = names.iter().find(|name| {
+                name.len() >= suffix.len() && name.is_char_boundary(name.len() - suffix.len())
+                    && name[name.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+            });
+            if let Some(name) = found {
+                loader.mount(directory.join(name))?;
+            }
+        }
+        loader.mount(directory)?;
+        Ok(loader)
+    }
+
+    /// Mount a PBG3 archive or a directory, overriding the files of every previous source.
+    pub fn mount<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let source = if path.is_dir() {
+            Source::Directory(path.to_owned())
+        } else {
+            Source::Archive(pbg3::from_path_shared(path)?)
+        };
+        self.sources.push(source);
+
+        // Files previously loaded may now come from this new source.
+        self.anms.clear();
+        self.ecls.clear();
+        self.stages.clear();
+        Ok(())
+    }
+
+    /// List the names of all files available from any source.
+    pub fn list_files(&self) -> io::Result<Vec<String>> {
+        let mut names = BTreeSet::new();
+        for source in self.sources.iter() {
+            names.extend(source.list_files()?);
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    /// Read a file from the source with the highest priority containing it.
+    pub fn get_file(&self, name: &str) -> io::Result<Vec<u8>> {
+        // Files are always in a flat namespace, don’t let them escape mounted directories.
+        if Path::new(name).file_name() != Some(OsStr::new(name)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid file name: {}", name)));
+        }
+        self.sources.iter()
+            .rev()
+            .find_map(|source| source.get_file(name))
+            .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::NotFound, format!("File not found: {}", name))))
+    }
+
+    /// Load and parse an ANM file.
+    pub fn get_anm(&mut self, name: &str) -> io::Result<Arc<Vec<Anm0>>> {
+        if let Some(anms) = self.anms.get(name) {
+            return Ok(anms.clone());
+        }
+        let anms = Arc::new(Anm0::from_slice(&self.get_file(name)?)?);
+        self.anms.insert(name.to_owned(), anms.clone());
+        Ok(anms)
+    }
+
+    /// Load and parse an ECL file.
+    pub fn get_ecl(&mut self, name: &str) -> io::Result<Arc<Ecl>> {
+        if let Some(ecl) = self.ecls.get(name) {
+            return Ok(ecl.clone());
+        }
+        let ecl = Arc::new(Ecl::from_slice(&self.get_file(name)?)?);
+        self.ecls.insert(name.to_owned(), ecl.clone());
+        Ok(ecl)
+    }
+
+    /// Load and parse an STD file.
+    pub fn get_stage(&mut self, name: &str) -> io::Result<Arc<Stage>> {
+        if let Some(stage) = self.stages.get(name) {
+            return Ok(stage.clone());
+        }
+        let stage = Arc::new(Stage::from_slice(&self.get_file(name)?)?);
+        self.stages.insert(name.to_owned(), stage.clone());
+        Ok(stage)
+    }
+
+    /// Load the enemy script of this stage, from 1 to 7 for the Extra.
+    pub fn get_stage_ecl(&mut self, stage: u8) -> io::Result<Arc<Ecl>> {
+        self.get_ecl(&format!("ecldata{}.ecl", stage))
+    }
+
+    /// Load the background of this stage, from 1 to 7 for the Extra.
+    pub fn get_stage_std(&mut self, stage: u8) -> io::Result<Arc<Stage>> {
+        self.get_stage(&format!("stage{}.std", stage))
+    }
+
+    /// Load the background animations of this stage, from 1 to 7 for the Extra.
+    pub fn get_stage_background_anm(&mut self, stage: u8) -> io::Result<Arc<Vec<Anm0>>> {
+        self.get_anm(&format!("stg{}bg.anm", stage))
+    }
+
+    /// Load the enemy animations of this stage, from 1 to 7 for the Extra.
+    ///
+    /// Some stages have a second file, whose entries then follow those of the first one.
+    pub fn get_stage_enemy_anms(&mut self, stage: u8) -> io::Result<Vec<Anm0>> {
+        let mut anms = (*self.get_anm(&format!("stg{}enm.anm", stage))?).clone();
+        match self.get_anm(&format!("stg{}enm2.anm", stage)) {
+            Ok(anms2) => anms.extend(anms2.iter().cloned()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err),
+        }
+        Ok(anms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::th06::ecl::{CallSub, Rank, Sub, SubInstruction};
+    use crate::th06::pbg3::PBG3Writer;
+    use std::collections::BTreeMap;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("touhou-loader-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn ecl_data(value: i32) -> Vec<u8> {
+        let ecl = Ecl {
+            subs: vec![Sub { instructions: vec![
+                CallSub::new(0, Rank::ALL, SubInstruction::SetInt(-10001, value)),
+            ]}],
+            mains: vec![],
+        };
+        let mut data = vec![];
+        ecl.write(&mut data).unwrap();
+        data
+    }
+
+    fn anm_data(width: u32) -> Vec<u8> {
+        let anm0 = Anm0 {
+            size: (width, 256),
+            format: crate::th06::thtx::Format::Argb4444,
+            color_key: 0,
+            png_filename: String::from("data/test.png"),
+            alpha_filename: None,
+            sprites: vec![],
+            scripts: BTreeMap::new(),
+            texture: None,
+        };
+        let mut data = vec![];
+        anm0.write(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn priority() {
+        let game = temp_dir("game");
+        let mut writer = PBG3Writer::new(vec![]);
+        writer.add_file("ecldata1.ecl", &ecl_data(1)).unwrap();
+        writer.add_file("stg1enm.anm", &anm_data(128)).unwrap();
+        writer.add_file("stg1enm2.anm", &anm_data(64)).unwrap();
+        writer.add_file("stg2enm.anm", &anm_data(32)).unwrap();
+        fs::write(game.join("th06_ST.DAT"), writer.finish().unwrap()).unwrap();
+        fs::write(game.join("readme.txt"), b"Hello").unwrap();
+
+        let mut loader = ResourceLoader::from_game_dir(&game).unwrap();
+        assert_eq!(loader.list_files().unwrap(), ["ecldata1.ecl", "readme.txt", "stg1enm.anm", "stg1enm2.anm", "stg2enm.anm", "th06_ST.DAT"]);
+        assert_eq!(loader.get_file("readme.txt").unwrap(), b"Hello");
+        assert_eq!(loader.get_file("../readme.txt").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(loader.get_file("missing.txt").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let ecl = loader.get_stage_ecl(1).unwrap();
+        assert!(matches!(ecl.subs[0].instructions[0].instr, SubInstruction::SetInt(-10001, 1)));
+        assert!(Arc::ptr_eq(&ecl, &loader.get_stage_ecl(1).unwrap()));
+
+        let anms = loader.get_stage_enemy_anms(1).unwrap();
+        assert_eq!(anms.iter().map(|anm0| anm0.size.0).collect::<Vec<_>>(), [128, 64]);
+        assert_eq!(loader.get_stage_enemy_anms(2).unwrap().len(), 1);
+
+        // A mod directory overrides the game files.
+        let mod_dir = temp_dir("mod");
+        fs::write(mod_dir.join("ecldata1.ecl"), ecl_data(2)).unwrap();
+        loader.mount(&mod_dir).unwrap();
+        let ecl = loader.get_stage_ecl(1).unwrap();
+        assert!(matches!(ecl.subs[0].instructions[0].instr, SubInstruction::SetInt(-10001, 2)));
+        assert_eq!(loader.get_stage_enemy_anms(1).unwrap().len(), 2);
+
+        fs::remove_dir_all(game).unwrap();
+        fs::remove_dir_all(mod_dir).unwrap();
+    }
+}
@@ -1,6 +1,19 @@
 //! Touhou 6: EoSD implementation.
 
 pub mod pbg3;
+#[cfg(feature = "std")]
+pub mod loader;
 pub mod anm0;
+pub mod anm0_script;
+pub mod thtx;
 pub mod ecl;
+pub mod ecl_script;
 pub mod std;
+pub mod msg;
+pub mod t6rp;
+pub mod score;
+pub mod sht;
+#[cfg(feature = "std")]
+pub mod exe;
+pub mod music;
+pub mod fmt;
@@ -0,0 +1,190 @@
+//! MSG dialogue format support.
+
+use crate::error::{Error, FileKind, Reason, IResult, failure, at_offset};
+use nom::{
+    bytes::complete::take,
+    number::complete::{le_u8, le_u16, le_u32, le_i16},
+    sequence::tuple,
+    multi::count,
+};
+use encoding_rs::SHIFT_JIS;
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+
+/// A single instruction, part of a `Script`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Call {
+    /// Time at which this instruction will be called.
+    pub time: u16,
+
+    /// The instruction to call.
+    pub instr: Instruction,
+}
+
+/// Script driving a dialogue.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Script {
+    /// List of instructions in this script.
+    pub instructions: Vec<Call>,
+}
+
+/// Main struct of the MSG dialogue format.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Msg {
+    /// A map of scripts, indexed by their entry number.
+    ///
+    /// In EoSD, Reimu’s scripts start at 0 and Marisa’s ones at 10.
+    pub scripts: BTreeMap<u32, Script>,
+}
+
+impl Msg {
+    /// Parse a slice of bytes into a `Msg` struct.
+    pub fn from_slice(data: &[u8]) -> Result<Msg, Error> {
+        match parse_msg(data) {
+            Ok((_, msg)) => Ok(msg),
+            Err(err) => Err(Error::from_nom(FileKind::Msg, data, err)),
+        }
+    }
+}
+
+/// Parse a SHIFT_JIS byte string filling the rest of the instruction into a String.
+#[allow(non_snake_case)]
+pub(crate) fn le_String(i: &[u8]) -> IResult<&[u8], String> {
+    let data = i.split(|c| *c == b'\0').next().unwrap();
+    let (string, _encoding, _replaced) = SHIFT_JIS.decode(data);
+    Ok((&i[i.len()..], string.into_owned()))
+}
+
+macro_rules! declare_msg_instructions {
+    ($($opcode:tt => fn $name:ident($($arg:ident: $arg_type:ident),*)),*,) => {
+        /// Available instructions in a `Msg`.
+        #[allow(missing_docs)]
+        #[derive(Debug, Clone)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub enum Instruction {
+            $(
+                $name($($arg_type),*)
+            ),*
+        }
+
+        fn parse_instruction_args(input: &[u8], opcode: u8) -> IResult<&[u8], Instruction> {
+            let mut i = input;
+            let instr = match opcode {
+                $(
+                    $opcode => {
+                        $(
+                            let (i2, $arg) = concat_idents!(le_, $arg_type)(i)?;
+                            i = i2;
+                        )*
+                        Instruction::$name($($arg),*)
+                    }
+                )*
+                _ => return failure(input, Reason::UnknownOpcode(opcode as u16))
+            };
+            Ok((i, instr))
+        }
+    };
+}
+
+declare_msg_instructions!{
+    // Ends the dialogue, seems to crash the game when skipping is allowed.
+    0 => fn End(),
+    1 => fn Enter(side: i16, effect: i16),
+    2 => fn ChangeFace(side: i16, index: i16),
+    3 => fn DisplayText(side: i16, index: i16, text: String),
+    4 => fn Pause(duration: u32),
+    5 => fn Animate(side: i16, effect: i16),
+    6 => fn SpawnEnemySprite(),
+    7 => fn ChangeMusic(track: u32),
+    8 => fn DisplayDescription(side: i16, index: i16, text: String),
+    9 => fn ShowScores(unknown: u32),
+    10 => fn Freeze(),
+    11 => fn NextStage(),
+    // TODO: find what that is.
+    12 => fn Unknown12(),
+    13 => fn SetAllowSkip(allow: u32),
+    // TODO: find what that is.
+    14 => fn Unknown14(),
+}
+
+fn parse_instruction(i: &[u8]) -> IResult<&[u8], Option<Call>> {
+    let (i, (time, opcode, size)) = tuple((le_u16, le_u8, le_u8))(i)?;
+    if time == 0 && opcode == 0 {
+        return Ok((i, None));
+    }
+    // Arguments are parsed from their own slice, so that strings know where to stop.
+    let (i, data) = take(size as usize)(i)?;
+    let (_, instr) = parse_instruction_args(data, opcode)?;
+    Ok((i, Some(Call { time, instr })))
+}
+
+fn parse_script(mut i: &[u8]) -> IResult<&[u8], Script> {
+    let mut instructions = vec![];
+    loop {
+        let (i2, call) = parse_instruction(i)?;
+        i = i2;
+        match call {
+            Some(call) => instructions.push(call),
+            None => break,
+        }
+    }
+    Ok((i, Script { instructions }))
+}
+
+fn parse_msg(input: &[u8]) -> IResult<&[u8], Msg> {
+    let (i, entry_count) = le_u32(input)?;
+    let (_, offsets) = count(le_u32, entry_count as usize)(i)?;
+
+    let mut scripts = BTreeMap::new();
+    for (index, &offset) in offsets.iter().enumerate() {
+        // If Reimu has less than 10 scripts, the remaining offsets are equal to her first.
+        if !scripts.is_empty() && offset == offsets[0] {
+            continue;
+        }
+        let (_, script) = parse_script(at_offset(input, offset)?)?;
+        scripts.insert(index as u32, script);
+    }
+
+    let msg = Msg {
+        scripts,
+    };
+    Ok((b"", msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msg() {
+        let mut buf = vec![];
+        // Two entries, the second one being an alias of the first.
+        buf.extend(&2u32.to_le_bytes());
+        buf.extend(&12u32.to_le_bytes());
+        buf.extend(&12u32.to_le_bytes());
+        // 0: DisplayText(0, 0, "こんにちは")
+        let (text, _, _) = SHIFT_JIS.encode("こんにちは");
+        buf.extend(&[0, 0, 3, 4 + text.len() as u8 + 1, 0, 0, 0, 0]);
+        buf.extend(&*text);
+        buf.push(0);
+        // 60: Pause(60)
+        buf.extend(&[60, 0, 4, 4, 60, 0, 0, 0]);
+        // 61: End()
+        buf.extend(&[61, 0, 0, 0]);
+        buf.extend(&[0, 0, 0, 0]);
+
+        let msg = Msg::from_slice(&buf).unwrap();
+        assert_eq!(msg.scripts.len(), 1);
+        let script = &msg.scripts[&0];
+        assert_eq!(script.instructions.len(), 3);
+        match &script.instructions[0].instr {
+            Instruction::DisplayText(0, 0, text) => assert_eq!(text, "こんにちは"),
+            instr => panic!("Wrong instruction {:?}", instr),
+        }
+        assert_eq!(script.instructions[1].time, 60);
+        assert!(matches!(script.instructions[1].instr, Instruction::Pause(60)));
+        assert!(matches!(script.instructions[2].instr, Instruction::End()));
+    }
+}
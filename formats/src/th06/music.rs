@@ -0,0 +1,53 @@
+//! Background music loop points (.pos) format support.
+
+use crate::error::{Error, FileKind};
+use nom::{
+    number::complete::le_u32,
+    sequence::tuple,
+};
+
+/// Loop points of a background music track, in samples.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Track {
+    /// Sample at which the loop starts, everything before it is the intro.
+    pub start: u32,
+
+    /// Sample at which playback jumps back to `start`.
+    pub end: u32,
+}
+
+impl Track {
+    /// Parse a slice of bytes into a `Track` struct.
+    pub fn from_slice(data: &[u8]) -> Result<Track, Error> {
+        match tuple((le_u32, le_u32))(data) {
+            Ok((_, (start, end))) => Ok(Track { start, end }),
+            Err(err) => Err(Error::from_nom(FileKind::Pos, data, err)),
+        }
+    }
+
+    /// Return the sample playback should continue from, if the current one is past the end of
+    /// the loop.
+    pub fn loop_offset(&self, sample: u32) -> Option<u32> {
+        if self.end <= self.start || sample < self.end {
+            return None;
+        }
+        Some(self.start + (sample - self.end) % (self.end - self.start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track() {
+        let track = Track::from_slice(b"\x10\x27\0\0\x20\x4e\0\0").unwrap();
+        assert_eq!(track, Track { start: 10000, end: 20000 });
+        assert_eq!(track.loop_offset(0), None);
+        assert_eq!(track.loop_offset(19999), None);
+        assert_eq!(track.loop_offset(20000), Some(10000));
+        assert_eq!(track.loop_offset(20735), Some(10735));
+        assert_eq!(track.loop_offset(30001), Some(10001));
+    }
+}
@@ -5,19 +5,36 @@
 //!
 //! PBG3 files are merely a bitstream composed of a header, a file
 //! table, and LZSS-compressed files.
+//!
+//! Without the `std` feature, only `SlicePBG3` is available, to read archives already in memory.
 
-use touhou_utils::bitstream::BitStream;
+use crate::error::{Error, FileKind, Reason};
+use alloc::collections::btree_map::{self, BTreeMap};
+use alloc::{string::String, vec, vec::Vec};
+#[cfg(feature = "std")]
+use touhou_utils::bitstream::{BitStream, BitWriter};
+#[cfg(feature = "std")]
 use touhou_utils::lzss;
-use std::fs::File;
-use std::io;
+#[cfg(feature = "std")]
+use std::fs::{self, File};
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+#[cfg(feature = "std")]
 use std::collections::hash_map::{self, HashMap};
+#[cfg(feature = "std")]
+use std::ops::Range;
+#[cfg(feature = "std")]
 use std::path::Path;
+#[cfg(feature = "std")]
+use std::sync::Arc;
 
 /// Helper struct to handle strings and integers in PBG3 bitstreams.
+#[cfg(feature = "std")]
 pub struct PBG3BitStream<R: io::Read + io::Seek> {
     bitstream: BitStream<R>,
 }
 
+#[cfg(feature = "std")]
 impl<R: io::Read + io::Seek> PBG3BitStream<R> {
     /// Create a bitstream capable of reading u32 and strings.
     pub fn new(bitstream: BitStream<R>) -> PBG3BitStream<R> {
@@ -73,13 +90,405 @@ impl<R: io::Read + io::Seek> PBG3BitStream<R> {
     }
 }
 
-type Entry = (u32, u32, u32, u32, u32);
+/// Helper struct to write strings and integers in PBG3 bitstreams.
+#[cfg(feature = "std")]
+pub struct PBG3BitWriter<W: io::Write> {
+    bitwriter: BitWriter<W>,
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> PBG3BitWriter<W> {
+    /// Create a bit writer capable of writing u32 and strings.
+    pub fn new(bitwriter: BitWriter<W>) -> PBG3BitWriter<W> {
+        PBG3BitWriter {
+            bitwriter,
+        }
+    }
+
+    /// Write a given amount of bits.
+    pub fn write(&mut self, value: usize, nb_bits: usize) -> io::Result<()> {
+        self.bitwriter.write(value, nb_bits)
+    }
+
+    /// Write a given amount of bytes.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.bitwriter.write_bytes(bytes)
+    }
+
+    /// Write an integer to the bitstream, using as few bytes as possible.
+    pub fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_u32_sized(value, u32_size(value))
+    }
+
+    /// Write an integer to the bitstream, using exactly `size` bytes.
+    pub fn write_u32_sized(&mut self, value: u32, size: usize) -> io::Result<()> {
+        assert!(size >= u32_size(value) && size <= 4);
+        self.write(size - 1, 2)?;
+        self.write(value as usize, size * 8)
+    }
+
+    /// Write a NULL-terminated string to the bitstream.
+    pub fn write_string(&mut self, string: &[u8]) -> io::Result<()> {
+        self.write_bytes(string)?;
+        self.write(0, 8)
+    }
+
+    /// Pad the last byte and return the inner writer.
+    pub fn into_inner(self) -> io::Result<W> {
+        self.bitwriter.into_inner()
+    }
+}
+
+/// Return the number of bytes needed to store this integer in a PBG3 bitstream.
+#[cfg(feature = "std")]
+fn u32_size(value: u32) -> usize {
+    match value {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x10000..=0xffffff => 3,
+        _ => 4,
+    }
+}
+
+/// Description of a file stored in a PBG3 archive, as found in its file table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry {
+    /// First unknown value.
+    pub unknown_1: u32,
+
+    /// Second unknown value.
+    pub unknown_2: u32,
+
+    /// Sum of all bytes of the compressed data.
+    pub checksum: u32,
+
+    /// Offset of the compressed data in the archive.
+    pub offset: u32,
+
+    /// Size of the file once decompressed.
+    pub size: u32,
+
+    /// Size of the compressed data, deduced from the offset of whatever follows it.
+    pub compressed_size: u32,
+}
+
+/// Read the header and the file table of a PBG3 archive.
+#[cfg(feature = "std")]
+fn read_entries<R: io::Read + io::Seek>(mut file: R) -> io::Result<(HashMap<String, Entry>, PBG3BitStream<R>)> {
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    if &magic != b"PBG3" {
+        return Err(io::Error::new(io::ErrorKind::Other, "Wrong magic!"));
+    }
+
+    let bitstream = BitStream::new(file);
+    let mut bitstream = PBG3BitStream::new(bitstream);
+    let mut entries = HashMap::new();
+
+    let nb_entries = bitstream.read_u32()?;
+    let offset = bitstream.read_u32()?;
+    bitstream.seek(io::SeekFrom::Start(offset as u64))?;
+
+    for _ in 0..nb_entries {
+        let unknown_1 = bitstream.read_u32()?;
+        let unknown_2 = bitstream.read_u32()?;
+        let checksum = bitstream.read_u32()?; // Checksum of *compressed data*
+        let offset = bitstream.read_u32()?;
+        let size = bitstream.read_u32()?;
+        let name = bitstream.read_string(255)?;
+        let name = String::from_utf8(name).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        entries.insert(name, Entry {
+            unknown_1,
+            unknown_2,
+            checksum,
+            offset,
+            size,
+            compressed_size: 0,
+        });
+    }
+
+    fill_compressed_sizes(entries.values_mut(), offset);
+    Ok((entries, bitstream))
+}
+
+/// Deduce the compressed size of every entry from the offset of whatever follows it.
+fn fill_compressed_sizes<'a, I: Iterator<Item = &'a mut Entry>>(entries: I, table_offset: u32) {
+    // Compressed data is stored contiguously, followed by the file table.
+    let mut entries: Vec<_> = entries.collect();
+    let mut offsets: Vec<u32> = entries.iter().map(|entry| entry.offset).collect();
+    offsets.push(table_offset);
+    offsets.sort_unstable();
+    for entry in entries.iter_mut() {
+        let end = offsets.iter().find(|&&end| end > entry.offset).copied().unwrap_or(entry.offset);
+        entry.compressed_size = end - entry.offset;
+    }
+}
+
+#[cfg(feature = "std")]
+fn find_entry<'a>(entries: &'a HashMap<String, Entry>, filename: &str) -> io::Result<&'a Entry> {
+    entries.get(filename)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("File not found in PBG3: {}", filename)))
+}
+
+/// Capacity to reserve for the decompressed data of a file, bounded by what its compressed data
+/// can give so that a bogus size doesn’t make us allocate too much: each bit gives at most one
+/// byte, a match of 18 bits copying at most 18 bytes.
+fn decompressed_capacity(size: usize, compressed_size: usize) -> usize {
+    size.min(compressed_size.saturating_mul(8))
+}
+
+fn sorted_entries<'a, I: Iterator<Item = (&'a String, &'a Entry)>>(entries: I) -> Vec<(&'a str, &'a Entry)> {
+    let mut entries: Vec<_> = entries.map(|(name, entry)| (name.as_str(), entry)).collect();
+    entries.sort_by_key(|(name, entry)| (entry.offset, *name));
+    entries
+}
+
+/// Bit reader over a slice, the counterpart of `PBG3BitStream` for data already in memory.
+struct SliceBitStream<'a> {
+    data: &'a [u8],
+
+    /// Position of the next bit to read, from the start of `data`.
+    position: usize,
+}
+
+impl<'a> SliceBitStream<'a> {
+    /// Start reading at this offset in bytes, returns `None` if it is out of bounds.
+    fn new(data: &'a [u8], offset: usize) -> Option<SliceBitStream<'a>> {
+        if offset > data.len() {
+            return None;
+        }
+        Some(SliceBitStream {
+            data,
+            position: offset * 8,
+        })
+    }
+
+    /// Offset of the byte containing the next bit.
+    fn offset(&self) -> usize {
+        self.position / 8
+    }
+
+    /// Read a given amount of bits, returns `None` at the end of the data.
+    fn read(&mut self, nb_bits: usize) -> Option<usize> {
+        let mut value = 0;
+        for _ in 0..nb_bits {
+            let byte = self.data.get(self.position / 8)?;
+            let bit = (byte >> (7 - self.position % 8)) & 0x01;
+            value = (value << 1) | bit as usize;
+            self.position += 1;
+        }
+        Some(value)
+    }
+
+    /// Read an integer, see `PBG3BitStream::read_u32`.
+    fn read_u32(&mut self) -> Option<u32> {
+        let size = self.read(2)?;
+        Some(self.read((size + 1) * 8)? as u32)
+    }
+
+    /// Read a string, see `PBG3BitStream::read_string`.
+    fn read_string(&mut self, mut max_size: usize) -> Option<Vec<u8>> {
+        let mut buf = Vec::new();
+        while max_size > 0 {
+            let byte = self.read(8)? as u8;
+            if byte == 0 {
+                break;
+            }
+            buf.push(byte);
+            max_size -= 1;
+        }
+        Some(buf)
+    }
+}
+
+/// Decompress the data of a file, like `lzss::decompress` with the parameters used by PBG3.
+fn decompress(compressed: &[u8], size: usize) -> Result<Vec<u8>, Reason> {
+    const DICTIONARY_SIZE: usize = 0x2000;
+    let mut bitstream = SliceBitStream::new(compressed, 0).unwrap();
+    let mut data = Vec::with_capacity(decompressed_capacity(size, compressed.len()));
+    let mut dictionary = vec![0u8; DICTIONARY_SIZE];
+    let mut dictionary_head = 1;
+
+    while data.len() < size {
+        if bitstream.read(1).ok_or(Reason::Truncated)? != 0 {
+            let byte = bitstream.read(8).ok_or(Reason::Truncated)? as u8;
+            dictionary[dictionary_head] = byte;
+            dictionary_head = (dictionary_head + 1) % DICTIONARY_SIZE;
+            data.push(byte);
+        } else {
+            let offset = bitstream.read(13).ok_or(Reason::Truncated)?;
+            let length = bitstream.read(4).ok_or(Reason::Truncated)? + 3;
+            if data.len() + length > size {
+                return Err(Reason::InvalidValue);
+            }
+            for i in offset..offset + length {
+                let byte = dictionary[i % DICTIONARY_SIZE];
+                dictionary[dictionary_head] = byte;
+                dictionary_head = (dictionary_head + 1) % DICTIONARY_SIZE;
+                data.push(byte);
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// PBG3 archive read from a slice, which doesn’t require the standard library.
+///
+/// Files get decompressed all at once, unlike with `PBG3` or `SharedPBG3`.
+pub struct SlicePBG3<'a> {
+    entries: BTreeMap<String, Entry>,
+    data: &'a [u8],
+}
+
+impl<'a> SlicePBG3<'a> {
+    /// Parse the header and the file table of a PBG3 archive.
+    pub fn from_slice(data: &'a [u8]) -> Result<SlicePBG3<'a>, Error> {
+        let error = |offset, reason| Error {
+            offset,
+            kind: FileKind::Pbg3,
+            reason,
+        };
+        let truncated = || error(data.len(), Reason::Truncated);
+        if data.len() < 4 {
+            return Err(truncated());
+        }
+        if &data[..4] != b"PBG3" {
+            return Err(error(0, Reason::BadMagic));
+        }
+
+        let mut bitstream = SliceBitStream::new(data, 4).unwrap();
+        let nb_entries = bitstream.read_u32().ok_or_else(truncated)?;
+        let table_offset = bitstream.read_u32().ok_or_else(truncated)?;
+        let mut bitstream = SliceBitStream::new(data, table_offset as usize)
+            .ok_or_else(|| error(4, Reason::BadOffset(table_offset)))?;
+
+        let mut entries = BTreeMap::new();
+        for _ in 0..nb_entries {
+            let start = bitstream.offset();
+            let unknown_1 = bitstream.read_u32().ok_or_else(truncated)?;
+            let unknown_2 = bitstream.read_u32().ok_or_else(truncated)?;
+            let checksum = bitstream.read_u32().ok_or_else(truncated)?;
+            let offset = bitstream.read_u32().ok_or_else(truncated)?;
+            let size = bitstream.read_u32().ok_or_else(truncated)?;
+            let name = bitstream.read_string(255).ok_or_else(truncated)?;
+            let name = String::from_utf8(name).map_err(|_| error(start, Reason::BadEncoding))?;
+            if offset > table_offset {
+                return Err(error(start, Reason::BadOffset(offset)));
+            }
+            entries.insert(name, Entry {
+                unknown_1,
+                unknown_2,
+                checksum,
+                offset,
+                size,
+                compressed_size: 0,
+            });
+        }
+        fill_compressed_sizes(entries.values_mut(), table_offset);
+
+        Ok(SlicePBG3 {
+            entries,
+            data,
+        })
+    }
+
+    /// List all file entries in this PBG3 archive.
+    pub fn list_files(&self) -> btree_map::Keys<'_, String, Entry> {
+        self.entries.keys()
+    }
+
+    /// Return the file table entry of this file, if present.
+    pub fn get_entry(&self, filename: &str) -> Option<&Entry> {
+        self.entries.get(filename)
+    }
+
+    /// List all file entries in this PBG3 archive, in the order of their data.
+    pub fn entries(&self) -> Vec<(&str, &Entry)> {
+        sorted_entries(self.entries.iter())
+    }
+
+    /// Read a single file from this PBG3 archive, returns `None` if it isn’t present.
+    pub fn get_file(&self, filename: &str, check: bool) -> Option<Result<Vec<u8>, Error>> {
+        let entry = self.entries.get(filename)?;
+        let error = |reason| Error {
+            offset: entry.offset as usize,
+            kind: FileKind::Pbg3,
+            reason,
+        };
+        let start = entry.offset as usize;
+        let compressed = &self.data[start..start + entry.compressed_size as usize];
+        // Checksum of *compressed data*
+        if check && compressed.iter().fold(0u32, |value, &c| value.wrapping_add(c as u32)) != entry.checksum {
+            return Some(Err(error(Reason::BadChecksum)));
+        }
+        Some(decompress(compressed, entry.size as usize).map_err(error))
+    }
+}
+
+/// Sum every byte read through it, to compute the checksum of compressed data.
+#[cfg(feature = "std")]
+struct ChecksumReader<R: io::Read> {
+    io: R,
+    sum: u32,
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> io::Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.io.read(buf)?;
+        for &c in buf[..len].iter() {
+            self.sum = self.sum.wrapping_add(c as u32);
+        }
+        Ok(len)
+    }
+}
+
+/// Reader of a single file from a PBG3 archive, decompressing it as it gets read.
+///
+/// When checking was requested, the read returning its last bytes fails instead if the checksum
+/// of the compressed data doesn’t match.
+#[cfg(feature = "std")]
+pub struct FileReader<R: io::Read> {
+    decompressor: lzss::Decompressor<ChecksumReader<R>>,
+    checksum: Option<u32>,
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> FileReader<R> {
+    fn new(io: R, entry: &Entry, check: bool) -> FileReader<R> {
+        let io = ChecksumReader { io, sum: 0 };
+        FileReader {
+            decompressor: lzss::Decompressor::new(io, entry.size as usize, 0x2000, 13, 4, 3),
+            checksum: if check { Some(entry.checksum) } else { None },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> io::Read for FileReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.decompressor.read(buf)?;
+        if self.decompressor.is_finished() {
+            if let Some(checksum) = self.checksum.take() {
+                // The checksum covers all of the compressed data, including its end marker.
+                io::copy(self.decompressor.get_mut(), &mut io::sink())?;
+                if self.decompressor.get_ref().sum != checksum {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Corrupted data!"));
+                }
+            }
+        }
+        Ok(len)
+    }
+}
 
 /// Handle PBG3 archive files.
 ///
 /// PBG3 is a file archive format used in Touhou 6: EoSD.
 /// This class provides a representation of such files, as well as functions to
 /// read and extract files from a PBG3 archive.
+#[cfg(feature = "std")]
 pub struct PBG3<R: io::Read + io::Seek> {
     /// List of PBG3Entry objects describing files present in the archive.
     entries: HashMap<String, Entry>,
@@ -88,6 +497,7 @@ pub struct PBG3<R: io::Read + io::Seek> {
     bitstream: PBG3BitStream<R>,
 }
 
+#[cfg(feature = "std")]
 impl<R: io::Read + io::Seek> PBG3<R> {
     /// Create a PBG3 archive.
     fn new(entries: HashMap<String, Entry>, bitstream: PBG3BitStream<R>) -> PBG3<R> {
@@ -98,32 +508,8 @@ impl<R: io::Read + io::Seek> PBG3<R> {
     }
 
     /// Open a PBG3 archive.
-    pub fn from_file(mut file: R) -> io::Result<PBG3<R>> {
-        let mut magic = [0; 4];
-        file.read_exact(&mut magic)?;
-        if &magic != b"PBG3" {
-            return Err(io::Error::new(io::ErrorKind::Other, "Wrong magic!"));
-        }
-
-        let bitstream = BitStream::new(file);
-        let mut bitstream = PBG3BitStream::new(bitstream);
-        let mut entries = HashMap::new();
-
-        let nb_entries = bitstream.read_u32()?;
-        let offset = bitstream.read_u32()?;
-        bitstream.seek(io::SeekFrom::Start(offset as u64))?;
-
-        for _ in 0..nb_entries {
-            let unknown_1 = bitstream.read_u32()?;
-            let unknown_2 = bitstream.read_u32()?;
-            let checksum = bitstream.read_u32()?; // Checksum of *compressed data*
-            let offset = bitstream.read_u32()?;
-            let size = bitstream.read_u32()?;
-            let name = bitstream.read_string(255)?;
-            let name = String::from_utf8(name).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
-            entries.insert(name, (unknown_1, unknown_2, checksum, offset, size));
-        }
-
+    pub fn from_file(file: R) -> io::Result<PBG3<R>> {
+        let (entries, bitstream) = read_entries(file)?;
         Ok(PBG3::new(entries, bitstream))
     }
 
@@ -132,36 +518,227 @@ impl<R: io::Read + io::Seek> PBG3<R> {
         self.entries.keys()
     }
 
+    /// Return the file table entry of this file, if present.
+    pub fn get_entry(&self, filename: &str) -> Option<&Entry> {
+        self.entries.get(filename)
+    }
+
+    /// List all file entries in this PBG3 archive, in the order of their data.
+    pub fn entries(&self) -> Vec<(&str, &Entry)> {
+        sorted_entries(self.entries.iter())
+    }
+
+    /// Open a single file from this PBG3 archive.
+    ///
+    /// Its compressed data is read at once, but the returned reader is independent from this
+    /// archive and only decompresses it as it gets read.
+    pub fn open_file(&mut self, filename: &str, check: bool) -> io::Result<FileReader<io::Cursor<Vec<u8>>>> {
+        let entry = *find_entry(&self.entries, filename)?;
+        self.bitstream.seek(io::SeekFrom::Start(entry.offset as u64))?;
+        let compressed = self.bitstream.read_bytes(entry.compressed_size as usize)?;
+        Ok(FileReader::new(io::Cursor::new(compressed), &entry, check))
+    }
+
     /// Read a single file from this PBG3 archive.
     pub fn get_file(&mut self, filename: &str, check: bool) -> io::Result<Vec<u8>> {
-        let (_unknown_1, _unknown_2, checksum, offset, size) =
-            self.entries.get(filename)
-                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("File not found in PBG3: {}", filename)))?;
-        self.bitstream.seek(io::SeekFrom::Start(*offset as u64))?;
-        let data = lzss::decompress(&mut self.bitstream.bitstream, *size as usize, 0x2000, 13, 4, 3)?;
-        if check {
-            // Verify the checksum.
-            let compressed_size = self.bitstream.tell()? as u32 - *offset;
-            self.bitstream.seek(io::SeekFrom::Start(*offset as u64))?;
-            let mut value: u32 = 0;
-            for c in self.bitstream.read_bytes(compressed_size as usize)? {
-                value = value.wrapping_add(c as u32);
-            }
-            if value != *checksum {
-                return Err(io::Error::new(io::ErrorKind::Other, "Corrupted data!"));
-            }
+        let mut reader = self.open_file(filename, check)?;
+        let entry = &self.entries[filename];
+        let mut data = Vec::with_capacity(decompressed_capacity(entry.size as usize, entry.compressed_size as usize));
+        reader.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Part of a buffer shared between threads.
+#[cfg(feature = "std")]
+struct SharedSlice {
+    data: Arc<[u8]>,
+    range: Range<usize>,
+}
+
+#[cfg(feature = "std")]
+impl AsRef<[u8]> for SharedSlice {
+    fn as_ref(&self) -> &[u8] {
+        &self.data[self.range.clone()]
+    }
+}
+
+/// Thread-safe handle to a PBG3 archive loaded in memory.
+///
+/// Files can be opened from a shared reference, and clones share the same data, so many files can
+/// be decompressed in parallel from different threads.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct SharedPBG3 {
+    entries: Arc<HashMap<String, Entry>>,
+    data: Arc<[u8]>,
+}
+
+#[cfg(feature = "std")]
+impl SharedPBG3 {
+    /// Open a PBG3 archive from its whole contents.
+    pub fn from_data<D: Into<Arc<[u8]>>>(data: D) -> io::Result<SharedPBG3> {
+        let data = data.into();
+        let (entries, _) = read_entries(io::Cursor::new(&data[..]))?;
+        Ok(SharedPBG3 {
+            entries: Arc::new(entries),
+            data,
+        })
+    }
+
+    /// List all file entries in this PBG3 archive.
+    pub fn list_files(&self) -> hash_map::Keys<'_, String, Entry> {
+        self.entries.keys()
+    }
+
+    /// Return the file table entry of this file, if present.
+    pub fn get_entry(&self, filename: &str) -> Option<&Entry> {
+        self.entries.get(filename)
+    }
+
+    /// List all file entries in this PBG3 archive, in the order of their data.
+    pub fn entries(&self) -> Vec<(&str, &Entry)> {
+        sorted_entries(self.entries.iter())
+    }
+
+    /// Open a single file from this PBG3 archive, decompressing it as it gets read.
+    pub fn open_file(&self, filename: &str, check: bool) -> io::Result<FileReader<impl io::Read + Send + Sync + 'static>> {
+        let entry = find_entry(&self.entries, filename)?;
+        let start = entry.offset as usize;
+        let end = start + entry.compressed_size as usize;
+        if end > self.data.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("Truncated file in PBG3: {}", filename)));
         }
+        let slice = SharedSlice {
+            data: self.data.clone(),
+            range: start..end,
+        };
+        Ok(FileReader::new(io::Cursor::new(slice), entry, check))
+    }
+
+    /// Read a single file from this PBG3 archive.
+    pub fn get_file(&self, filename: &str, check: bool) -> io::Result<Vec<u8>> {
+        let mut reader = self.open_file(filename, check)?;
+        let entry = &self.entries[filename];
+        let mut data = Vec::with_capacity(decompressed_capacity(entry.size as usize, entry.compressed_size as usize));
+        reader.read_to_end(&mut data)?;
         Ok(data)
     }
 }
 
+#[cfg(feature = "std")]
+struct WriterEntry {
+    name: String,
+    unknown_1: u32,
+    unknown_2: u32,
+    checksum: u32,
+    size: u32,
+    compressed: Vec<u8>,
+}
+
+/// Build PBG3 archive files.
+///
+/// Files are compressed as soon as they get added, and the archive itself is
+/// only written once `finish` gets called, since the header has to point to
+/// the file table placed after all of the compressed data.
+#[cfg(feature = "std")]
+pub struct PBG3Writer<W: io::Write> {
+    file: W,
+    entries: Vec<WriterEntry>,
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> PBG3Writer<W> {
+    /// Create a PBG3 archive writer.
+    pub fn new(file: W) -> PBG3Writer<W> {
+        PBG3Writer {
+            file,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Compress and add a file to this archive.
+    pub fn add_file(&mut self, filename: &str, data: &[u8]) -> io::Result<()> {
+        self.add_file_with_unknowns(filename, data, 0, 0)
+    }
+
+    /// Compress and add a file to this archive, along with the two unknown
+    /// values of its entry.
+    pub fn add_file_with_unknowns(&mut self, filename: &str, data: &[u8], unknown_1: u32, unknown_2: u32) -> io::Result<()> {
+        if filename.len() > 255 || filename.as_bytes().contains(&0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid file name for PBG3: {}", filename)));
+        }
+        let mut bitwriter = BitWriter::new(Vec::new());
+        lzss::compress(&mut bitwriter, data, 0x2000, 13, 4, 3)?;
+        let compressed = bitwriter.into_inner()?;
+        // Checksum of *compressed data*
+        let checksum = compressed.iter().fold(0u32, |value, &c| value.wrapping_add(c as u32));
+        self.entries.push(WriterEntry {
+            name: filename.to_string(),
+            unknown_1,
+            unknown_2,
+            checksum,
+            size: data.len() as u32,
+            compressed,
+        });
+        Ok(())
+    }
+
+    /// Write the header, all compressed files and the file table, then
+    /// return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let nb_entries = self.entries.len() as u32;
+        let data_size: usize = self.entries.iter().map(|entry| entry.compressed.len()).sum();
+
+        // The header size depends on the size of the file table offset, so
+        // pick the smallest one which can hold it.
+        let (offset_size, data_offset) = (1..=4).map(|size| {
+            let header_bits = 2 + u32_size(nb_entries) * 8 + 2 + size * 8;
+            (size, 4 + header_bits.div_ceil(8))
+        }).find(|&(size, data_offset)| u32_size((data_offset + data_size) as u32) <= size).unwrap();
+        let table_offset = data_offset + data_size;
+
+        self.file.write_all(b"PBG3")?;
+        let mut header = PBG3BitWriter::new(BitWriter::new(&mut self.file));
+        header.write_u32(nb_entries)?;
+        header.write_u32_sized(table_offset as u32, offset_size)?;
+        header.into_inner()?;
+
+        for entry in self.entries.iter() {
+            self.file.write_all(&entry.compressed)?;
+        }
+
+        let mut table = PBG3BitWriter::new(BitWriter::new(&mut self.file));
+        let mut offset = data_offset as u32;
+        for entry in self.entries.iter() {
+            table.write_u32(entry.unknown_1)?;
+            table.write_u32(entry.unknown_2)?;
+            table.write_u32(entry.checksum)?;
+            table.write_u32(offset)?;
+            table.write_u32(entry.size)?;
+            table.write_string(entry.name.as_bytes())?;
+            offset += entry.compressed.len() as u32;
+        }
+        table.into_inner()?;
+
+        Ok(self.file)
+    }
+}
+
 /// Open a PBG3 archive from its path.
+#[cfg(feature = "std")]
 pub fn from_path_buffered<P: AsRef<Path>>(path: P) -> io::Result<PBG3<io::BufReader<File>>> {
     let file = File::open(path)?;
     let buf_file = io::BufReader::new(file);
     PBG3::from_file(buf_file)
 }
 
+/// Load a whole PBG3 archive in memory from its path, to share it between threads.
+#[cfg(feature = "std")]
+pub fn from_path_shared<P: AsRef<Path>>(path: P) -> io::Result<SharedPBG3> {
+    SharedPBG3::from_data(fs::read(path)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,6 +752,198 @@ mod tests {
         assert_eq!(pbg3.read_string(42).unwrap(), b"Hello world!");
     }
 
+    #[test]
+    fn bitwriter() {
+        let mut pbg3 = PBG3BitWriter::new(BitWriter::new(Vec::new()));
+        pbg3.write_u32(0x1234).unwrap();
+        pbg3.write_string(b"Hello world!").unwrap();
+        let data = pbg3.into_inner().unwrap();
+
+        let mut pbg3 = PBG3BitStream::new(BitStream::new(Cursor::new(data)));
+        assert_eq!(pbg3.read_u32().unwrap(), 0x1234);
+        assert_eq!(pbg3.read_string(42).unwrap(), b"Hello world!");
+    }
+
+    #[test]
+    fn write_archive() {
+        let mut writer = PBG3Writer::new(Vec::new());
+        writer.add_file("empty.txt", b"").unwrap();
+        writer.add_file("hello.txt", b"Hello world! Hello world!").unwrap();
+        let zeroes = vec![0u8; 0x10000];
+        writer.add_file_with_unknowns("zeroes.bin", &zeroes, 1, 2).unwrap();
+        let data = writer.finish().unwrap();
+
+        let mut pbg3 = PBG3::from_file(Cursor::new(data)).unwrap();
+        let mut files = pbg3.list_files().cloned().collect::<Vec<String>>();
+        files.sort();
+        assert_eq!(files, ["empty.txt", "hello.txt", "zeroes.bin"]);
+        assert_eq!(pbg3.get_file("empty.txt", true).unwrap(), b"");
+        assert_eq!(pbg3.get_file("hello.txt", true).unwrap(), b"Hello world! Hello world!");
+        assert_eq!(pbg3.get_file("zeroes.bin", true).unwrap(), zeroes);
+
+        let entries = pbg3.entries();
+        assert_eq!(entries.iter().map(|(name, _)| *name).collect::<Vec<_>>(), files);
+        let zeroes = pbg3.get_entry("zeroes.bin").unwrap();
+        assert_eq!((zeroes.unknown_1, zeroes.unknown_2, zeroes.size), (1, 2, 0x10000));
+        let (_, hello) = entries[1];
+        assert_eq!(hello.offset + hello.compressed_size, zeroes.offset);
+        assert!(hello.compressed_size > 0);
+    }
+
+    #[test]
+    fn streaming() {
+        let mut writer = PBG3Writer::new(Vec::new());
+        let data: Vec<u8> = (0..0x4000).map(|i| (i * 13 % 61) as u8).collect();
+        writer.add_file("data.bin", &data).unwrap();
+        writer.add_file("hello.txt", b"Hello world!").unwrap();
+        let archive = writer.finish().unwrap();
+
+        let mut pbg3 = PBG3::from_file(Cursor::new(archive.clone())).unwrap();
+        let mut reader = pbg3.open_file("data.bin", true).unwrap();
+        // Reading other files doesn’t disturb an already opened one.
+        assert_eq!(pbg3.get_file("hello.txt", true).unwrap(), b"Hello world!");
+        let mut streamed = vec![];
+        let mut buf = [0u8; 100];
+        loop {
+            let len = reader.read(&mut buf).unwrap();
+            if len == 0 {
+                break;
+            }
+            streamed.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(streamed, data);
+
+        // Corrupt the first compressed byte, which must be caught by the checksum.
+        let mut corrupted = archive.clone();
+        let offset = pbg3.get_entry("data.bin").unwrap().offset as usize;
+        corrupted[offset] ^= 0x01;
+        let mut pbg3 = PBG3::from_file(Cursor::new(corrupted)).unwrap();
+        let err = pbg3.get_file("data.bin", true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(pbg3.get_file("hello.txt", true).unwrap(), b"Hello world!");
+
+        // So must the last one, past the end of the decompressed data.
+        let entry = *pbg3.get_entry("data.bin").unwrap();
+        let mut corrupted = archive;
+        corrupted[(entry.offset + entry.compressed_size) as usize - 1] ^= 0x01;
+        let mut pbg3 = PBG3::from_file(Cursor::new(corrupted)).unwrap();
+        assert_eq!(pbg3.get_file("data.bin", false).unwrap(), data);
+        assert_eq!(pbg3.get_file("data.bin", true).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn shared() {
+        let mut writer = PBG3Writer::new(Vec::new());
+        let files: Vec<(String, Vec<u8>)> = (0..8)
+            .map(|i| (format!("file{}.bin", i), (0..0x1000).map(|j| (i * j % 251) as u8).collect()))
+            .collect();
+        for (name, data) in files.iter() {
+            writer.add_file(name, data).unwrap();
+        }
+        let pbg3 = SharedPBG3::from_data(writer.finish().unwrap()).unwrap();
+        assert_eq!(pbg3.entries().len(), 8);
+
+        let threads: Vec<_> = files.into_iter().map(|(name, data)| {
+            let pbg3 = pbg3.clone();
+            std::thread::spawn(move || assert_eq!(pbg3.get_file(&name, true).unwrap(), data))
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(pbg3.get_file("missing.bin", true).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn slice() {
+        let mut writer = PBG3Writer::new(Vec::new());
+        let data: Vec<u8> = (0..0x4000).map(|i| (i * 7 % 53) as u8).collect();
+        writer.add_file("hello.txt", b"Hello world! Hello world!").unwrap();
+        writer.add_file_with_unknowns("data.bin", &data, 1, 2).unwrap();
+        let mut archive = writer.finish().unwrap();
+
+        let pbg3 = SlicePBG3::from_slice(&archive).unwrap();
+        assert_eq!(pbg3.list_files().collect::<Vec<_>>(), ["data.bin", "hello.txt"]);
+        assert_eq!(pbg3.get_file("hello.txt", true).unwrap().unwrap(), b"Hello world! Hello world!");
+        assert_eq!(pbg3.get_file("data.bin", true).unwrap().unwrap(), data);
+        assert!(pbg3.get_file("missing.bin", true).is_none());
+
+        // Both readers agree on the file table.
+        let mut reference = PBG3::from_file(Cursor::new(archive.clone())).unwrap();
+        assert_eq!(pbg3.entries(), reference.entries());
+        assert_eq!(pbg3.get_file("data.bin", false).unwrap().unwrap(), reference.get_file("data.bin", false).unwrap());
+
+        let offset = pbg3.get_entry("data.bin").unwrap().offset as usize;
+        archive[offset] ^= 0x01;
+        let pbg3 = SlicePBG3::from_slice(&archive).unwrap();
+        let err = pbg3.get_file("data.bin", true).unwrap().unwrap_err();
+        assert_eq!((err.offset, err.reason), (offset, Reason::BadChecksum));
+
+        assert_eq!(SlicePBG3::from_slice(b"PBG4\0").err().unwrap().reason, Reason::BadMagic);
+        let err = SlicePBG3::from_slice(&archive[..archive.len() - 2]).err().unwrap();
+        assert_eq!(err.reason, Reason::Truncated);
+    }
+
+    /// Build an archive holding already compressed data as `data.bin`.
+    fn single_file_archive(compressed: &[u8], size: u32) -> Vec<u8> {
+        let checksum = compressed.iter().fold(0u32, |value, &c| value.wrapping_add(c as u32));
+
+        // A single byte for both header values puts the data at offset 7.
+        let mut archive = b"PBG3".to_vec();
+        let mut header = PBG3BitWriter::new(BitWriter::new(&mut archive));
+        header.write_u32(1).unwrap();
+        header.write_u32(7 + compressed.len() as u32).unwrap();
+        header.into_inner().unwrap();
+        assert_eq!(archive.len(), 7);
+        archive.extend_from_slice(compressed);
+        let mut table = PBG3BitWriter::new(BitWriter::new(&mut archive));
+        for value in [0, 0, checksum, 7, size].iter() {
+            table.write_u32(*value).unwrap();
+        }
+        table.write_string(b"data.bin").unwrap();
+        table.into_inner().unwrap();
+        archive
+    }
+
+    #[test]
+    fn readers_agree() {
+        // Literals "abc" followed by a match at offset zero, which reads the start of the
+        // dictionary instead of ending the stream.
+        let mut bitwriter = BitWriter::new(Vec::new());
+        for &byte in b"abc" {
+            bitwriter.write_bit(true).unwrap();
+            bitwriter.write(byte as usize, 8).unwrap();
+        }
+        bitwriter.write_bit(false).unwrap();
+        bitwriter.write(0, 13).unwrap();
+        bitwriter.write(0, 4).unwrap();
+        let compressed = bitwriter.into_inner().unwrap();
+        let archive = single_file_archive(&compressed, 6);
+
+        let mut pbg3 = PBG3::from_file(Cursor::new(archive.clone())).unwrap();
+        let data = pbg3.get_file("data.bin", true).unwrap();
+        assert_eq!(data, b"abc\0ab");
+        let shared = SharedPBG3::from_data(archive.clone()).unwrap();
+        assert_eq!(shared.get_file("data.bin", true).unwrap(), data);
+        let slice = SlicePBG3::from_slice(&archive).unwrap();
+        assert_eq!(slice.get_file("data.bin", true).unwrap().unwrap(), data);
+    }
+
+    #[test]
+    fn bogus_size() {
+        // A file claiming to be way bigger than its data fails once the data runs out, without
+        // reserving memory for its whole size beforehand.
+        let mut bitwriter = BitWriter::new(Vec::new());
+        lzss::compress(&mut bitwriter, b"Hello world!", 0x2000, 13, 4, 3).unwrap();
+        let archive = single_file_archive(&bitwriter.into_inner().unwrap(), u32::MAX);
+
+        let mut pbg3 = PBG3::from_file(Cursor::new(archive.clone())).unwrap();
+        assert!(pbg3.get_file("data.bin", false).is_err());
+        let shared = SharedPBG3::from_data(archive.clone()).unwrap();
+        assert!(shared.get_file("data.bin", false).is_err());
+        let slice = SlicePBG3::from_slice(&archive).unwrap();
+        assert_eq!(slice.get_file("data.bin", false).unwrap().unwrap_err().reason, Reason::Truncated);
+    }
+
     #[test]
     fn file_present() {
         let file = File::open("EoSD/MD.DAT").unwrap();
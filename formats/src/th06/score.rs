@@ -0,0 +1,491 @@
+//! score.dat high score format support.
+//!
+//! This file stores the high scores, clear flags, spell card history and
+//! practice scores of the player. Everything but its first byte is encrypted.
+
+use crate::error::{Error, FileKind, Reason, IResult, failure, at_offset};
+use nom::{
+    bytes::complete::take,
+    number::complete::{le_u8, le_u16, le_u32},
+    sequence::tuple,
+};
+use encoding_rs::SHIFT_JIS;
+use alloc::{string::String, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::io;
+
+/// Size of the header, the entries start right after it.
+#[cfg(feature = "std")]
+const HEADER_SIZE: usize = 20;
+
+/// Unknown entry, always present first.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Th6k {
+    /// TODO: find what that is.
+    pub unknown: u32,
+}
+
+/// A high score, for a given character and rank.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HighScore {
+    /// TODO: find what that is.
+    pub unknown: u32,
+
+    /// The score reached.
+    pub score: u32,
+
+    /// Character and shot type used.
+    pub character: u8,
+
+    /// Difficulty level, from easy to extra.
+    pub rank: u8,
+
+    /// Last stage reached, 7 meaning all clear.
+    pub stage: u8,
+
+    /// Name of the player, at most eight characters.
+    pub name: String,
+}
+
+/// A practice score, for a given character, rank and stage.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PracticeScore {
+    /// TODO: find what that is.
+    pub unknown: u32,
+
+    /// The score reached.
+    pub score: u32,
+
+    /// Character and shot type used.
+    pub character: u8,
+
+    /// Difficulty level, from easy to lunatic.
+    pub rank: u8,
+
+    /// Stage practiced.
+    pub stage: u8,
+}
+
+/// Clear flags of a given character.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Clear {
+    /// TODO: find what that is.
+    pub unknown: u32,
+
+    /// Clear flags for easy, normal, hard, lunatic and extra.
+    pub clears: [u8; 5],
+
+    /// Clear flags for easy, normal, hard, lunatic and extra, when
+    /// continues were used.
+    pub continue_clears: [u8; 5],
+
+    /// Character these flags are about.
+    pub character: u8,
+}
+
+/// History of a single spell card.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpellCard {
+    /// TODO: find what that is.
+    pub unknown: u32,
+
+    /// TODO: find what that is.
+    pub unknown2: u32,
+
+    /// Number of this spell card.
+    pub number: u16,
+
+    /// TODO: find what that is.
+    pub unknown3: u16,
+
+    /// Name of this spell card.
+    pub name: String,
+
+    /// Number of times this spell card has been seen.
+    pub seen: u16,
+
+    /// Number of times this spell card has been captured.
+    pub defeated: u16,
+}
+
+/// A single tagged entry of the score file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Entry {
+    /// TH6K entry.
+    Th6k(Th6k),
+
+    /// HSCR entry.
+    HighScore(HighScore),
+
+    /// PSCR entry.
+    PracticeScore(PracticeScore),
+
+    /// CLRD entry.
+    Clear(Clear),
+
+    /// CATK entry.
+    SpellCard(SpellCard),
+}
+
+impl Entry {
+    #[cfg(feature = "std")]
+    fn tag(&self) -> &'static [u8; 4] {
+        match self {
+            Entry::Th6k(_) => b"TH6K",
+            Entry::HighScore(_) => b"HSCR",
+            Entry::PracticeScore(_) => b"PSCR",
+            Entry::Clear(_) => b"CLRD",
+            Entry::SpellCard(_) => b"CATK",
+        }
+    }
+}
+
+/// Main struct of the score.dat format.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Score {
+    /// TODO: find what that is.
+    pub unknown1: u8,
+
+    /// TODO: find what that is.
+    pub key1: u8,
+
+    /// TODO: find what that is.
+    pub unknown2: u16,
+
+    /// TODO: find what that is.
+    pub key2: u8,
+
+    /// TODO: find what that is.
+    pub unknown3: u8,
+
+    /// TODO: find what that is.
+    pub unknown4: u32,
+
+    /// All entries, in file order.
+    pub entries: Vec<Entry>,
+}
+
+impl Default for Score {
+    fn default() -> Score {
+        Score {
+            unknown1: 0,
+            key1: 0,
+            unknown2: 16,
+            key2: 0,
+            unknown3: 0,
+            unknown4: 0,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl Score {
+    /// Parse a slice of bytes into a `Score` struct, decrypting it and
+    /// verifying its checksum.
+    pub fn from_slice(data: &[u8]) -> Result<Score, Error> {
+        let mut decrypted = data.to_vec();
+        decrypt(&mut decrypted);
+        // Offsets are the same in the decrypted data as in the original one.
+        match parse_score(&decrypted, true) {
+            Ok((_, score)) => Ok(score),
+            Err(err) => Err(Error::from_nom(FileKind::Score, &decrypted, err)),
+        }
+    }
+
+    /// Parse a slice of already decrypted bytes into a `Score` struct,
+    /// without verifying its checksum.
+    pub fn from_decrypted_slice(data: &[u8]) -> Result<Score, Error> {
+        match parse_score(data, false) {
+            Ok((_, score)) => Ok(score),
+            Err(err) => Err(Error::from_nom(FileKind::Score, data, err)),
+        }
+    }
+
+    /// Serialize this score file, computing its checksum and encrypting it
+    /// if asked to.
+    #[cfg(feature = "std")]
+    pub fn write<W: io::Write>(&self, file: &mut W, encrypt_data: bool) -> io::Result<()> {
+        let mut data = self.to_decrypted_vec()?;
+        if encrypt_data {
+            encrypt(&mut data);
+        }
+        file.write_all(&data)
+    }
+
+    /// Iterate over all high scores.
+    pub fn high_scores(&self) -> impl Iterator<Item = &HighScore> {
+        self.entries.iter().filter_map(|entry| match entry {
+            Entry::HighScore(high_score) => Some(high_score),
+            _ => None,
+        })
+    }
+
+    /// Iterate over all practice scores.
+    pub fn practice_scores(&self) -> impl Iterator<Item = &PracticeScore> {
+        self.entries.iter().filter_map(|entry| match entry {
+            Entry::PracticeScore(practice_score) => Some(practice_score),
+            _ => None,
+        })
+    }
+
+    /// Return the clear flags of this character, if any.
+    pub fn clear(&self, character: u8) -> Option<&Clear> {
+        self.entries.iter().filter_map(|entry| match entry {
+            Entry::Clear(clear) => Some(clear),
+            _ => None,
+        }).find(|clear| clear.character == character)
+    }
+
+    /// Iterate over the history of all spell cards.
+    pub fn spell_cards(&self) -> impl Iterator<Item = &SpellCard> {
+        self.entries.iter().filter_map(|entry| match entry {
+            Entry::SpellCard(spell_card) => Some(spell_card),
+            _ => None,
+        })
+    }
+
+    #[cfg(feature = "std")]
+    fn to_decrypted_vec(&self) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; HEADER_SIZE];
+        for entry in self.entries.iter() {
+            let mut payload = vec![];
+            match entry {
+                Entry::Th6k(Th6k { unknown }) => {
+                    payload.extend_from_slice(&unknown.to_le_bytes());
+                }
+                Entry::HighScore(high_score) => {
+                    payload.extend_from_slice(&high_score.unknown.to_le_bytes());
+                    payload.extend_from_slice(&high_score.score.to_le_bytes());
+                    payload.extend_from_slice(&[high_score.character, high_score.rank, high_score.stage]);
+                    write_string(&mut payload, high_score.name.as_bytes(), 8)?;
+                    payload.push(0);
+                }
+                Entry::PracticeScore(practice_score) => {
+                    payload.extend_from_slice(&practice_score.unknown.to_le_bytes());
+                    payload.extend_from_slice(&practice_score.score.to_le_bytes());
+                    payload.extend_from_slice(&[practice_score.character, practice_score.rank, practice_score.stage, 0]);
+                }
+                Entry::Clear(clear) => {
+                    payload.extend_from_slice(&clear.unknown.to_le_bytes());
+                    payload.extend_from_slice(&clear.clears);
+                    payload.extend_from_slice(&clear.continue_clears);
+                    payload.extend_from_slice(&[clear.character, 0]);
+                }
+                Entry::SpellCard(spell_card) => {
+                    payload.extend_from_slice(&spell_card.unknown.to_le_bytes());
+                    payload.extend_from_slice(&spell_card.unknown2.to_le_bytes());
+                    payload.extend_from_slice(&spell_card.number.to_le_bytes());
+                    payload.extend_from_slice(&spell_card.unknown3.to_le_bytes());
+                    payload.extend_from_slice(&[0; 4]);
+                    let (name, _encoding, _replaced) = SHIFT_JIS.encode(&spell_card.name);
+                    write_string(&mut payload, &name, 34)?;
+                    payload.extend_from_slice(&[0; 2]);
+                    payload.extend_from_slice(&spell_card.seen.to_le_bytes());
+                    payload.extend_from_slice(&spell_card.defeated.to_le_bytes());
+                }
+            }
+            let size = (payload.len() + 8) as u16;
+            data.extend_from_slice(entry.tag());
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&payload);
+        }
+
+        let size = data.len() as u32;
+        data[0] = self.unknown1;
+        data[1] = self.key1;
+        data[4..6].copy_from_slice(&self.unknown2.to_le_bytes());
+        data[6] = self.key2;
+        data[7] = self.unknown3;
+        data[8..12].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data[12..16].copy_from_slice(&self.unknown4.to_le_bytes());
+        data[16..20].copy_from_slice(&size.to_le_bytes());
+
+        let checksum = compute_checksum(&data);
+        data[2..4].copy_from_slice(&checksum.to_le_bytes());
+        Ok(data)
+    }
+}
+
+/// Decrypt a score file in place, its first byte is stored in clear.
+pub fn decrypt(data: &mut [u8]) {
+    let mut key = 0u8;
+    for byte in data.iter_mut().skip(1) {
+        key = key.rotate_left(3);
+        *byte ^= key;
+        key = key.wrapping_add(*byte);
+    }
+}
+
+/// Encrypt a score file in place, its first byte is stored in clear.
+pub fn encrypt(data: &mut [u8]) {
+    let mut key = 0u8;
+    for byte in data.iter_mut().skip(1) {
+        key = key.rotate_left(3);
+        let clear = *byte;
+        *byte ^= key;
+        key = key.wrapping_add(clear);
+    }
+}
+
+/// Compute the checksum of a decrypted score file.
+pub fn compute_checksum(data: &[u8]) -> u16 {
+    data.iter().skip(4).fold(0u16, |sum, &c| sum.wrapping_add(c as u16))
+}
+
+#[cfg(feature = "std")]
+fn write_string(data: &mut Vec<u8>, string: &[u8], size: usize) -> io::Result<()> {
+    if string.len() > size {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("String too long for score.dat: {}", String::from_utf8_lossy(string))));
+    }
+    data.extend_from_slice(string);
+    data.resize(data.len() + size - string.len(), 0);
+    Ok(())
+}
+
+fn parse_name(i: &[u8]) -> IResult<&[u8], String> {
+    let (i, data) = take(8usize)(i)?;
+    let data = data.split(|c| *c == b'\0').next().unwrap();
+    Ok((i, String::from_utf8_lossy(data).into_owned()))
+}
+
+/// Parse the SHIFT_JIS name of a spell card, in a field of 34 bytes.
+fn parse_spellcard_name(i: &[u8]) -> IResult<&[u8], String> {
+    let (i, data) = take(34usize)(i)?;
+    let data = data.split(|c| *c == b'\0').next().unwrap();
+    let (string, _encoding, _replaced) = SHIFT_JIS.decode(data);
+    Ok((i, string.into_owned()))
+}
+
+fn parse_entry(input: &[u8]) -> IResult<&[u8], Entry> {
+    let (i, (tag, size, size2)) = tuple((take(4usize), le_u16, le_u16))(input)?;
+    if size != size2 {
+        return failure(input, Reason::SizeMismatch { expected: size as usize, found: size2 as usize });
+    }
+    if size < 8 {
+        return failure(input, Reason::InvalidValue);
+    }
+    let (i, payload) = take(size as usize - 8)(i)?;
+    let (_, entry) = match tag {
+        b"TH6K" => {
+            let (p, unknown) = le_u32(payload)?;
+            (p, Entry::Th6k(Th6k { unknown }))
+        }
+        b"HSCR" => {
+            let (p, (unknown, score, character, rank, stage, name)) =
+                tuple((le_u32, le_u32, le_u8, le_u8, le_u8, parse_name))(payload)?;
+            (p, Entry::HighScore(HighScore { unknown, score, character, rank, stage, name }))
+        }
+        b"PSCR" => {
+            let (p, (unknown, score, character, rank, stage)) =
+                tuple((le_u32, le_u32, le_u8, le_u8, le_u8))(payload)?;
+            (p, Entry::PracticeScore(PracticeScore { unknown, score, character, rank, stage }))
+        }
+        b"CLRD" => {
+            let (p, (unknown, clears, continue_clears, character)) =
+                tuple((le_u32, take(5usize), take(5usize), le_u8))(payload)?;
+            let mut clear = Clear { unknown, clears: [0; 5], continue_clears: [0; 5], character };
+            clear.clears.copy_from_slice(clears);
+            clear.continue_clears.copy_from_slice(continue_clears);
+            (p, Entry::Clear(clear))
+        }
+        b"CATK" => {
+            let (p, (unknown, unknown2, number, unknown3, _, name, _, seen, defeated)) =
+                tuple((le_u32, le_u32, le_u16, le_u16, le_u32, parse_spellcard_name, le_u16, le_u16, le_u16))(payload)?;
+            (p, Entry::SpellCard(SpellCard { unknown, unknown2, number, unknown3, name, seen, defeated }))
+        }
+        _ => return failure(input, Reason::BadMagic),
+    };
+    Ok((i, entry))
+}
+
+fn parse_score(input: &[u8], verify: bool) -> IResult<&[u8], Score> {
+    let (_, (unknown1, key1, checksum, unknown2, key2, unknown3, offset, unknown4, _size)) =
+        tuple((le_u8, le_u8, le_u16, le_u16, le_u8, le_u8, le_u32, le_u32, le_u32))(input)?;
+
+    if verify && checksum != compute_checksum(input) {
+        return failure(&input[2..], Reason::BadChecksum);
+    }
+
+    let mut i = at_offset(input, offset)?;
+    let mut entries = vec![];
+    while !i.is_empty() {
+        let (i2, entry) = parse_entry(i)?;
+        entries.push(entry);
+        i = i2;
+    }
+
+    let score = Score {
+        unknown1,
+        key1,
+        unknown2,
+        key2,
+        unknown3,
+        unknown4,
+        entries,
+    };
+    Ok((i, score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let mut score = Score::default();
+        score.entries.push(Entry::Th6k(Th6k { unknown: 0x10 }));
+        score.entries.push(Entry::HighScore(HighScore {
+            unknown: 0,
+            score: 1000000,
+            character: 1,
+            rank: 2,
+            stage: 7,
+            name: String::from("Nobody"),
+        }));
+        score.entries.push(Entry::PracticeScore(PracticeScore { unknown: 0, score: 4242, character: 3, rank: 0, stage: 4 }));
+        score.entries.push(Entry::Clear(Clear { unknown: 0, clears: [1, 1, 0, 0, 0], continue_clears: [1, 0, 0, 0, 0], character: 0 }));
+        score.entries.push(Entry::SpellCard(SpellCard {
+            unknown: 0,
+            unknown2: 0,
+            number: 1,
+            unknown3: 0,
+            name: String::from("月符「ムーンライトレイ」"),
+            seen: 3,
+            defeated: 1,
+        }));
+
+        let mut data = vec![];
+        score.write(&mut data, true).unwrap();
+        let parsed = Score::from_slice(&data).unwrap();
+        assert_eq!(parsed.entries, score.entries);
+        assert_eq!(parsed.high_scores().next().unwrap().name, "Nobody");
+        assert_eq!(parsed.clear(0).unwrap().clears, [1, 1, 0, 0, 0]);
+        assert!(parsed.clear(1).is_none());
+        assert_eq!(parsed.spell_cards().count(), 1);
+
+        let mut data2 = vec![];
+        parsed.write(&mut data2, true).unwrap();
+        assert_eq!(data, data2);
+    }
+
+    #[test]
+    fn checksum() {
+        let mut score = Score::default();
+        score.entries.push(Entry::Th6k(Th6k { unknown: 0x10 }));
+        let mut data = vec![];
+        score.write(&mut data, false).unwrap();
+        data[HEADER_SIZE + 8] ^= 0xff;
+        let parsed = Score::from_decrypted_slice(&data).unwrap();
+        assert_eq!(parsed.entries, [Entry::Th6k(Th6k { unknown: 0xef })]);
+        encrypt(&mut data);
+        Score::from_slice(&data).unwrap_err();
+    }
+}
@@ -0,0 +1,272 @@
+//! SHT player shot format support.
+
+use crate::error::{Error, FileKind, IResult, at_offset};
+use nom::{
+    number::complete::{le_u8, le_u16, le_u32, le_i16, le_f32},
+    sequence::tuple,
+    multi::count,
+};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::ops::Bound;
+
+/// A single bullet fired by the player.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Shot {
+    /// Number of frames between two shots.
+    pub interval: u16,
+
+    /// Frame of the interval at which this shot is fired.
+    pub delay: u16,
+
+    /// Position relative to the player.
+    pub pos: (f32, f32),
+
+    /// Size of the hitbox.
+    pub hitbox: (f32, f32),
+
+    /// Angle of the bullet, in radians.
+    pub angle: f32,
+
+    /// Initial speed of the bullet.
+    pub speed: f32,
+
+    /// Damage dealt to enemies.
+    pub damage: u16,
+
+    /// Orb from which this shot is fired, 0 being the player itself.
+    pub orb: u8,
+
+    /// Behaviour of the bullet, e.g. homing or laser.
+    pub type_: u8,
+
+    /// Sprite of the bullet in the player’s ANM.
+    pub sprite: i16,
+
+    /// Sound played when firing this shot.
+    pub sound: i16,
+
+    /// TODO: find what that is.
+    pub unknown1: u32,
+
+    /// TODO: find what that is.
+    pub unknown2: u32,
+
+    /// TODO: find what that is.
+    pub unknown3: u32,
+
+    /// TODO: find what that is.
+    pub unknown4: u32,
+}
+
+/// Main struct of the SHT player shot format.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sht {
+    /// TODO: find what that is.
+    pub unknown1: i16,
+
+    /// Number of bombs available at the start.
+    pub bombs: f32,
+
+    /// TODO: find what that is.
+    pub unknown2: u32,
+
+    /// Radius of the player’s hitbox.
+    pub hitbox: f32,
+
+    /// Radius in which bullets get grazed.
+    pub graze_hitbox: f32,
+
+    /// Speed at which items get collected automatically.
+    pub autocollection_speed: f32,
+
+    /// Radius in which items get collected.
+    pub item_hitbox: f32,
+
+    /// Percentage of cherry lost on death.
+    pub percentage_of_cherry_loss_on_die: f32,
+
+    /// Height above which all items get collected.
+    pub point_of_collection: f32,
+
+    /// Horizontal and vertical speed.
+    pub horizontal_vertical_speed: f32,
+
+    /// Horizontal and vertical speed when focused.
+    pub horizontal_vertical_focused_speed: f32,
+
+    /// Diagonal speed.
+    pub diagonal_speed: f32,
+
+    /// Diagonal speed when focused.
+    pub diagonal_focused_speed: f32,
+
+    /// Shots fired, indexed by the power from which the next level is used instead.
+    pub shots: BTreeMap<u32, Vec<Shot>>,
+}
+
+impl Sht {
+    /// Parse a slice of bytes into a `Sht` struct.
+    pub fn from_slice(data: &[u8]) -> Result<Sht, Error> {
+        match parse_sht(data) {
+            Ok((_, sht)) => Ok(sht),
+            Err(err) => Err(Error::from_nom(FileKind::Sht, data, err)),
+        }
+    }
+
+    /// Return the shots fired at this power level, which are those of the first level whose power
+    /// is strictly above it.
+    pub fn shots_for_power(&self, power: u32) -> &[Shot] {
+        self.shots.range((Bound::Excluded(power), Bound::Unbounded)).next().map(|(_, shots)| &shots[..]).unwrap_or(&[])
+    }
+}
+
+fn parse_shot(i: &[u8]) -> IResult<&[u8], Option<Shot>> {
+    let (i, (interval, delay)) = tuple((le_u16, le_u16))(i)?;
+    if interval == 0xffff && delay == 0xffff {
+        return Ok((i, None));
+    }
+    let (i, (x, y, hitbox_x, hitbox_y, angle, speed)) = tuple((le_f32, le_f32, le_f32, le_f32, le_f32, le_f32))(i)?;
+    let (i, (damage, orb, type_, sprite, sound)) = tuple((le_u16, le_u8, le_u8, le_i16, le_i16))(i)?;
+    let (i, (unknown1, unknown2, unknown3, unknown4)) = tuple((le_u32, le_u32, le_u32, le_u32))(i)?;
+    let shot = Shot {
+        interval,
+        delay,
+        pos: (x, y),
+        hitbox: (hitbox_x, hitbox_y),
+        angle,
+        speed,
+        damage,
+        orb,
+        type_,
+        sprite,
+        sound,
+        unknown1,
+        unknown2,
+        unknown3,
+        unknown4,
+    };
+    Ok((i, Some(shot)))
+}
+
+fn parse_shots(mut i: &[u8]) -> IResult<&[u8], Vec<Shot>> {
+    let mut shots = vec![];
+    loop {
+        let (i2, shot) = parse_shot(i)?;
+        i = i2;
+        match shot {
+            Some(shot) => shots.push(shot),
+            None => break,
+        }
+    }
+    Ok((i, shots))
+}
+
+fn parse_sht(input: &[u8]) -> IResult<&[u8], Sht> {
+    let (i, (unknown1, level_count, bombs, unknown2)) = tuple((le_i16, le_u16, le_f32, le_u32))(input)?;
+    let (i, (hitbox, graze_hitbox, autocollection_speed, item_hitbox, percentage_of_cherry_loss_on_die)) =
+        tuple((le_f32, le_f32, le_f32, le_f32, le_f32))(i)?;
+    let (i, (point_of_collection, horizontal_vertical_speed, horizontal_vertical_focused_speed, diagonal_speed, diagonal_focused_speed)) =
+        tuple((le_f32, le_f32, le_f32, le_f32, le_f32))(i)?;
+    let (_, levels) = count(tuple((le_u32, le_u32)), level_count as usize)(i)?;
+
+    let mut shots = BTreeMap::new();
+    for (offset, power) in levels {
+        let (_, level) = parse_shots(at_offset(input, offset)?)?;
+        shots.insert(power, level);
+    }
+
+    let sht = Sht {
+        unknown1,
+        bombs,
+        unknown2,
+        hitbox,
+        graze_hitbox,
+        autocollection_speed,
+        item_hitbox,
+        percentage_of_cherry_loss_on_die,
+        point_of_collection,
+        horizontal_vertical_speed,
+        horizontal_vertical_focused_speed,
+        diagonal_speed,
+        diagonal_focused_speed,
+        shots,
+    };
+    Ok((b"", sht))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_f32s(buf: &mut Vec<u8>, values: &[f32]) {
+        for value in values {
+            buf.extend(&value.to_le_bytes());
+        }
+    }
+
+    fn sample_sht() -> Vec<u8> {
+        let mut buf = vec![];
+        buf.extend(&0i16.to_le_bytes());
+        buf.extend(&2u16.to_le_bytes());
+        push_f32s(&mut buf, &[3.]);
+        buf.extend(&0u32.to_le_bytes());
+        push_f32s(&mut buf, &[2., 24., 8., 16., 0.5, 128., 4., 2., 4. / 2f32.sqrt(), 2. / 2f32.sqrt()]);
+        // Levels, both pointing to the same list of shots.
+        buf.extend(&68u32.to_le_bytes());
+        buf.extend(&0u32.to_le_bytes());
+        buf.extend(&68u32.to_le_bytes());
+        buf.extend(&8u32.to_le_bytes());
+        assert_eq!(buf.len(), 68);
+        // A single shot, fired every 5 frames.
+        buf.extend(&5u16.to_le_bytes());
+        buf.extend(&0u16.to_le_bytes());
+        push_f32s(&mut buf, &[0., -32., 12., 12., -1.5, 10.]);
+        buf.extend(&48u16.to_le_bytes());
+        buf.extend(&[0, 0]);
+        buf.extend(&64i16.to_le_bytes());
+        buf.extend(&(-1i16).to_le_bytes());
+        buf.extend(&[0; 16]);
+        buf.extend(&[0xff; 4]);
+        buf
+    }
+
+    #[test]
+    fn sht() {
+        let sht = Sht::from_slice(&sample_sht()).unwrap();
+        assert_eq!(sht.bombs, 3.);
+        assert_eq!(sht.hitbox, 2.);
+        assert_eq!(sht.point_of_collection, 128.);
+        assert_eq!(sht.diagonal_focused_speed, 2. / 2f32.sqrt());
+        assert_eq!(sht.shots.len(), 2);
+        let shots = sht.shots_for_power(7);
+        assert_eq!(shots.len(), 1);
+        assert_eq!(shots[0].interval, 5);
+        assert_eq!(shots[0].pos, (0., -32.));
+        assert_eq!(shots[0].damage, 48);
+        assert_eq!(shots[0].sprite, 64);
+        assert_eq!(shots[0].sound, -1);
+        assert!(sht.shots_for_power(8).is_empty());
+    }
+
+    #[test]
+    fn shots_for_power() {
+        let mut sht = Sht::from_slice(&sample_sht()).unwrap();
+        let shot = sht.shots[&0][0].clone();
+        sht.shots = [8, 16, 999].iter().map(|&power| {
+            let shot = Shot { interval: power as u16, ..shot.clone() };
+            (power, vec![shot])
+        }).collect();
+
+        // Each level is used up to its power, excluded, like in pytouhou.
+        let interval = |power| sht.shots_for_power(power)[0].interval;
+        assert_eq!(interval(0), 8);
+        assert_eq!(interval(7), 8);
+        assert_eq!(interval(8), 16);
+        assert_eq!(interval(15), 16);
+        assert_eq!(interval(16), 999);
+        assert_eq!(interval(128), 999);
+        assert!(sht.shots_for_power(999).is_empty());
+    }
+}
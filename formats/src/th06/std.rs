@@ -1,19 +1,21 @@
 //! STD background format support.
 
+use crate::error::{Error, FileKind, Reason, IResult, failure, at_offset, until_terminator};
 use nom::{
-    IResult,
-    bytes::complete::tag,
+    bytes::complete::{tag, take},
     number::complete::{le_u8, le_u16, le_u32, le_i32, le_f32},
     sequence::tuple,
     combinator::map,
-    multi::{many0, count},
-    error::ErrorKind,
-    Err,
+    multi::count,
 };
 use encoding_rs::SHIFT_JIS;
+use alloc::{string::String, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::io;
 
 /// A float position in the 3D space.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Position {
     /// X component.
     pub x: f32,
@@ -34,7 +36,8 @@ struct Box3D {
 }
 
 /// A 2D box around something.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Box2D {
     /// Width.
     pub width: f32,
@@ -44,7 +47,8 @@ pub struct Box2D {
 }
 
 /// A quad in the 3D space.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Quad {
     /// The anm script to run for this quad.
     pub anm_script: u16,
@@ -57,7 +61,8 @@ pub struct Quad {
 }
 
 /// A model formed of multiple quads in space.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Model {
     /// TODO: find what that is.
     pub unknown: u16,
@@ -70,7 +75,8 @@ pub struct Model {
 }
 
 /// An instance of a model.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instance {
     /// The instance identifier.
     pub id: u16,
@@ -80,7 +86,8 @@ pub struct Instance {
 }
 
 /// A single instruction, part of a `Script`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Call {
     /// Time at which this instruction will be called.
     pub time: u32,
@@ -91,14 +98,16 @@ pub struct Call {
 
 /// Parse a SHIFT_JIS byte string of length 128 into a String.
 #[allow(non_snake_case)]
-pub fn le_String(i: &[u8]) -> IResult<&[u8], String> {
-    let data = i.splitn(2, |c| *c == b'\0').nth(0).unwrap();
+pub(crate) fn le_String(i: &[u8]) -> IResult<&[u8], String> {
+    let (i, data) = take(128usize)(i)?;
+    let data = data.split(|c| *c == b'\0').next().unwrap();
     let (string, _encoding, _replaced) = SHIFT_JIS.decode(data);
-    Ok((&i[128..], string.into_owned()))
+    Ok((i, string.into_owned()))
 }
 
 /// Main struct of the STD stage format.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Stage {
     /// The name of the stage.
     pub name: String,
@@ -119,8 +128,110 @@ pub struct Stage {
 
 impl Stage {
     /// Parse a slice of bytes into an `Stage` struct.
-    pub fn from_slice(data: &[u8]) -> IResult<&[u8], Stage> {
-        parse_stage(data)
+    pub fn from_slice(data: &[u8]) -> Result<Stage, Error> {
+        match parse_stage(data) {
+            Ok((_, stage)) => Ok(stage),
+            Err(err) => Err(Error::from_nom(FileKind::Std, data, err)),
+        }
+    }
+
+    /// Serialize this `Stage` into a file.
+    #[cfg(feature = "std")]
+    pub fn write<W: io::Write>(&self, file: &mut W) -> io::Result<()> {
+        file.write_all(&write_stage(self)?)
+    }
+}
+
+impl Model {
+    /// Create a model from its quads, computing its bounding box from their positions and size
+    /// overrides.
+    ///
+    /// Quads without a size override don’t extend the bounding box past their position, set it
+    /// manually if they should.
+    pub fn new(unknown: u16, quads: Vec<Quad>) -> Model {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for Quad { pos, size_override, .. } in quads.iter() {
+            let corners = [
+                [pos.x, pos.y, pos.z],
+                [pos.x + size_override.width, pos.y + size_override.height, pos.z],
+            ];
+            for corner in corners.iter() {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(corner[axis]);
+                    max[axis] = max[axis].max(corner[axis]);
+                }
+            }
+        }
+        let bounding_box = if quads.is_empty() {
+            [0.; 6]
+        } else {
+            [min[0], min[1], min[2], max[0] - min[0], max[1] - min[1], max[2] - min[2]]
+        };
+        Model {
+            unknown,
+            bounding_box,
+            quads,
+        }
+    }
+}
+
+/// Helper to generate a `Stage` from code, instead of writing all of its fields by hand.
+#[derive(Debug, Clone)]
+pub struct StageBuilder {
+    stage: Stage,
+}
+
+impl StageBuilder {
+    /// Start a new empty stage with this name.
+    pub fn new(name: &str) -> StageBuilder {
+        StageBuilder {
+            stage: Stage {
+                name: String::from(name),
+                musics: vec![],
+                models: vec![],
+                instances: vec![],
+                script: vec![],
+            },
+        }
+    }
+
+    /// Append a background music to this stage.
+    pub fn music(mut self, name: &str, path: &str) -> StageBuilder {
+        self.stage.musics.push(Some((String::from(name), String::from(path))));
+        self
+    }
+
+    /// Append an empty music slot to this stage.
+    pub fn no_music(mut self) -> StageBuilder {
+        self.stage.musics.push(None);
+        self
+    }
+
+    /// Append a model to this stage, its id being the number of models added before it.
+    pub fn model(mut self, model: Model) -> StageBuilder {
+        self.stage.models.push(model);
+        self
+    }
+
+    /// Place an instance of the model `id` at this position.
+    pub fn instance(mut self, id: u16, x: f32, y: f32, z: f32) -> StageBuilder {
+        self.stage.instances.push(Instance {
+            id,
+            pos: Position { x, y, z },
+        });
+        self
+    }
+
+    /// Append an instruction to the script, to be run at this time.
+    pub fn call(mut self, time: u32, instr: Instruction) -> StageBuilder {
+        self.stage.script.push(Call { time, instr });
+        self
+    }
+
+    /// Return the generated `Stage`.
+    pub fn build(self) -> Stage {
+        self.stage
     }
 }
 
@@ -128,14 +239,27 @@ macro_rules! declare_stage_instructions {
     ($($opcode:tt => fn $name:ident($($arg:ident: $arg_type:ident),*)),*,) => {
         /// Available instructions in an `Stage`.
         #[allow(missing_docs)]
-        #[derive(Debug, Clone, Copy)]
+        #[derive(Debug, Clone, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum Instruction {
             $(
-                $name($($arg_type),*)
-            ),*
+                $name($($arg_type),*),
+            )*
+
+            /// An instruction whose opcode isn’t known, kept as is.
+            ///
+            /// Unlike in the other formats it can’t be called `Unknown`, opcode 5 already is.
+            UnknownOpcode {
+                /// Its opcode.
+                opcode: u16,
+
+                /// Its raw arguments.
+                args: Vec<u8>,
+            },
         }
 
-        fn parse_instruction_args(input: &[u8], opcode: u16) -> IResult<&[u8], Instruction> {
+        /// Parse the arguments of this opcode, `size` only being used for unknown ones.
+        fn parse_instruction_args(input: &[u8], opcode: u16, size: usize) -> IResult<&[u8], Instruction> {
             let mut i = &input[..];
             let instr = match opcode {
                 $(
@@ -147,10 +271,40 @@ macro_rules! declare_stage_instructions {
                         Instruction::$name($($arg),*)
                     }
                 )*
-                _ => unreachable!()
+                _ => {
+                    let (i2, args) = take(size)(i)?;
+                    i = i2;
+                    Instruction::UnknownOpcode { opcode, args: args.to_vec() }
+                }
             };
             Ok((i, instr))
         }
+
+        impl Instruction {
+            #[cfg(feature = "std")]
+            pub(crate) fn opcode(&self) -> u16 {
+                match self {
+                    $(
+                        Instruction::$name(..) => $opcode,
+                    )*
+                    Instruction::UnknownOpcode { opcode, .. } => *opcode,
+                }
+            }
+        }
+
+        #[cfg(feature = "std")]
+        fn write_instruction_args(data: &mut Vec<u8>, instr: &Instruction) {
+            match instr {
+                $(
+                    Instruction::$name($($arg),*) => {
+                        $(
+                            data.extend_from_slice(&$arg.to_le_bytes());
+                        )*
+                    }
+                )*
+                Instruction::UnknownOpcode { args, .. } => data.extend_from_slice(args),
+            }
+        }
     };
 }
 
@@ -158,29 +312,30 @@ declare_stage_instructions!{
     0 => fn SetViewpos(x: f32, y: f32, z: f32),
     1 => fn SetFog(r: u8, g: u8, b: u8, a: u8, near: f32, far: f32),
     2 => fn SetViewpos2(x: f32, y: f32, z: f32),
-    3 => fn StartInterpolatingViewpos2(frame: u32, _unused: i32, _unused: i32),
-    4 => fn StartInterpolatingFog(frame: u32, _unused: i32, _unused: i32),
-    5 => fn Unknown(_unused: i32, _unused: i32, _unused: i32),
+    3 => fn StartInterpolatingViewpos2(frame: u32, _unused1: i32, _unused2: i32),
+    4 => fn StartInterpolatingFog(frame: u32, _unused1: i32, _unused2: i32),
+    5 => fn Unknown(_unused1: i32, _unused2: i32, _unused3: i32),
 }
 
-fn parse_quad(i: &[u8]) -> IResult<&[u8], Quad> {
+fn parse_quad(i: &[u8]) -> IResult<&[u8], Option<Quad>> {
     let (i, (unk1, size)) = tuple((le_u16, le_u16))(i)?;
     if unk1 == 0xffff {
-        return Err(Err::Error(nom::error::Error::new(i, ErrorKind::Eof)));
+        return Ok((i, None));
+    }
+    if size != 0x1c {
+        return failure(i, Reason::SizeMismatch { expected: size as usize, found: 0x1c });
     }
-    // TODO: replace this assert with a custom error.
-    assert_eq!(size, 0x1c);
     let (i, (anm_script, _, x, y, z, width, height)) = tuple((le_u16, tag(b"\0\0"), le_f32, le_f32, le_f32, le_f32, le_f32))(i)?;
     let quad = Quad {
         anm_script,
         pos: Position { x, y, z },
         size_override: Box2D { width, height },
     };
-    Ok((i, quad))
+    Ok((i, Some(quad)))
 }
 
 fn parse_model(i: &[u8]) -> IResult<&[u8], Model> {
-    let (i, (_id, unknown, x, y, z, width, height, depth, quads)) = tuple((le_u16, le_u16, le_f32, le_f32, le_f32, le_f32, le_f32, le_f32, many0(parse_quad)))(i)?;
+    let (i, (_id, unknown, x, y, z, width, height, depth, quads)) = tuple((le_u16, le_u16, le_f32, le_f32, le_f32, le_f32, le_f32, le_f32, until_terminator(parse_quad)))(i)?;
     let bounding_box = [x, y, z, width, height, depth];
     let model = Model {
         unknown,
@@ -190,31 +345,36 @@ fn parse_model(i: &[u8]) -> IResult<&[u8], Model> {
     Ok((i, model))
 }
 
-fn parse_instance(i: &[u8]) -> IResult<&[u8], Instance> {
+fn parse_instance(i: &[u8]) -> IResult<&[u8], Option<Instance>> {
     let (i, (id, unknown, x, y, z)) = tuple((le_u16, le_u16, le_f32, le_f32, le_f32))(i)?;
     if id == 0xffff && unknown == 0xffff {
-        return Err(Err::Error(nom::error::Error::new(i, ErrorKind::Eof)));
+        return Ok((i, None));
+    }
+    if unknown != 0x100 {
+        return failure(i, Reason::InvalidValue);
     }
-    // TODO: replace this assert with a custom error.
-    assert_eq!(unknown, 0x100);
     let instance = Instance {
         id,
         pos: Position { x, y, z },
     };
-    Ok((i, instance))
+    Ok((i, Some(instance)))
 }
 
-fn parse_instruction(i: &[u8]) -> IResult<&[u8], Call> {
+fn parse_instruction(i: &[u8]) -> IResult<&[u8], Option<Call>> {
     let (i, (time, opcode, size)) = tuple((le_u32, le_u16, le_u16))(i)?;
     if time == 0xffffffff && opcode == 0xffff && size == 0xffff {
-        return Err(Err::Error(nom::error::Error::new(i, ErrorKind::Eof)));
+        // The terminator is padded to 20 bytes.
+        let (i, _) = take(12usize)(i)?;
+        return Ok((i, None));
+    }
+    let args = i;
+    let (i, instr) = parse_instruction_args(args, opcode, size as usize)?;
+    let found = args.len() - i.len();
+    if found != size as usize {
+        return failure(args, Reason::SizeMismatch { expected: size as usize, found });
     }
-    // TODO: replace this assert with a custom error.
-    assert_eq!(size, 12);
-    let (i, instr) = parse_instruction_args(i, opcode)?;
-    println!("{} {:?}", time, instr);
     let call = Call { time, instr };
-    Ok((i, call))
+    Ok((i, Some(call)))
 }
 
 fn parse_stage(input: &[u8]) -> IResult<&[u8], Stage> {
@@ -232,12 +392,12 @@ fn parse_stage(input: &[u8]) -> IResult<&[u8], Stage> {
 
     let mut models = vec![];
     for offset in offsets {
-        let (_, model) = parse_model(&input[offset as usize..])?;
+        let (_, model) = parse_model(at_offset(input, offset)?)?;
         models.push(model);
     }
 
-    let (_, instances) = many0(parse_instance)(&input[object_instances_offset as usize..])?;
-    let (_, script) = many0(parse_instruction)(&input[script_offset as usize..])?;
+    let (_, instances) = until_terminator(parse_instance)(at_offset(input, object_instances_offset)?)?;
+    let (_, script) = until_terminator(parse_instruction)(at_offset(input, script_offset)?)?;
 
     let stage = Stage {
         name,
@@ -249,6 +409,103 @@ fn parse_stage(input: &[u8]) -> IResult<&[u8], Stage> {
     Ok((b"", stage))
 }
 
+/// Maximum number of background musics in a stage.
+#[cfg(feature = "std")]
+const MUSIC_COUNT: usize = 4;
+
+/// Write a string in a fixed-size 128 bytes field, using the SHIFT_JIS encoding.
+#[cfg(feature = "std")]
+fn write_string(data: &mut Vec<u8>, string: &str) -> io::Result<()> {
+    let (encoded, _encoding, replaced) = SHIFT_JIS.encode(string);
+    if replaced {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("“{}” can’t be encoded as SHIFT_JIS", string)));
+    }
+    if encoded.len() > 128 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("“{}” is longer than 128 bytes", string)));
+    }
+    data.extend_from_slice(&encoded);
+    data.resize(data.len() + 128 - encoded.len(), 0);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn write_stage(stage: &Stage) -> io::Result<Vec<u8>> {
+    if stage.musics.len() > MUSIC_COUNT {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("A stage can’t have more than {} musics", MUSIC_COUNT)));
+    }
+    let num_faces: usize = stage.models.iter().map(|model| model.quads.len()).sum();
+
+    let mut data = vec![];
+    data.extend_from_slice(&(stage.models.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(num_faces as u16).to_le_bytes());
+    // Instances and script offsets, filled once known.
+    data.extend_from_slice(&[0; 12]);
+    write_string(&mut data, &stage.name)?;
+
+    // Empty music slots are named with a single space.
+    let musics: Vec<_> = (0..MUSIC_COUNT).map(|i| match stage.musics.get(i) {
+        Some(Some((name, path))) => (&name[..], &path[..]),
+        _ => (" ", ""),
+    }).collect();
+    for (name, _) in musics.iter() {
+        write_string(&mut data, name)?;
+    }
+    for (_, path) in musics.iter() {
+        write_string(&mut data, path)?;
+    }
+
+    let model_offsets_offset = data.len();
+    data.resize(data.len() + 4 * stage.models.len(), 0);
+
+    for (id, model) in stage.models.iter().enumerate() {
+        let offset = data.len() as u32;
+        let start = model_offsets_offset + 4 * id;
+        data[start..start + 4].copy_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&(id as u16).to_le_bytes());
+        data.extend_from_slice(&model.unknown.to_le_bytes());
+        for value in model.bounding_box.iter() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for quad in model.quads.iter() {
+            data.extend_from_slice(&0u16.to_le_bytes());
+            data.extend_from_slice(&0x1cu16.to_le_bytes());
+            data.extend_from_slice(&quad.anm_script.to_le_bytes());
+            data.extend_from_slice(&[0; 2]);
+            for value in [quad.pos.x, quad.pos.y, quad.pos.z, quad.size_override.width, quad.size_override.height] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        data.extend_from_slice(&0xffffu16.to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+    }
+
+    let object_instances_offset = data.len() as u32;
+    for instance in stage.instances.iter() {
+        data.extend_from_slice(&instance.id.to_le_bytes());
+        data.extend_from_slice(&0x100u16.to_le_bytes());
+        for value in [instance.pos.x, instance.pos.y, instance.pos.z] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    data.extend_from_slice(&[0xff; 16]);
+
+    let script_offset = data.len() as u32;
+    let mut args = vec![];
+    for Call { time, instr } in stage.script.iter() {
+        args.clear();
+        write_instruction_args(&mut args, instr);
+        data.extend_from_slice(&time.to_le_bytes());
+        data.extend_from_slice(&instr.opcode().to_le_bytes());
+        data.extend_from_slice(&(args.len() as u16).to_le_bytes());
+        data.extend_from_slice(&args);
+    }
+    data.extend_from_slice(&[0xff; 20]);
+
+    data[4..8].copy_from_slice(&object_instances_offset.to_le_bytes());
+    data[8..12].copy_from_slice(&script_offset.to_le_bytes());
+    Ok(data)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -261,11 +518,103 @@ mod tests {
         let mut file = io::BufReader::new(file);
         let mut buf = vec![];
         file.read_to_end(&mut buf).unwrap();
-        let (_, stage) = Stage::from_slice(&buf).unwrap();
+        let stage = Stage::from_slice(&buf).unwrap();
         assert_eq!(stage.name, "夢幻夜行絵巻　～ Mystic Flier");
         assert_eq!(stage.musics.len(), 4);
         assert_eq!(stage.models.len(), 13);
         assert_eq!(stage.instances.len(), 90);
         assert_eq!(stage.script.len(), 21);
+
+        let mut data = vec![];
+        stage.write(&mut data).unwrap();
+        let stage2 = Stage::from_slice(&data).unwrap();
+        assert_eq!(stage, stage2);
+    }
+
+    fn quad(anm_script: u16, x: f32, y: f32, z: f32, width: f32, height: f32) -> Quad {
+        Quad {
+            anm_script,
+            pos: Position { x, y, z },
+            size_override: Box2D { width, height },
+        }
+    }
+
+    #[test]
+    fn builder() {
+        let ground = Model::new(0, vec![
+            quad(14, -100., -138., 0.5, 584., 138.),
+            quad(11, -100., -138., 0., 220., 138.),
+        ]);
+        assert_eq!(ground.bounding_box, [-100., -138., 0., 584., 138., 0.5]);
+        let tree = Model::new(0, vec![quad(9, 0., 0., 0., 0., 0.)]);
+        assert_eq!(tree.bounding_box, [0.; 6]);
+
+        let stage = StageBuilder::new("Test by ThibG")
+            .no_music()
+            .music("", "bgm/th06_15.mid")
+            .model(ground)
+            .model(tree)
+            .instance(0, 0., 138., 0.)
+            .instance(1, 40., -138., -50.)
+            .call(0, Instruction::SetFog(50, 0, 50, 0, 300., 800.))
+            .call(2100, Instruction::SetViewpos(0., -800., 0.))
+            .build();
+
+        let mut data = vec![];
+        stage.write(&mut data).unwrap();
+        assert_eq!(u16::from_le_bytes([data[2], data[3]]), 3);
+        let stage2 = Stage::from_slice(&data).unwrap();
+        assert_eq!(stage2.name, "Test by ThibG");
+        assert_eq!(stage2.musics, vec![None, Some((String::from(""), String::from("bgm/th06_15.mid"))), None, None]);
+        assert_eq!(stage2.models, stage.models);
+        assert_eq!(stage2.instances, stage.instances);
+        assert_eq!(stage2.script, stage.script);
+
+        let too_many = StageBuilder::new("").no_music().no_music().no_music().no_music().no_music().build();
+        too_many.write(&mut vec![]).unwrap_err();
+    }
+
+    #[test]
+    fn errors() {
+        use crate::error::{FileKind, Reason};
+
+        let stage = StageBuilder::new("Errors")
+            .model(Model::new(0, vec![quad(1, 0., 0., 0., 16., 16.)]))
+            .instance(0, 0., 0., 0.)
+            .call(0, Instruction::SetViewpos(0., 0., 0.))
+            .build();
+        let mut data = vec![];
+        stage.write(&mut data).unwrap();
+
+        // Truncated files must be rejected, never parsed as a shorter file nor make the parser panic.
+        for len in 0..data.len() {
+            assert!(Stage::from_slice(&data[..len]).is_err(), "prefix of {} bytes was accepted", len);
+        }
+
+        let instances_offset = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let script_offset = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
+
+        let mut corrupted = data.clone();
+        corrupted[instances_offset + 2..instances_offset + 4].copy_from_slice(&0x200u16.to_le_bytes());
+        let err = Stage::from_slice(&corrupted).unwrap_err();
+        assert_eq!(err.kind, FileKind::Std);
+        assert_eq!(err.reason, Reason::InvalidValue);
+
+        // Unknown opcodes are kept as is.
+        let mut patched = data.clone();
+        patched[script_offset + 4..script_offset + 6].copy_from_slice(&42u16.to_le_bytes());
+        let stage = Stage::from_slice(&patched).unwrap();
+        assert_eq!(stage.script[0].instr, Instruction::UnknownOpcode { opcode: 42, args: vec![0; 12] });
+        let mut data2 = vec![];
+        stage.write(&mut data2).unwrap();
+        assert_eq!(data2, patched);
+
+        let mut corrupted = data.clone();
+        corrupted[script_offset + 6..script_offset + 8].copy_from_slice(&16u16.to_le_bytes());
+        assert_eq!(Stage::from_slice(&corrupted).unwrap_err().reason, Reason::SizeMismatch { expected: 16, found: 12 });
+
+        let mut corrupted = data;
+        corrupted[8..12].copy_from_slice(&0x10000u32.to_le_bytes());
+        assert_eq!(Stage::from_slice(&corrupted).unwrap_err().reason, Reason::BadOffset(0x10000));
     }
 }
@@ -0,0 +1,427 @@
+//! T6RP replay format support.
+//!
+//! The T6RP file format is an encrypted format describing different aspects of
+//! a game of EoSD. Since the EoSD engine is entirely deterministic, a small
+//! replay file is sufficient to unfold a full game.
+
+use crate::error::{Error, FileKind, Reason, IResult, failure, at_offset};
+use nom::{
+    bytes::complete::{tag, take},
+    number::complete::{le_u8, le_u16, le_u32, le_i8, le_f32},
+    sequence::tuple,
+    multi::count,
+};
+use alloc::{string::String, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::io;
+
+/// Offset of the first encrypted byte, everything before it is stored in clear.
+const ENCRYPTED_OFFSET: usize = 15;
+
+/// Time value marking the end of the key events of a level.
+const END_OF_KEYS: u32 = 9999999;
+
+/// A change in the pressed keys, at a given frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyEvent {
+    /// Frame at which this event happens.
+    pub time: u32,
+
+    /// Bitmask of the keys pressed from this frame onward.
+    pub keys: u16,
+
+    /// TODO: find what that is.
+    pub unknown: u16,
+}
+
+/// The recording of a single stage.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Level {
+    /// Score at the beginning of this stage.
+    pub score: u32,
+
+    /// Seed of the PRNG at the beginning of this stage.
+    pub random_seed: u16,
+
+    /// Number of point items collected before this stage.
+    pub point_items: u16,
+
+    /// Power of the player at the beginning of this stage.
+    pub power: u8,
+
+    /// Remaining lives at the beginning of this stage.
+    pub lives: i8,
+
+    /// Remaining bombs at the beginning of this stage.
+    pub bombs: i8,
+
+    /// Difficulty at the beginning of this stage.
+    pub difficulty: u8,
+
+    /// TODO: find what that is.
+    pub unknown: u32,
+
+    /// List of key changes during this stage.
+    pub keys: Vec<KeyEvent>,
+}
+
+impl Default for Level {
+    fn default() -> Level {
+        Level {
+            score: 0,
+            random_seed: 0,
+            point_items: 0,
+            power: 0,
+            lives: 2,
+            bombs: 3,
+            difficulty: 16,
+            unknown: 0,
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl Level {
+    /// Iterate over the pressed keys, one item per frame.
+    pub fn iter_keystates(&self) -> impl Iterator<Item = u16> + '_ {
+        let mut counter = 0;
+        let mut previous = 0;
+        self.keys.iter().flat_map(move |event| {
+            let frames = (event.time + 1).saturating_sub(counter);
+            counter += frames;
+            let keystate = previous;
+            previous = event.keys;
+            core::iter::repeat_n(keystate, frames as usize)
+        })
+    }
+}
+
+/// Main struct of the T6RP replay format.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct T6rp {
+    /// Version of the game which recorded this replay.
+    pub version: u16,
+
+    /// Character and shot type used.
+    pub character: u8,
+
+    /// Difficulty level, from easy to extra.
+    pub rank: u8,
+
+    /// TODO: find what that is.
+    pub unknown1: u8,
+
+    /// TODO: find what that is.
+    pub unknown2: u8,
+
+    /// Key used to encrypt the rest of the file.
+    pub key: u8,
+
+    /// TODO: find what that is.
+    pub unknown3: u8,
+
+    /// Date at which this replay got recorded, as dd/mm/yy.
+    pub date: String,
+
+    /// Name of the player.
+    pub name: String,
+
+    /// TODO: find what that is.
+    pub unknown4: u16,
+
+    /// Final score.
+    pub score: u32,
+
+    /// TODO: find what that is.
+    pub unknown5: u32,
+
+    /// Percentage of frames which got slowed down.
+    pub slowdown: f32,
+
+    /// TODO: find what that is.
+    pub unknown6: u32,
+
+    /// The recording of each of the seven stages, if played.
+    pub levels: [Option<Level>; 7],
+}
+
+impl T6rp {
+    /// Parse a slice of bytes into a `T6rp` struct, decrypting it and
+    /// verifying its checksum.
+    pub fn from_slice(data: &[u8]) -> Result<T6rp, Error> {
+        let mut decrypted = data.to_vec();
+        if decrypted.len() >= ENCRYPTED_OFFSET {
+            decrypt(&mut decrypted);
+        }
+        // Offsets are the same in the decrypted data as in the original one.
+        match parse_t6rp(&decrypted, true) {
+            Ok((_, replay)) => Ok(replay),
+            Err(err) => Err(Error::from_nom(FileKind::T6rp, &decrypted, err)),
+        }
+    }
+
+    /// Parse a slice of already decrypted bytes into a `T6rp` struct, without
+    /// verifying its checksum.
+    pub fn from_decrypted_slice(data: &[u8]) -> Result<T6rp, Error> {
+        match parse_t6rp(data, false) {
+            Ok((_, replay)) => Ok(replay),
+            Err(err) => Err(Error::from_nom(FileKind::T6rp, data, err)),
+        }
+    }
+
+    /// Serialize this replay, computing its checksum and encrypting it if
+    /// asked to.
+    #[cfg(feature = "std")]
+    pub fn write<W: io::Write>(&self, file: &mut W, encrypt_data: bool) -> io::Result<()> {
+        let mut data = self.to_decrypted_vec()?;
+        if encrypt_data {
+            encrypt(&mut data);
+        }
+        file.write_all(&data)
+    }
+
+    #[cfg(feature = "std")]
+    fn to_decrypted_vec(&self) -> io::Result<Vec<u8>> {
+        let mut data = vec![];
+        data.extend_from_slice(b"T6RP");
+        data.extend_from_slice(&self.version.to_le_bytes());
+        data.push(self.character);
+        data.push(self.rank);
+        // The checksum gets filled once everything else is known.
+        data.extend_from_slice(&[0; 4]);
+        data.push(self.unknown1);
+        data.push(self.unknown2);
+        data.push(self.key);
+        data.push(self.unknown3);
+        write_string(&mut data, &self.date)?;
+        write_string(&mut data, &self.name)?;
+        data.extend_from_slice(&self.unknown4.to_le_bytes());
+        data.extend_from_slice(&self.score.to_le_bytes());
+        data.extend_from_slice(&self.unknown5.to_le_bytes());
+        data.extend_from_slice(&self.slowdown.to_le_bytes());
+        data.extend_from_slice(&self.unknown6.to_le_bytes());
+
+        let stages_offsets_offset = data.len();
+        data.extend_from_slice(&[0; 7 * 4]);
+
+        for (i, level) in self.levels.iter().enumerate() {
+            let level = match level {
+                Some(level) => level,
+                None => continue,
+            };
+            let offset = data.len() as u32;
+            data[stages_offsets_offset + 4 * i..][..4].copy_from_slice(&offset.to_le_bytes());
+            data.extend_from_slice(&level.score.to_le_bytes());
+            data.extend_from_slice(&level.random_seed.to_le_bytes());
+            data.extend_from_slice(&level.point_items.to_le_bytes());
+            data.push(level.power);
+            data.push(level.lives as u8);
+            data.push(level.bombs as u8);
+            data.push(level.difficulty);
+            data.extend_from_slice(&level.unknown.to_le_bytes());
+            for event in level.keys.iter() {
+                data.extend_from_slice(&event.time.to_le_bytes());
+                data.extend_from_slice(&event.keys.to_le_bytes());
+                data.extend_from_slice(&event.unknown.to_le_bytes());
+            }
+            data.extend_from_slice(&END_OF_KEYS.to_le_bytes());
+            data.extend_from_slice(&[0; 4]);
+        }
+
+        let checksum = compute_checksum(&data);
+        data[8..12].copy_from_slice(&checksum.to_le_bytes());
+        Ok(data)
+    }
+}
+
+/// Decrypt a replay in place, using the key stored in its header.
+pub fn decrypt(data: &mut [u8]) {
+    let key = data[ENCRYPTED_OFFSET - 1];
+    for (i, byte) in data[ENCRYPTED_OFFSET..].iter_mut().enumerate() {
+        *byte = byte.wrapping_sub(key).wrapping_sub((7 * i) as u8);
+    }
+}
+
+/// Encrypt a replay in place, using the key stored in its header.
+pub fn encrypt(data: &mut [u8]) {
+    let key = data[ENCRYPTED_OFFSET - 1];
+    for (i, byte) in data[ENCRYPTED_OFFSET..].iter_mut().enumerate() {
+        *byte = byte.wrapping_add(key).wrapping_add((7 * i) as u8);
+    }
+}
+
+/// Compute the checksum of a decrypted replay.
+pub fn compute_checksum(data: &[u8]) -> u32 {
+    let key = data[ENCRYPTED_OFFSET - 1];
+    data[ENCRYPTED_OFFSET..].iter()
+        .fold(0x3f000318u32.wrapping_add(key as u32), |sum, &c| sum.wrapping_add(c as u32))
+}
+
+#[cfg(feature = "std")]
+fn write_string(data: &mut Vec<u8>, string: &str) -> io::Result<()> {
+    let bytes = string.as_bytes();
+    if bytes.len() > 8 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("String too long for T6RP: {}", string)));
+    }
+    data.extend_from_slice(bytes);
+    data.resize(data.len() + 9 - bytes.len(), 0);
+    Ok(())
+}
+
+fn parse_string(i: &[u8]) -> IResult<&[u8], String> {
+    let (i, data) = take(9usize)(i)?;
+    let data = data.split(|c| *c == b'\0').next().unwrap();
+    Ok((i, String::from_utf8_lossy(data).into_owned()))
+}
+
+fn parse_key_event(i: &[u8]) -> IResult<&[u8], KeyEvent> {
+    let (i, (time, keys, unknown)) = tuple((le_u32, le_u16, le_u16))(i)?;
+    Ok((i, KeyEvent { time, keys, unknown }))
+}
+
+fn parse_level(i: &[u8]) -> IResult<&[u8], Level> {
+    let (mut i, (score, random_seed, point_items, power, lives, bombs, difficulty, unknown)) =
+        tuple((le_u32, le_u16, le_u16, le_u8, le_i8, le_i8, le_u8, le_u32))(i)?;
+    let mut keys = vec![];
+    loop {
+        let (i2, event) = parse_key_event(i)?;
+        i = i2;
+        if event.time == END_OF_KEYS {
+            break;
+        }
+        keys.push(event);
+    }
+    let level = Level {
+        score,
+        random_seed,
+        point_items,
+        power,
+        lives,
+        bombs,
+        difficulty,
+        unknown,
+        keys,
+    };
+    Ok((i, level))
+}
+
+fn parse_t6rp(input: &[u8], verify: bool) -> IResult<&[u8], T6rp> {
+    let (i, (_, version, character, rank, checksum, unknown1, unknown2, key)) =
+        tuple((tag(b"T6RP"), le_u16, le_u8, le_u8, le_u32, le_u8, le_u8, le_u8))(input)?;
+
+    if verify && checksum != compute_checksum(input) {
+        return failure(i, Reason::BadChecksum);
+    }
+
+    let (i, (unknown3, date, name, unknown4, score, unknown5, slowdown, unknown6)) =
+        tuple((le_u8, parse_string, parse_string, le_u16, le_u32, le_u32, le_f32, le_u32))(i)?;
+    let (_, stages_offsets) = count(le_u32, 7)(i)?;
+
+    let mut levels: [Option<Level>; 7] = Default::default();
+    for (level, offset) in levels.iter_mut().zip(stages_offsets) {
+        if offset == 0 {
+            continue;
+        }
+        let (_, parsed) = parse_level(at_offset(input, offset)?)?;
+        *level = Some(parsed);
+    }
+
+    let replay = T6rp {
+        version,
+        character,
+        rank,
+        unknown1,
+        unknown2,
+        key,
+        unknown3,
+        date,
+        name,
+        unknown4,
+        score,
+        unknown5,
+        slowdown,
+        unknown6,
+        levels,
+    };
+    Ok((b"", replay))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> T6rp {
+        let mut level = Level {
+            score: 0,
+            random_seed: 0x1234,
+            ..Default::default()
+        };
+        level.keys.push(KeyEvent { time: 0, keys: 0, unknown: 0 });
+        level.keys.push(KeyEvent { time: 2, keys: 0x10, unknown: 0 });
+        level.keys.push(KeyEvent { time: 4, keys: 0x11, unknown: 0 });
+        let mut levels: [Option<Level>; 7] = Default::default();
+        levels[0] = Some(level);
+        T6rp {
+            version: 0x102,
+            character: 1,
+            rank: 3,
+            unknown1: 0,
+            unknown2: 0,
+            key: 0x42,
+            unknown3: 0,
+            date: String::from("17/10/26"),
+            name: String::from("PyTouhou"),
+            unknown4: 0,
+            score: 123456,
+            unknown5: 0,
+            slowdown: 0.5,
+            unknown6: 0,
+            levels,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut data = vec![];
+        replay().write(&mut data, true).unwrap();
+        let parsed = T6rp::from_slice(&data).unwrap();
+        assert_eq!(parsed.character, 1);
+        assert_eq!(parsed.rank, 3);
+        assert_eq!(parsed.date, "17/10/26");
+        assert_eq!(parsed.name, "PyTouhou");
+        assert_eq!(parsed.score, 123456);
+        assert_eq!(parsed.slowdown, 0.5);
+        assert!(parsed.levels[1..].iter().all(Option::is_none));
+        let level = parsed.levels[0].as_ref().unwrap();
+        assert_eq!(level.random_seed, 0x1234);
+        assert_eq!(level.keys.len(), 3);
+
+        let mut data2 = vec![];
+        parsed.write(&mut data2, true).unwrap();
+        assert_eq!(data, data2);
+    }
+
+    #[test]
+    fn checksum() {
+        let mut data = vec![];
+        replay().write(&mut data, true).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        T6rp::from_slice(&data).unwrap_err();
+
+        decrypt(&mut data);
+        let parsed = T6rp::from_decrypted_slice(&data).unwrap();
+        assert_eq!(parsed.score, 123456);
+    }
+
+    #[test]
+    fn keystates() {
+        let replay = replay();
+        let level = replay.levels[0].as_ref().unwrap();
+        let keystates: Vec<_> = level.iter_keystates().collect();
+        assert_eq!(keystates, [0, 0, 0, 0x10, 0x10]);
+    }
+}
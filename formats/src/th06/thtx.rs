@@ -0,0 +1,213 @@
+//! THTX embedded texture support.
+
+use crate::error::{Error, FileKind, IResult};
+use nom::{
+    bytes::complete::{tag, take},
+    number::complete::{le_u16, le_u32},
+    sequence::tuple,
+};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io;
+
+/// Pixel format of a texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Format {
+    /// 32-bit pixels, stored as B, G, R, A bytes.
+    Argb8888,
+
+    /// 16-bit pixels, with five bits of red, six of green and five of blue.
+    Rgb565,
+
+    /// 16-bit pixels, with four bits for each channel.
+    Argb4444,
+
+    /// 8-bit luminance.
+    Gray8,
+
+    /// A format we don’t know how to decode.
+    Unknown(u32),
+}
+
+impl Format {
+    /// Size in bytes of a single pixel, if this format is known.
+    pub fn bytes_per_pixel(self) -> Option<usize> {
+        match self {
+            Format::Argb8888 => Some(4),
+            Format::Rgb565 | Format::Argb4444 => Some(2),
+            Format::Gray8 => Some(1),
+            Format::Unknown(_) => None,
+        }
+    }
+
+    /// Decode these pixels into RGBA8, returns `None` for unknown formats.
+    pub fn decode_rgba8(self, data: &[u8]) -> Option<Vec<u8>> {
+        let bpp = self.bytes_per_pixel()?;
+        let mut rgba = Vec::with_capacity(data.len() / bpp * 4);
+        for pixel in data.chunks_exact(bpp) {
+            let color = match self {
+                Format::Argb8888 => [pixel[2], pixel[1], pixel[0], pixel[3]],
+                Format::Rgb565 => {
+                    let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                    let r = (value >> 11) as u8 & 0x1f;
+                    let g = (value >> 5) as u8 & 0x3f;
+                    let b = value as u8 & 0x1f;
+                    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2), 0xff]
+                }
+                Format::Argb4444 => {
+                    let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                    let a = (value >> 12) as u8 & 0xf;
+                    let r = (value >> 8) as u8 & 0xf;
+                    let g = (value >> 4) as u8 & 0xf;
+                    let b = value as u8 & 0xf;
+                    [r * 0x11, g * 0x11, b * 0x11, a * 0x11]
+                }
+                Format::Gray8 => [pixel[0], pixel[0], pixel[0], 0xff],
+                Format::Unknown(_) => unreachable!(),
+            };
+            rgba.extend_from_slice(&color);
+        }
+        Some(rgba)
+    }
+}
+
+impl From<u32> for Format {
+    fn from(value: u32) -> Format {
+        match value {
+            1 => Format::Argb8888,
+            3 => Format::Rgb565,
+            5 => Format::Argb4444,
+            7 => Format::Gray8,
+            value => Format::Unknown(value),
+        }
+    }
+}
+
+impl From<Format> for u32 {
+    fn from(format: Format) -> u32 {
+        match format {
+            Format::Argb8888 => 1,
+            Format::Rgb565 => 3,
+            Format::Argb4444 => 5,
+            Format::Gray8 => 7,
+            Format::Unknown(value) => value,
+        }
+    }
+}
+
+/// A texture embedded in an ANM file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Texture {
+    /// Pixel format of the data.
+    pub format: Format,
+
+    /// Width of the texture, in pixels.
+    pub width: u16,
+
+    /// Height of the texture, in pixels.
+    pub height: u16,
+
+    /// Raw pixel data, in `format`.
+    pub data: Vec<u8>,
+}
+
+impl Texture {
+    /// Parse a slice of bytes into a `Texture` struct.
+    pub fn from_slice(data: &[u8]) -> Result<Texture, Error> {
+        match parse_texture(data) {
+            Ok((_, texture)) => Ok(texture),
+            Err(err) => Err(Error::from_nom(FileKind::Thtx, data, err)),
+        }
+    }
+
+    /// Serialize this `Texture` into a THTX block.
+    #[cfg(feature = "std")]
+    pub fn write<W: io::Write>(&self, file: &mut W) -> io::Result<()> {
+        let mut data = vec![];
+        write_texture(&mut data, self);
+        file.write_all(&data)
+    }
+
+    /// Decode this texture into RGBA8 pixels, row by row.
+    ///
+    /// Returns `None` if the format is unknown or if there isn’t enough data for its size.
+    pub fn to_rgba8(&self) -> Option<Vec<u8>> {
+        let size = self.width as usize * self.height as usize * self.format.bytes_per_pixel()?;
+        let data = self.data.get(..size)?;
+        self.format.decode_rgba8(data)
+    }
+}
+
+pub(crate) fn parse_texture(input: &[u8]) -> IResult<&[u8], Texture> {
+    let (i, (_, _, format, width, height, size)) =
+        tuple((tag(b"THTX"), tag(b"\0\0"), le_u16, le_u16, le_u16, le_u32))(input)?;
+    let (i, data) = take(size as usize)(i)?;
+    Ok((i, Texture {
+        format: Format::from(u32::from(format)),
+        width,
+        height,
+        data: data.to_vec(),
+    }))
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn write_texture(data: &mut Vec<u8>, texture: &Texture) {
+    data.extend_from_slice(b"THTX");
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&(u32::from(texture.format) as u16).to_le_bytes());
+    data.extend_from_slice(&texture.width.to_le_bytes());
+    data.extend_from_slice(&texture.height.to_le_bytes());
+    data.extend_from_slice(&(texture.data.len() as u32).to_le_bytes());
+    data.extend_from_slice(&texture.data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Reason;
+
+    #[test]
+    fn formats() {
+        for value in 0..10 {
+            assert_eq!(u32::from(Format::from(value)), value);
+        }
+        assert_eq!(Format::from(5), Format::Argb4444);
+        assert_eq!(Format::from(2), Format::Unknown(2));
+    }
+
+    #[test]
+    fn decoders() {
+        let bgra = Format::Argb8888.decode_rgba8(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(bgra, [3, 2, 1, 4]);
+        let rgb565 = Format::Rgb565.decode_rgba8(&0xf81fu16.to_le_bytes()).unwrap();
+        assert_eq!(rgb565, [0xff, 0, 0xff, 0xff]);
+        let argb4444 = Format::Argb4444.decode_rgba8(&0x8f30u16.to_le_bytes()).unwrap();
+        assert_eq!(argb4444, [0xff, 0x33, 0, 0x88]);
+        let gray = Format::Gray8.decode_rgba8(&[0x42, 0x10]).unwrap();
+        assert_eq!(gray, [0x42, 0x42, 0x42, 0xff, 0x10, 0x10, 0x10, 0xff]);
+        assert_eq!(Format::Unknown(2).decode_rgba8(&[0; 4]), None);
+    }
+
+    #[test]
+    fn texture() {
+        let texture = Texture {
+            format: Format::Argb4444,
+            width: 2,
+            height: 1,
+            data: vec![0x00, 0xf0, 0xff, 0xff],
+        };
+        let mut data = vec![];
+        texture.write(&mut data).unwrap();
+        assert_eq!(&data[..4], b"THTX");
+        let parsed = Texture::from_slice(&data).unwrap();
+        assert_eq!(parsed, texture);
+        assert_eq!(parsed.to_rgba8().unwrap(), [0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff]);
+
+        let err = Texture::from_slice(&data[..data.len() - 1]).unwrap_err();
+        assert_eq!(err.reason, Reason::Truncated);
+        let err = Texture::from_slice(b"THTY").unwrap_err();
+        assert_eq!(err.reason, Reason::BadMagic);
+    }
+}
@@ -89,6 +89,78 @@ impl Sprite {
         }
     }
 
+    /// Show this sprite from these ANMs, without any script to animate it.
+    pub(crate) fn load_sprite(&mut self, anms: Rc<RefCell<[Anm0]>>, id: u8) {
+        Anms::new(anms).load_sprite(self, id);
+    }
+
+    /// Set the angle used when this sprite is automatically oriented.
+    pub(crate) fn set_angle(&mut self, angle: f32) {
+        self.angle = angle;
+        self.changed = true;
+    }
+
+    /// Show or hide this sprite.
+    pub(crate) fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+        self.changed = true;
+    }
+
+    /// Override the size of this sprite, ignoring its texture area and rescaling.
+    pub(crate) fn set_size_override(&mut self, width: f32, height: f32) {
+        self.width_override = width;
+        self.height_override = height;
+        self.changed = true;
+    }
+
+    /// Rotate this sprite by this angle, regardless of its automatic orientation.
+    pub(crate) fn set_rotation(&mut self, angle: f32) {
+        self.angle = angle;
+        self.force_rotation = true;
+        self.changed = true;
+    }
+
+    /// Set the colour of this sprite, keeping its alpha.
+    pub(crate) fn set_color(&mut self, r: u8, g: u8, b: u8) {
+        self.color[0] = r;
+        self.color[1] = g;
+        self.color[2] = b;
+        self.changed = true;
+    }
+
+    /// Set the alpha of this sprite.
+    pub(crate) fn set_alpha(&mut self, alpha: u8) {
+        self.color[3] = alpha;
+        self.changed = true;
+    }
+
+    /// Set the scale of this sprite.
+    pub(crate) fn set_rescale(&mut self, sx: f32, sy: f32) {
+        self.rescale = [sx, sy];
+        self.changed = true;
+    }
+
+    /// Select how this sprite gets blended, 0 for alpha blending and 1 for additive.
+    pub(crate) fn set_blendfunc(&mut self, blendfunc: u32) {
+        self.blendfunc = blendfunc;
+        self.changed = true;
+    }
+
+    /// Fade this sprite to this alpha over this many frames.
+    pub(crate) fn fade(&mut self, duration: u32, alpha: u8) {
+        self.fade_interpolator = Some(Interpolator1::new([self.color[3] as f32], self.frame, [alpha as f32], self.frame + duration, Formula::Linear));
+    }
+
+    /// Scale this sprite to this size over this many frames.
+    pub(crate) fn scale_in(&mut self, duration: u32, sx: f32, sy: f32) {
+        self.scale_interpolator = Some(Interpolator2::new(self.rescale, self.frame, [sx, sy], self.frame + duration, Formula::Linear));
+    }
+
+    /// Size of the texture area of this sprite, before any rescaling.
+    pub(crate) fn get_size(&self) -> (f32, f32) {
+        (self.texcoords[2], self.texcoords[3])
+    }
+
     /// TODO
     pub fn fill_vertices(&self, vertices: &mut [Vertex; 4], x: f32, y: f32, z: f32) {
         let mut mat = Mat4::new([[-0.5, 0.5, 0.5, -0.5],
@@ -320,7 +392,7 @@ impl AnmRunner {
             new_ip = self.script.interrupts.get(&-1);
         }
         let new_ip = if let Some(new_ip) = new_ip {
-            *new_ip as usize
+            *new_ip
         } else {
             return false;
         };
@@ -339,8 +411,8 @@ impl AnmRunner {
         }
 
         while self.running && !self.waiting {
-            let Call { time: frame, instr } = self.script.instructions[self.instruction_pointer];
-            let frame = frame.clone();
+            let Call { time: frame, ref instr } = self.script.instructions[self.instruction_pointer];
+            let instr = instr.clone();
 
             if frame > self.frame {
                 break;
@@ -384,7 +456,7 @@ impl AnmRunner {
                 // TODO: check this modulo.
                 sprite.color[3] = (alpha % 256) as u8;
             }
-            Instruction::SetColor(b, g, r) => {
+            Instruction::SetColor(b, g, r, _) => {
                 if sprite.fade_interpolator.is_none() {
                     sprite.color[0] = r;
                     sprite.color[1] = g;
@@ -476,6 +548,9 @@ impl AnmRunner {
             Instruction::Todo(_todo) => {
                 // TODO.
             }
+            Instruction::Unknown { .. } => {
+                // Unknown to us, kept by the parser but skipped here.
+            }
         }
     }
 }
@@ -492,13 +567,14 @@ mod tests {
         let mut file = io::BufReader::new(file);
         let mut buf = vec![];
         file.read_to_end(&mut buf).unwrap();
-        let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
+        let mut anms = Anm0::from_slice(&buf).unwrap();
         let anm0 = anms.pop().unwrap();
         assert_eq!(anm0.size, (256, 256));
-        assert_eq!(anm0.format, 5);
+        assert_eq!(anm0.format, touhou_formats::th06::thtx::Format::Argb4444);
         let sprite = Rc::new(RefCell::new(Sprite::new()));
+        let anms = Rc::new(RefCell::new([anm0]));
         let prng = Rc::new(RefCell::new(Prng::new(0)));
-        let mut anm_runner = AnmRunner::new(&anm0, 1, sprite.clone(), Rc::downgrade(&prng), 0);
+        let mut anm_runner = AnmRunner::new(anms, 1, sprite.clone(), Rc::downgrade(&prng), 0);
         for _ in 0..50 {
             anm_runner.run_frame();
         }
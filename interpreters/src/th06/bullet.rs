@@ -0,0 +1,644 @@
+//! Module providing a Bullet struct, for the bullets fired by enemies and by the player.
+
+use touhou_formats::th06::anm0::Anm0;
+use touhou_formats::th06::sht::Shot;
+use crate::th06::anm0::{Sprite, AnmRunner};
+use crate::th06::enemy::{BulletAttributes, Game, Position, GAME_WIDTH, GAME_HEIGHT};
+use crate::th06::interpolator::{Interpolator1, Formula};
+use touhou_utils::prng::Prng;
+use std::cell::RefCell;
+use std::f32::consts::PI;
+use std::rc::{Rc, Weak};
+
+/// Start 5 faster than its speed, slowing down to it during the first 16 frames.
+pub const FLAG_SPEED_BURST: u32 = 1;
+
+/// Spawn with the first launch animation of its type, moving at half its speed until it ends.
+pub const FLAG_LAUNCH_ANIM_1: u32 = 2;
+
+/// Spawn with the second launch animation of its type, moving at 0.4 times its speed until it
+/// ends.
+pub const FLAG_LAUNCH_ANIM_2: u32 = 4;
+
+/// Spawn with the third launch animation of its type, moving at a third of its speed until it
+/// ends.
+pub const FLAG_LAUNCH_ANIM_3: u32 = 8;
+
+/// Every frame, add a vector of length `attributes.4` and angle `attributes.5` to the movement,
+/// until frame `attributes.0`.  The angle of the bullet is used instead if `attributes.5` is
+/// below -900.
+pub const FLAG_ACCELERATION: u32 = 16;
+
+/// Every frame, add `attributes.4` to the speed and `attributes.5` to the angle, until frame
+/// `attributes.0`.
+pub const FLAG_ACCELERATE_AND_ROTATE: u32 = 32;
+
+/// Every `attributes.0` frames, slow down to a stop then add `attributes.4` to the angle, up to
+/// `attributes.1` times.  The speed then becomes `attributes.5`, unless it is below -900.
+pub const FLAG_CHANGE_ANGLE: u32 = 64;
+
+/// Like `FLAG_CHANGE_ANGLE`, but aiming at the player with an offset of `attributes.4`.
+pub const FLAG_AIM_AT_PLAYER: u32 = 128;
+
+/// Like `FLAG_CHANGE_ANGLE`, but setting the angle to `attributes.4`.
+pub const FLAG_SET_ANGLE: u32 = 256;
+
+/// Bounce on all four edges of the screen, up to `attributes.0` times.
+pub const FLAG_BOUNCE: u32 = 1024;
+
+/// Bounce on every edge of the screen but the bottom one, up to `attributes.0` times.
+pub const FLAG_BOUNCE_NO_BOTTOM: u32 = 2048;
+
+const FLAGS_LAUNCH_ANIM: u32 = FLAG_LAUNCH_ANIM_1 | FLAG_LAUNCH_ANIM_2 | FLAG_LAUNCH_ANIM_3;
+const FLAGS_STOP_AND_CHANGE: u32 = FLAG_CHANGE_ANGLE | FLAG_AIM_AT_PLAYER | FLAG_SET_ANGLE;
+
+/// The animations and hitbox of a kind of bullet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulletType {
+    /// Index of the bullet ANM containing the scripts of this type.
+    pub anm: usize,
+
+    /// Script used once the bullet has been launched.
+    pub anim_index: u8,
+
+    /// Script used when the bullet gets cancelled.
+    pub cancel_anim_index: u8,
+
+    /// Scripts used for the launch animations requested by the flags 2, 4 and 8.
+    pub launch_anim_indices: [u8; 3],
+
+    /// Half the size of the square hitbox of this bullet.
+    pub hitbox_size: f32,
+
+    /// Speed multipliers applied during each of the launch animations.
+    pub launch_anim_penalties: [f32; 3],
+
+    /// Sprite index offset of the launch animation, for each sprite index offset of the bullet.
+    pub launch_anim_offsets: &'static [u32],
+}
+
+const DEFAULT_PENALTIES: [f32; 3] = [0.5, 0.4, 1. / 3.];
+const DEFAULT_OFFSETS: &[u32] = &[0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4, 0];
+
+const fn bullet_type(anm: usize, anim_index: u8, cancel_anim_index: u8, launch_anim_indices: [u8; 3],
+                     hitbox_size: f32, launch_anim_offsets: &'static [u32]) -> BulletType {
+    BulletType {
+        anm,
+        anim_index,
+        cancel_anim_index,
+        launch_anim_indices,
+        hitbox_size,
+        launch_anim_penalties: DEFAULT_PENALTIES,
+        launch_anim_offsets,
+    }
+}
+
+/// Bullet types of EoSD, the last one coming from etama4.anm while all of the others come from
+/// etama3.anm.
+pub const EOSD_BULLET_TYPES: [BulletType; 10] = [
+    bullet_type(0, 0, 11, [14, 15, 16], 2., DEFAULT_OFFSETS),
+    bullet_type(0, 1, 12, [17, 18, 19], 3., DEFAULT_OFFSETS),
+    bullet_type(0, 2, 12, [17, 18, 19], 2., DEFAULT_OFFSETS),
+    bullet_type(0, 3, 12, [17, 18, 19], 3., DEFAULT_OFFSETS),
+    bullet_type(0, 4, 12, [17, 18, 19], 2.5, DEFAULT_OFFSETS),
+    bullet_type(0, 5, 12, [17, 18, 19], 2., DEFAULT_OFFSETS),
+    bullet_type(0, 6, 13, [20, 20, 20], 8., &[0, 1, 1, 2, 2, 3, 4, 0]),
+    bullet_type(0, 7, 13, [20, 20, 20], 5.5, &[1, 1, 1, 1]),
+    bullet_type(0, 8, 13, [20, 20, 20], 4.5, &[0, 1, 1, 2, 2, 3, 4, 0]),
+    bullet_type(1, 0, 1, [2, 2, 2], 16., &[0, 1, 2, 3]),
+];
+
+/// The lifecycle of a bullet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulletState {
+    /// Playing its launch animation, before moving at its full speed.
+    Launching,
+
+    /// Moving according to its flags.
+    Launched,
+
+    /// Playing its cancel animation, after which it gets removed.
+    Cancelled,
+}
+
+/// Struct representing a bullet, fired either by an enemy or by the player.
+pub struct Bullet {
+    /// Current position of the bullet.
+    pub pos: Position,
+
+    /// Current angle of the bullet.
+    pub angle: f32,
+
+    /// Current speed of the bullet.
+    pub speed: f32,
+
+    /// Movement of the bullet for this frame, the last component is always zero.
+    pub dpos: [f32; 3],
+
+    /// Behaviours of the bullet, see the `FLAG_*` constants.
+    pub flags: u32,
+
+    /// Current frame of the bullet, since it got launched.
+    pub frame: u32,
+
+    /// Extended attributes of the bullet, used by its flags.
+    pub attributes: (i32, i32, i32, i32, f32, f32, f32, f32),
+
+    /// Current state of the bullet.
+    pub state: BulletState,
+
+    pub(crate) bullet_type: BulletType,
+    pub(crate) sprite_index_offset: u32,
+    pub(crate) hitbox: [f32; 2],
+    pub(crate) removed: bool,
+    pub(crate) fired_by_player: bool,
+    pub(crate) damage: u16,
+    pub(crate) grazed: bool,
+    was_visible: bool,
+    speed_interpolator: Option<(Interpolator1<f32>, u32)>,
+    sprite: Option<Rc<RefCell<Sprite>>>,
+    anmrunner: Option<AnmRunner>,
+    anms: Option<Rc<RefCell<[Anm0]>>>,
+    prng: Weak<RefCell<Prng>>,
+}
+
+impl Bullet {
+    /// Create a bullet from these attributes, at this angle and speed, or None if its type doesn’t
+    /// exist.
+    ///
+    /// Its launch animation is skipped if the ANM of its type hasn’t been loaded.
+    pub(crate) fn new(attributes: &BulletAttributes, angle: f32, speed: f32, game: &Game) -> Option<Bullet> {
+        let bullet_type = game.bullet_types.get(attributes.anim as usize)?.clone();
+        let hitbox = [bullet_type.hitbox_size, bullet_type.hitbox_size];
+        let anms = game.bullet_anms.get(bullet_type.anm).cloned();
+        let mut bullet = Bullet {
+            pos: attributes.pos,
+            angle,
+            speed,
+            dpos: [angle.cos() * speed, angle.sin() * speed, 0.],
+            flags: attributes.flags,
+            frame: 0,
+            attributes: attributes.extended_attributes,
+            state: BulletState::Launching,
+            bullet_type,
+            sprite_index_offset: attributes.sprite_index_offset as u32,
+            hitbox,
+            removed: false,
+            fired_by_player: false,
+            damage: 0,
+            grazed: false,
+            was_visible: true,
+            speed_interpolator: None,
+            sprite: None,
+            anmrunner: None,
+            anms,
+            prng: Rc::downgrade(&game.prng),
+        };
+
+        let flags = bullet.flags;
+        if flags & FLAGS_LAUNCH_ANIM != 0 && bullet.anms.is_some() {
+            let which = if flags & FLAG_LAUNCH_ANIM_1 != 0 {
+                0
+            } else if flags & FLAG_LAUNCH_ANIM_2 != 0 {
+                1
+            } else {
+                2
+            };
+            let penalty = bullet.bullet_type.launch_anim_penalties[which];
+            bullet.dpos[0] *= penalty;
+            bullet.dpos[1] *= penalty;
+            let script = bullet.bullet_type.launch_anim_indices[which];
+            let offset = bullet.launch_anim_offset();
+            bullet.start_anim(script, offset);
+        } else {
+            bullet.launch();
+        }
+        Some(bullet)
+    }
+
+    /// Create a bullet fired by the player for this shot of its SHT, using the ANM of the player
+    /// if it has been loaded.
+    pub(crate) fn new_player_shot(pos: Position, shot: &Shot, anms: Option<Rc<RefCell<[Anm0]>>>, prng: Weak<RefCell<Prng>>) -> Bullet {
+        let anim_index = shot.sprite.rem_euclid(256) as u8;
+        let bullet_type = BulletType {
+            // Unused, the scripts come from the ANM of the player.
+            anm: 0,
+            anim_index,
+            // TODO: find the real cancel anim.
+            cancel_anim_index: anim_index.wrapping_add(32),
+            launch_anim_indices: [0; 3],
+            hitbox_size: 0.,
+            launch_anim_penalties: [0.; 3],
+            launch_anim_offsets: &[],
+        };
+        // TODO: type 1 is homing, and triple-check the acceleration of type 2!
+        let (flags, attributes) = if shot.type_ == 2 {
+            (FLAG_ACCELERATION, (-1, 0, 0, 0, 0.15, -PI / 2., 0., 0.))
+        } else {
+            (0, (0, 0, 0, 0, 0., 0., 0., 0.))
+        };
+        let mut bullet = Bullet {
+            pos,
+            angle: shot.angle,
+            speed: shot.speed,
+            dpos: [shot.angle.cos() * shot.speed, shot.angle.sin() * shot.speed, 0.],
+            flags,
+            frame: 0,
+            attributes,
+            state: BulletState::Launching,
+            bullet_type,
+            sprite_index_offset: 0,
+            hitbox: [shot.hitbox.0, shot.hitbox.1],
+            removed: false,
+            fired_by_player: true,
+            damage: shot.damage,
+            grazed: false,
+            was_visible: true,
+            speed_interpolator: None,
+            sprite: None,
+            anmrunner: None,
+            anms,
+            prng,
+        };
+        bullet.launch();
+        bullet
+    }
+
+    fn launch_anim_offset(&self) -> u32 {
+        let offsets = self.bullet_type.launch_anim_offsets;
+        offsets.get(self.sprite_index_offset as usize).copied().unwrap_or(0)
+    }
+
+    /// Replace the animation of this bullet with this script, if the ANM of its type has been
+    /// loaded.
+    fn start_anim(&mut self, script: u8, sprite_index_offset: u32) {
+        let anms = match &self.anms {
+            Some(anms) => anms.clone(),
+            None => return,
+        };
+        let mut sprite = Sprite::new();
+        sprite.set_angle(self.sprite_angle(self.angle));
+        let sprite = Rc::new(RefCell::new(sprite));
+        let anmrunner = AnmRunner::new(anms, script, sprite.clone(), self.prng.clone(), sprite_index_offset);
+        self.sprite = Some(sprite);
+        self.anmrunner = Some(anmrunner);
+    }
+
+    /// Restart the animation of this bullet, optionally with another sprite index offset.
+    pub fn set_anim(&mut self, sprite_index_offset: Option<u32>) {
+        if let Some(sprite_index_offset) = sprite_index_offset {
+            self.sprite_index_offset = sprite_index_offset;
+        }
+        self.start_anim(self.bullet_type.anim_index, self.sprite_index_offset);
+    }
+
+    fn launch(&mut self) {
+        self.state = BulletState::Launched;
+        self.frame = 0;
+        self.set_anim(None);
+        self.set_angle_and_speed(self.angle, self.speed);
+
+        if self.flags & FLAG_SPEED_BURST != 0 {
+            self.interpolate_speed(self.speed + 5., 0, self.speed, 16);
+        }
+    }
+
+    /// Linearly change the speed of this bullet, until its end frame.
+    fn interpolate_speed(&mut self, start_speed: f32, start_frame: u32, end_speed: f32, end_frame: u32) {
+        let interpolator = Interpolator1::new([start_speed], start_frame, [end_speed], end_frame, Formula::Linear);
+        self.speed_interpolator = Some((interpolator, end_frame));
+    }
+
+    /// Cancel this bullet, playing its cancel animation while slowing it down before removing it.
+    pub fn cancel(&mut self) {
+        let offset = self.launch_anim_offset();
+        self.start_anim(self.bullet_type.cancel_anim_index, offset);
+        let divisor = if self.fired_by_player { 8. } else { 2. };
+        self.dpos[0] /= divisor;
+        self.dpos[1] /= divisor;
+        self.state = BulletState::Cancelled;
+
+        // Without any animation, there is nothing to wait for.
+        if self.anmrunner.is_none() {
+            self.removed = true;
+        }
+    }
+
+    /// The sprites of the player’s bullets point backwards.
+    fn sprite_angle(&self, angle: f32) -> f32 {
+        if self.fired_by_player {
+            angle - PI
+        } else {
+            angle
+        }
+    }
+
+    fn set_angle_and_speed(&mut self, angle: f32, speed: f32) {
+        self.angle = angle;
+        self.speed = speed;
+        self.dpos = [angle.cos() * speed, angle.sin() * speed, 0.];
+        if let Some(sprite) = &self.sprite {
+            sprite.borrow_mut().set_angle(self.sprite_angle(angle));
+        }
+    }
+
+    /// Get the sprite of this bullet, if it has got one.
+    pub fn get_sprite(&self) -> Option<Rc<RefCell<Sprite>>> {
+        self.sprite.clone()
+    }
+
+    /// Whether the sprite of this bullet is at least 30 pixels high, which is how the original
+    /// engine tells big bullets apart in a few spellcards.
+    pub(crate) fn is_large(&self) -> bool {
+        match &self.sprite {
+            Some(sprite) => sprite.borrow().get_size().1 >= 30.,
+            None => self.hitbox[1] * 2. >= 30.,
+        }
+    }
+
+    fn is_visible(&self) -> bool {
+        let (width, height) = match &self.sprite {
+            Some(sprite) => sprite.borrow().get_size(),
+            None => (self.hitbox[0] * 2., self.hitbox[1] * 2.),
+        };
+        let Position { x, y } = self.pos;
+        let (max_x, max_y) = (width / 2., height / 2.);
+        !(max_x < x - GAME_WIDTH || max_x < -x || max_y < y - GAME_HEIGHT || max_y < -y)
+    }
+
+    /// Run the behaviours of the bullet and move it for a single frame, marking it as removed once
+    /// it has left the screen or finished being cancelled.
+    ///
+    /// The target is the position of the player, used by `FLAG_AIM_AT_PLAYER`.
+    pub fn update(&mut self, target: Position) {
+        if let Some(anmrunner) = &mut self.anmrunner {
+            if !anmrunner.run_frame() {
+                match self.state {
+                    // TODO: check whether this skips a frame.
+                    BulletState::Launching => self.launch(),
+                    BulletState::Cancelled => self.removed = true,
+                    BulletState::Launched => self.anmrunner = None,
+                }
+            }
+        }
+
+        if self.state == BulletState::Launched {
+            self.update_flags(target);
+        }
+
+        if let Some((interpolator, end_frame)) = &self.speed_interpolator {
+            let speed = interpolator.values(self.frame)[0];
+            self.dpos = [self.angle.cos() * speed, self.angle.sin() * speed, 0.];
+            if self.frame >= *end_frame {
+                self.speed_interpolator = None;
+            }
+        }
+
+        self.pos.x += self.dpos[0];
+        self.pos.y += self.dpos[1];
+        self.frame += 1;
+
+        // Filter out bullets which left the screen, unless they can bounce back.
+        if self.flags & FLAGS_STOP_AND_CHANGE != 0 {
+            self.was_visible = false;
+        } else if self.is_visible() {
+            self.was_visible = true;
+        } else if self.was_visible {
+            self.removed = true;
+            if self.flags & (FLAG_BOUNCE | FLAG_BOUNCE_NO_BOTTOM) != 0 && self.attributes.0 > 0 {
+                let mut angle = self.angle;
+                if self.pos.x < 0. || self.pos.x > GAME_WIDTH {
+                    angle = PI - angle;
+                    self.removed = false;
+                }
+                if self.pos.y < 0. || (self.flags & FLAG_BOUNCE != 0 && self.pos.y > GAME_HEIGHT) {
+                    angle = -angle;
+                    self.removed = false;
+                }
+                self.set_angle_and_speed(angle, self.speed);
+                self.attributes.0 -= 1;
+            }
+        }
+    }
+
+    fn update_flags(&mut self, target: Position) {
+        if self.flags & FLAG_SPEED_BURST != 0 {
+            if self.speed_interpolator.is_none() {
+                self.flags &= !FLAG_SPEED_BURST;
+            }
+        } else if self.flags & FLAG_ACCELERATION != 0 {
+            let (length, angle) = (self.attributes.4, self.attributes.5);
+            // TODO: is that right?
+            let angle = if angle < -900. { self.angle } else { angle };
+            let dx = self.dpos[0] + angle.cos() * length;
+            let dy = self.dpos[1] + angle.sin() * length;
+            self.speed = dx.hypot(dy);
+            self.angle = dy.atan2(dx);
+            self.dpos = [dx, dy, 0.];
+            if let Some(sprite) = &self.sprite {
+                sprite.borrow_mut().set_angle(self.sprite_angle(self.angle));
+            }
+            // TODO: include the last frame, or not?
+            if self.frame as i32 == self.attributes.0 {
+                self.flags &= !FLAG_ACCELERATION;
+            }
+        } else if self.flags & FLAG_ACCELERATE_AND_ROTATE != 0 {
+            let (acceleration, angular_speed) = (self.attributes.4, self.attributes.5);
+            self.set_angle_and_speed(self.angle + angular_speed, self.speed + acceleration);
+            if self.frame as i32 == self.attributes.0 {
+                self.flags &= !FLAG_ACCELERATE_AND_ROTATE;
+            }
+        } else if self.flags & FLAGS_STOP_AND_CHANGE != 0 {
+            let (interval, mut count) = (self.attributes.0.max(1) as u32, self.attributes.1);
+            let (angle, speed) = (self.attributes.4, self.attributes.5);
+            if self.frame.is_multiple_of(interval) {
+                count -= 1;
+
+                if self.frame != 0 {
+                    let speed = if speed < -900. { self.speed } else { speed };
+                    let angle = if self.flags & FLAG_CHANGE_ANGLE != 0 {
+                        self.angle + angle
+                    } else if self.flags & FLAG_AIM_AT_PLAYER != 0 {
+                        let offset = self.pos - target;
+                        offset.dy.atan2(offset.dx) + angle
+                    } else {
+                        angle
+                    };
+                    self.set_angle_and_speed(angle, speed);
+                }
+
+                if count >= 0 {
+                    self.interpolate_speed(self.speed, self.frame, 0., self.frame + interval - 1);
+                } else {
+                    self.flags &= !FLAGS_STOP_AND_CHANGE;
+                }
+
+                self.attributes.1 = count;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::th06::enemy::BulletAttributes;
+    use touhou_formats::th06::ecl::Rank;
+    use touhou_utils::prng::Prng;
+    use std::f32::consts::PI;
+
+    fn attributes(opcode: i16, bullets_per_shot: i16, number_of_shots: i16, launch_angle: f32, angle: f32) -> BulletAttributes {
+        BulletAttributes {
+            pos: Position::new(192., 0.),
+            bullet_type: opcode - 67,
+            bullets_per_shot,
+            number_of_shots,
+            speed: 1.,
+            speed2: 2.,
+            launch_angle,
+            angle,
+            ..Default::default()
+        }
+    }
+
+    fn fire(attributes: BulletAttributes) -> Vec<(f32, f32)> {
+        let prng = Rc::new(RefCell::new(Prng::new(0)));
+        let mut game = Game::new(prng, Rank::EASY);
+        attributes.fire(&mut game);
+        game.bullets.iter().map(|bullet| {
+            let bullet = bullet.borrow();
+            (bullet.angle, bullet.speed)
+        }).collect()
+    }
+
+    fn assert_close(bullets: &[(f32, f32)], expected: &[(f32, f32)]) {
+        assert_eq!(bullets.len(), expected.len());
+        for (&(angle, speed), &(expected_angle, expected_speed)) in bullets.iter().zip(expected.iter()) {
+            assert!((angle - expected_angle).abs() < 1e-5, "{} != {}", angle, expected_angle);
+            assert!((speed - expected_speed).abs() < 1e-5, "{} != {}", speed, expected_speed);
+        }
+    }
+
+    #[test]
+    fn aimed_fan() {
+        // The player starts right below this position.
+        let bullets = fire(attributes(67, 3, 2, 0., 0.1));
+        assert_close(&bullets, &[(PI / 2. - 0.1, 1.), (PI / 2., 1.), (PI / 2. + 0.1, 1.),
+                                 (PI / 2. - 0.1, 1.5), (PI / 2., 1.5), (PI / 2. + 0.1, 1.5)]);
+    }
+
+    #[test]
+    fn circle() {
+        // An even number of bullets gets shifted by half the spacing.
+        let bullets = fire(attributes(70, 4, 1, 0., 0.));
+        assert_close(&bullets, &[(PI / 4., 1.), (3. * PI / 4., 1.), (5. * PI / 4., 1.), (7. * PI / 4., 1.)]);
+    }
+
+    #[test]
+    fn random() {
+        let bullets = fire(attributes(75, 8, 1, 1., 0.5));
+        assert_eq!(bullets.len(), 8);
+        for (angle, speed) in bullets {
+            assert!((0.5..=1.).contains(&angle));
+            assert!((1. ..=2.).contains(&speed));
+        }
+    }
+
+    fn new_bullet(pos: Position, angle: f32, speed: f32, flags: u32, extended_attributes: (i32, i32, i32, i32, f32, f32, f32, f32)) -> Bullet {
+        let prng = Rc::new(RefCell::new(Prng::new(0)));
+        let game = Game::new(prng, Rank::EASY);
+        let attributes = BulletAttributes {
+            pos,
+            flags,
+            extended_attributes,
+            ..Default::default()
+        };
+        Bullet::new(&attributes, angle, speed, &game).unwrap()
+    }
+
+    const TARGET: Position = Position { x: 192., y: 384. };
+
+    #[test]
+    fn movement() {
+        let mut bullet = new_bullet(Position::new(10., 10.), PI, 2., 0, Default::default());
+        assert_eq!(bullet.state, BulletState::Launched);
+        bullet.update(TARGET);
+        assert!((bullet.pos.x - 8.).abs() < 1e-5 && (bullet.pos.y - 10.).abs() < 1e-5);
+        for _ in 0..5 {
+            bullet.update(TARGET);
+        }
+        assert!(!bullet.removed);
+        bullet.update(TARGET);
+        assert!(bullet.removed);
+    }
+
+    #[test]
+    fn unknown_type() {
+        let prng = Rc::new(RefCell::new(Prng::new(0)));
+        let game = Game::new(prng, Rank::EASY);
+        for anim in [-1, EOSD_BULLET_TYPES.len() as i16] {
+            let attributes = BulletAttributes {
+                anim,
+                ..Default::default()
+            };
+            assert!(Bullet::new(&attributes, 0., 1., &game).is_none());
+        }
+    }
+
+    #[test]
+    fn speed_burst() {
+        let mut bullet = new_bullet(Position::new(192., 224.), 0., 1., FLAG_SPEED_BURST, Default::default());
+        bullet.update(TARGET);
+        assert!((bullet.pos.x - 198.).abs() < 1e-5);
+        for _ in 0..20 {
+            bullet.update(TARGET);
+        }
+        assert_eq!(bullet.flags, 0);
+        assert!((bullet.dpos[0] - 1.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn acceleration() {
+        // Gravity, pulling the bullet downwards until its tenth frame.
+        let mut bullet = new_bullet(Position::new(192., 224.), 0., 1., FLAG_ACCELERATION, (10, 0, 0, 0, 0.1, PI / 2., 0., 0.));
+        for _ in 0..20 {
+            bullet.update(TARGET);
+        }
+        assert_eq!(bullet.flags, 0);
+        assert!((bullet.dpos[1] - 1.1).abs() < 1e-5);
+        assert!((bullet.angle - 1.1f32.atan2(1.)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn aim_at_player() {
+        // Stop after 10 frames then aim at the player, twice.
+        let mut bullet = new_bullet(Position::new(192., 224.), 0., 1., FLAG_AIM_AT_PLAYER, (10, 2, 0, 0, 0., 2., 0., 0.));
+        for _ in 0..10 {
+            bullet.update(TARGET);
+        }
+        assert!(bullet.dpos[0].abs() < 1e-5);
+        bullet.update(TARGET);
+        assert!((bullet.angle - PI / 2.).abs() < 0.05);
+        assert_eq!(bullet.speed, 2.);
+        for _ in 0..20 {
+            bullet.update(TARGET);
+        }
+        assert_eq!(bullet.attributes.1, -1);
+        assert_eq!(bullet.flags, 0);
+    }
+
+    #[test]
+    fn bounce() {
+        let mut bullet = new_bullet(Position::new(2., 224.), PI, 2., FLAG_BOUNCE, (1, 0, 0, 0, 0., 0., 0., 0.));
+        for _ in 0..5 {
+            bullet.update(TARGET);
+        }
+        assert!(!bullet.removed);
+        assert!(bullet.angle.abs() < 1e-5);
+        assert_eq!(bullet.attributes.0, 0);
+        for _ in 0..200 {
+            bullet.update(TARGET);
+        }
+        assert!(bullet.removed);
+    }
+}
@@ -1,7 +1,9 @@
 //! ECL runner.
 
 use touhou_formats::th06::ecl::{Ecl, SubInstruction};
-use crate::th06::enemy::{Enemy, Offset, BulletAttributes, Position};
+use crate::th06::bullet::{BulletState, FLAG_ACCELERATION};
+use crate::th06::enemy::{Enemy, Offset, BulletAttributes, DifficultyCoeffs, Position};
+use crate::th06::laser::LaserAttributes;
 use touhou_utils::prng::Prng;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -24,6 +26,32 @@ macro_rules! gen_SetBulletAttributes {
     }};
 }
 
+macro_rules! gen_NewLaser {
+    ($self:ident, $aimed:tt, $laser_type:ident, $sprite_index_offset:ident, $angle:ident, $speed:ident,
+     $start_offset:ident, $end_offset:ident, $max_length:ident, $width:ident,
+     $start_duration:ident, $duration:ident, $end_duration:ident, $grazing_delay:ident,
+     $grazing_extra_duration:ident) => {{
+        let attributes = LaserAttributes {
+            laser_type: $laser_type as usize,
+            sprite_index_offset: $sprite_index_offset as u32,
+            angle: $self.get_f32($angle),
+            speed: $speed,
+            start_offset: $start_offset,
+            end_offset: $end_offset,
+            max_length: $max_length,
+            width: $width,
+            start_duration: $start_duration as u32,
+            duration: $duration as u32,
+            end_duration: $end_duration as u32,
+            grazing_delay: $grazing_delay as u32,
+            grazing_extra_duration: $grazing_extra_duration as u32,
+        };
+
+        let mut enemy = $self.enemy.borrow_mut();
+        enemy.new_laser($aimed, attributes);
+    }};
+}
+
 #[derive(Clone, Default)]
 struct StackFrame {
     frame: i32,
@@ -695,7 +723,23 @@ impl EclRunner {
                 }
             }
 
-            // 85-86 ire newlaser functions
+            // 85
+            SubInstruction::NewLaser(laser_type, sprite_index_offset, angle, speed, start_offset,
+                                     end_offset, max_length, width, start_duration, duration,
+                                     end_duration, grazing_delay, grazing_extra_duration, _) => {
+                gen_NewLaser!(self, false, laser_type, sprite_index_offset, angle, speed,
+                              start_offset, end_offset, max_length, width, start_duration,
+                              duration, end_duration, grazing_delay, grazing_extra_duration);
+            }
+            // 86
+            SubInstruction::NewLaserTowardsPlayer(laser_type, sprite_index_offset, angle, speed,
+                                                  start_offset, end_offset, max_length, width,
+                                                  start_duration, duration, end_duration,
+                                                  grazing_delay, grazing_extra_duration, _) => {
+                gen_NewLaser!(self, true, laser_type, sprite_index_offset, angle, speed,
+                              start_offset, end_offset, max_length, width, start_duration,
+                              duration, end_duration, grazing_delay, grazing_extra_duration);
+            }
 
             // 87
             SubInstruction::SetUpcomingLaserId(laser_id) => {
@@ -704,15 +748,13 @@ impl EclRunner {
             }
 
             // 88
-            /*
             SubInstruction::AlterLaserAngle(laser_id, delta) => {
-                let mut enemy = self.enemy.borrow_mut();
-                if enemy.laser_by_id.contains_key(&laser_id) {
-                    let mut laser = enemy.laser_by_id.get(&laser_id);
-                    laser.angle += self.get_f32(delta);
+                let delta = self.get_f32(delta);
+                let enemy = self.enemy.borrow();
+                if let Some(laser) = enemy.laser_by_id.get(&laser_id) {
+                    laser.borrow_mut().angle += delta;
                 }
             }
-            */
 
             // 89
             /*
@@ -727,15 +769,12 @@ impl EclRunner {
             */
 
             // 90
-            /*
-            SubInstruction::RepositionLaser(laser_id, ox, oy, oz) => {
-                let mut enemy = self.enemy.borrow_mut();
-                if enemy.laser_by_id.contains_key(&laser_id) {
-                    let mut laser = enemy.laser_by_id.get(&laser_id);
-                    laser.set_base_pos(enemy.pos.x + ox, enemy.pos.y + oy, enemy.z + oz)
+            SubInstruction::RepositionLaser(laser_id, ox, oy, _oz) => {
+                let enemy = self.enemy.borrow();
+                if let Some(laser) = enemy.laser_by_id.get(&laser_id) {
+                    laser.borrow_mut().set_base_pos(enemy.pos.x + ox, enemy.pos.y + oy);
                 }
             }
-            */
             // 91
             // wat
             SubInstruction::LaserSetCompare(laser_id) => {
@@ -754,25 +793,26 @@ impl EclRunner {
             }
 
             // 92
-            /*
-            SubInstruction::RepositionLaser(laser_id, ox, oy, oz) => {
-                let mut enemy = self.enemy.borrow_mut();
-                if enemy.laser_by_id.contains_key(&laser_id) {
-                    let mut laser = enemy.laser_by_id.get(laser_id);
-                    laser.cancel();
+            SubInstruction::CancelLaser(laser_id) => {
+                let enemy = self.enemy.borrow();
+                if let Some(laser) = enemy.laser_by_id.get(&laser_id) {
+                    laser.borrow_mut().cancel();
                 }
             }
-            */
             // 93
-            // TODO: actually implement that hell
-            SubInstruction::SetSpellcard(face, number, name) => {
-                unimplemented!("spellcard start");
-
+            SubInstruction::SetSpellcard(face, number, name, _) => {
+                // TODO: change the bullets into star items, and show the name and effect.
+                let mut enemy = self.enemy.borrow_mut();
+                enemy.difficulty_coeffs = DifficultyCoeffs::default();
+                let game = enemy.game.upgrade().unwrap();
+                game.borrow_mut().spellcard = Some((number, name, face));
             }
             // 94
             SubInstruction::EndSpellcard() => {
-                unimplemented!("spellcard end");
-
+                // TODO: change the bullets into star items, and give the bonus.
+                let enemy = self.enemy.borrow();
+                let game = enemy.game.upgrade().unwrap();
+                game.borrow_mut().spellcard = None;
             }
 
             // 95
@@ -976,12 +1016,14 @@ impl EclRunner {
                                 bullet.speed = 0.;
                                 bullet.dpos = [0., 0., 0.];
                             } else if arg == 1 {
-                                bullet.flags |= 0x10;
+                                bullet.flags |= FLAG_ACCELERATION;
                                 bullet.frame = 220;
                                 let rand_angle = game.prng.borrow_mut().get_f64() * 2. * std::f64::consts::PI - std::f64::consts::PI;
-                                bullet.attributes[0] = (rand_angle.cos() * 0.01) as f32;
-                                bullet.attributes[1] = (rand_angle.sin() * 0.01) as f32;
+                                bullet.attributes.4 = 0.01;
+                                bullet.attributes.5 = rand_angle as f32;
                             }
+                            //TODO: check
+                            bullet.set_anim(Some(15));
                         }
                     }
                     1 => {
@@ -990,9 +1032,9 @@ impl EclRunner {
                         let rand_x = self.get_prng().borrow_mut().get_f64();
                         let rand_y = self.get_prng().borrow_mut().get_f64();
                         let mut enemy = self.enemy.borrow_mut();
-                        let pos = [rand_x * range_x + enemy.pos.x as f64 - range_x / 2.,
-                                   rand_y * range_y + enemy.pos.x as f64 - range_y / 2.];
-                        enemy.bullet_attributes.fire();
+                        let pos = Position::new((rand_x * range_x + enemy.pos.x as f64 - range_x / 2.) as f32,
+                                                (rand_y * range_y + enemy.pos.y as f64 - range_y / 2.) as f32);
+                        enemy.fire_from(pos);
                     }
                     3 => { // Patchouli’s dual sign spellcard selector
                         let mut enemy = self.enemy.borrow_mut();
@@ -1115,15 +1157,14 @@ impl EclRunner {
                             let enemy = self.enemy.borrow();
                             let game = enemy.game.upgrade().unwrap();
                             let mut game = game.borrow_mut();
-                            let mut n = 0;
+                            let mut fired = vec![];
                             for bullet in game.bullets.iter() {
-                                let mut bullet = bullet.borrow();
-                                // TODO: uncomment that one.
-                                if bullet.state != 0 && bullet.state != 5 /* && (30. <= (bullet.sprites[0].additional_infos).height) */ {
+                                let bullet = bullet.borrow();
+                                if bullet.state != BulletState::Cancelled && bullet.is_large() {
                                     let prng = enemy.prng.upgrade().unwrap();
                                     let random = prng.borrow_mut().get_f64();
                                     let launch_angle = (random * (2. * std::f64::consts::PI) - std::f64::consts::PI) as f32;
-                                    let mut attribs = BulletAttributes {
+                                    let attribs = BulletAttributes {
                                         // TODO: check if the z value of this pos is really used.
                                         pos: bullet.pos,
                                         anim: 3,
@@ -1139,11 +1180,13 @@ impl EclRunner {
                                         extended_attributes: Default::default(),
                                         sound: None,
                                     };
-                                    attribs.fire();
-                                    n += 1
+                                    fired.push(attribs);
                                 }
                             }
-                            n
+                            for attribs in fired.iter() {
+                                attribs.fire(&mut game);
+                            }
+                            fired.len() as i32
                         };
                         //TODO: this variable might not always be correct! it uses the argument in
                         //th06: *(int *)(param_1 + 0x9b0) = local_60;
@@ -1151,55 +1194,59 @@ impl EclRunner {
                     }
 
                     9 => {
-                        let mut rnd = self.get_prng().borrow_mut().get_f64();
+                        let rnd = self.get_prng().borrow_mut().get_f64();
                         //TODO: the game does that
                         //drop_particle(&PARTICLES_ARRAY,0xc,enemy->pos,1,0xffffffff);
                         //self._game.new_effect((enemy.x, enemy.y), 17)
-                        /*
-                        for bullet in game.bullets {
-                            if bullet._bullet_type.state != 0 && bullet._bullet_type.state != 5 && (30. <= (bullet.sprites[0].additional_infos)->height) && bullet.speed == 0. {
-                                bullet.flags = bullet.flags | 0x10;
+                        let enemy = self.enemy.borrow();
+                        let game = enemy.game.upgrade().unwrap();
+                        let game = game.borrow();
+                        for bullet in game.bullets.iter() {
+                            let mut bullet = bullet.borrow_mut();
+                            if bullet.state != BulletState::Cancelled && bullet.is_large() && bullet.speed == 0. {
+                                bullet.flags |= FLAG_ACCELERATION;
                                 //TODO: reverse this field and effect
-                                bullet->field_0x5ba = 2;
-                                new_effect(GAME_OBJECT,(sprite *)bullet, (int)bullet->sprites[0].sometimes_copy_of_UNK1 + (int)bullet->field_0x5ba);
-                                bullet.speed=0.01;
-                                bullet.frame=0x78;
-
-                                let mut dx = bullet.x - enemy.x;
-                                let mut distance = dx.hypot(bullet.y - enemy.y);
+                                //bullet->field_0x5ba = 2;
+                                //new_effect(GAME_OBJECT,(sprite *)bullet, (int)bullet->sprites[0].sometimes_copy_of_UNK1 + (int)bullet->field_0x5ba);
+                                bullet.speed = 0.01;
+                                bullet.frame = 0x78;
 
+                                let offset = enemy.pos - bullet.pos;
+                                let mut distance = offset.dx.hypot(offset.dy) as f64;
                                 if distance > 0.01 {
-                                    distance = sqrt(distance);
-                                }else{distance = 0.;}
-                                let mut angle = (distance * std::f64::consts::PI) / 256. + (rnd * (2*std::f64::consts::PI) - std::f64::consts::PI);
-                                bullet->attributes[0] = cos(angle) * 0.01000000;
-                                bullet->attributes[1] = sin(angle) * 0.01000000;
+                                    distance = distance.sqrt();
+                                } else {
+                                    distance = 0.;
+                                }
+                                let angle = (distance * std::f64::consts::PI) / 256. + (rnd * (2. * std::f64::consts::PI) - std::f64::consts::PI);
+                                bullet.attributes.4 = 0.01;
+                                bullet.attributes.5 = angle as f32;
                             }
                         }
-                        */
                     }
                     11 => {
-                        self.get_prng().borrow_mut().get_f64();
+                        let prng = self.get_prng();
+                        prng.borrow_mut().get_f64();
                         //TODO: the game does that
                         //drop_particle(&PARTICLES_ARRAY,0xc,enemy->pos,1,0xffffffff);
                         //self._game.new_effect((enemy.x, enemy.y), 17)
-                        /*
-                        for bullet in game.bullets {
-                            if bullet._bullet_type.state != 0 && bullet._bullet_type.state != 5 && (30. <= (bullet.sprites[0].additional_infos)->height) && bullet.speed == 0. {
-                                bullet.flags = bullet.flags | 0x10;
+                        let enemy = self.enemy.borrow();
+                        let game = enemy.game.upgrade().unwrap();
+                        let game = game.borrow();
+                        for bullet in game.bullets.iter() {
+                            let mut bullet = bullet.borrow_mut();
+                            if bullet.state != BulletState::Cancelled && bullet.is_large() && bullet.speed == 0. {
+                                bullet.flags |= FLAG_ACCELERATION;
                                 //TODO: reverse this field and effect
-                                bullet->field_0x5ba = 2;
-                                new_effect(GAME_OBJECT,(sprite *)bullet, (int)bullet->sprites[0].sometimes_copy_of_UNK1 + (int)bullet->field_0x5ba);
-                                bullet.speed=0.01;
-                                bullet.frame=0x78;
-                                let mut angle = self.get_prng().borrow_mut().get_f64() * (2*std::f64::consts::PI) - std::f64::consts::PI;
-                                bullet->attributes[0] = cos(angle) * 0.01000000;
-                                bullet->attributes[1] = sin(angle) * 0.01000000;
-
-
+                                //bullet->field_0x5ba = 2;
+                                //new_effect(GAME_OBJECT,(sprite *)bullet, (int)bullet->sprites[0].sometimes_copy_of_UNK1 + (int)bullet->field_0x5ba);
+                                bullet.speed = 0.01;
+                                bullet.frame = 0x78;
+                                let angle = prng.borrow_mut().get_f64() * (2. * std::f64::consts::PI) - std::f64::consts::PI;
+                                bullet.attributes.4 = 0.01;
+                                bullet.attributes.5 = angle as f32;
                             }
                         }
-                        */
                     }
                     13 => {
                         if self.frame.ints1[3] % 6 == 0 {
@@ -1226,13 +1273,15 @@ impl EclRunner {
                     14 => { // Lävatein
                         let mut enemy = self.enemy.borrow_mut();
                         self.frame.ints1[3] = 0;
+                        let mut positions = vec![];
                         for laser in enemy.laser_by_id.values() {
-                            //for pos in laser.get_bullets_pos(){
                             //TODO: the game checks for laser end_offset before firing
-                            //  enemy.fire(launch_pos=pos);
-                            //}
+                            positions.extend(laser.borrow().get_bullets_pos());
                             self.frame.ints1[3] += 1;
                         }
+                        for pos in positions {
+                            enemy.fire_from(pos);
+                        }
                     }
                     16 => { // QED: Ripples of 495 years
                         let mut enemy = self.enemy.borrow_mut();
@@ -1278,6 +1327,9 @@ impl EclRunner {
                     _ => unimplemented!("Special function {:?} not found!", function)
                 }
             }
+            SubInstruction::Unknown { .. } => {
+                // Unknown to us, kept by the parser but skipped here.
+            }
             _ => unimplemented!("{:?}", instruction)
         }
 
@@ -1287,30 +1339,22 @@ impl EclRunner {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::th06::anm0::Anm0;
-    use crate::th06::ecl::{Sub, CallSub, Rank};
+    use touhou_formats::th06::ecl::{Sub, CallSub, Rank};
     use crate::th06::enemy::Game;
-    use std::io::{self, Read};
-    use std::fs::File;
+    use std::rc::Weak;
 
     fn setup() -> (Rc<RefCell<Game>>, Rc<RefCell<Enemy>>) {
-        let file = File::open("EoSD/ST/stg1enm.anm").unwrap();
-        let mut file = io::BufReader::new(file);
-        let mut buf = vec![];
-        file.read_to_end(&mut buf).unwrap();
-        let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
-        let anm0 = anms.pop().unwrap();
-        let anm0 = Rc::new(RefCell::new(anm0));
         let prng = Rc::new(RefCell::new(Prng::new(0)));
         let game = Game::new(prng, Rank::EASY);
         let game = Rc::new(RefCell::new(game));
-        let enemy = Enemy::new(Position::new(0., 0.), 500, 0, 640, Rc::downgrade(&anm0), Rc::downgrade(&game));
+        // Calls and returns don’t need any animation.
+        let enemy = Enemy::new(Position::new(0., 0.), 500, 0, 640, false, Weak::new(), Rc::downgrade(&game));
         (game, enemy)
     }
 
     #[test]
     fn call_and_return() {
-        let (game, enemy) = setup();
+        let (_game, enemy) = setup();
         let ecl = Ecl { mains: vec![], subs: vec![
             Sub { instructions: vec![
                 CallSub::new(0, Rank::EASY, SubInstruction::Call(1, 13, 12.)),
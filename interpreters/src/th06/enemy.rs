@@ -2,11 +2,16 @@
 
 use touhou_formats::th06::anm0::Anm0;
 use touhou_formats::th06::ecl::Rank;
+use touhou_formats::th06::sht::Sht;
 use crate::th06::anm0::{Sprite, AnmRunner};
+use crate::th06::bullet::{Bullet, BulletState, BulletType, EOSD_BULLET_TYPES};
 use crate::th06::interpolator::{Interpolator1, Interpolator2};
+use crate::th06::laser::{Laser, LaserAttributes, LaserType, PlayerLaser, EOSD_LASER_TYPES};
+use crate::th06::player::Player;
 use touhou_utils::prng::Prng;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::f32::consts::PI;
 use std::rc::{Rc, Weak};
 
 /// The 2D position of an object in the game.
@@ -59,51 +64,45 @@ impl std::ops::Sub<Position> for Position {
 
 type Callback = i32;
 
-#[derive(Debug, Clone)]
-/// XXX
-pub struct Laser {
-    /// XXX
-    pub placeholder: u32
-}
-
 #[derive(Debug, Clone, Default)]
 struct Process;
 
-/// Struct representing the player.
-pub struct Player {
-    pos: Position,
-}
-
-/// Struct representing an enemy bullet.
-pub struct Bullet {
-    /// Current position of the bullet.
-    pub pos: Position,
-
-    /// Current speed of the bullet.
-    pub speed: f32,
+/// Width of the playfield, in pixels.
+pub(crate) const GAME_WIDTH: f32 = 384.;
 
-    /// Current XXX of the bullet.
-    pub dpos: [f32; 3],
+/// Height of the playfield, in pixels.
+pub(crate) const GAME_HEIGHT: f32 = 448.;
 
-    /// Current XXX of the bullet.
-    pub flags: u32,
+/// Most damage an enemy can take in a single frame.
+const MAX_DAMAGES: u32 = 70;
 
-    /// Current frame of the bullet.
-    pub frame: i32,
+/// Score earned by grazing a bullet or a laser.
+const GRAZE_SCORE: u32 = 500;
 
-    /// Current attributes of the bullet.
-    pub attributes: [f32; 2],
-
-    /// TODO: what are the values?
-    pub state: i8,
+/// Whether these two boxes, given by their centers and half sizes, overlap.
+fn boxes_overlap(a: Position, a_half_size: [f32; 2], b: Position, b_half_size: [f32; 2]) -> bool {
+    !(a.x + a_half_size[0] < b.x - b_half_size[0]
+      || a.x - a_half_size[0] > b.x + b_half_size[0]
+      || a.y + a_half_size[1] < b.y - b_half_size[1]
+      || a.y - a_half_size[1] > b.y + b_half_size[1])
 }
 
+/// Maximum number of enemy bullets alive at the same time, and of player bullets too.
+pub(crate) const MAX_BULLETS: usize = 640;
+
 /// God struct of our game.
 pub struct Game {
     enemies: Vec<Rc<RefCell<Enemy>>>,
     anmrunners: Vec<Rc<RefCell<AnmRunner>>>,
     pub(crate) bullets: Vec<Rc<RefCell<Bullet>>>,
+    pub(crate) bullet_types: Vec<BulletType>,
+    pub(crate) bullet_anms: Vec<Rc<RefCell<[Anm0]>>>,
+    pub(crate) lasers: Vec<Rc<RefCell<Laser>>>,
+    pub(crate) laser_types: Vec<LaserType>,
+    pub(crate) player_bullets: Vec<Rc<RefCell<Bullet>>>,
+    pub(crate) player_lasers: [Option<PlayerLaser>; 2],
     player: Rc<RefCell<Player>>,
+    pub(crate) spellcard: Option<(i16, String, i16)>,
     pub(crate) prng: Rc<RefCell<Prng>>,
     rank: Rank,
     difficulty: i32,
@@ -116,15 +115,26 @@ impl Game {
             enemies: Vec::new(),
             anmrunners: Vec::new(),
             bullets: Vec::new(),
-            player: Rc::new(RefCell::new(Player { pos: Position { x: 192., y: 384. } })),
+            bullet_types: EOSD_BULLET_TYPES.to_vec(),
+            bullet_anms: Vec::new(),
+            lasers: Vec::new(),
+            laser_types: EOSD_LASER_TYPES.to_vec(),
+            player_bullets: Vec::new(),
+            player_lasers: [None, None],
+            player: Rc::new(RefCell::new(Player::new(Rc::downgrade(&prng)))),
+            spellcard: None,
             prng,
             rank,
             difficulty: 0,
         }
     }
 
-    /// Run the simulation for a single frame.
-    pub fn run_frame(&mut self) {
+    /// Run the simulation for a single frame, with the player holding these keys, see the
+    /// `KEY_*` constants of the player module.
+    ///
+    /// Enemies aren’t moved here, their ECL runners and `Enemy::update` have to be run before,
+    /// since enemies borrow the game to fire.
+    pub fn run_frame(&mut self, keystate: u16) {
         /*
         for eclrunner in self.eclrunners {
             eclrunner.run_frame();
@@ -135,6 +145,185 @@ impl Game {
             let mut anmrunner = anmrunner.borrow_mut();
             anmrunner.run_frame();
         }
+
+        for bullet in self.player_bullets.iter() {
+            bullet.borrow_mut().update(Position::default());
+        }
+        self.player_bullets.retain(|bullet| !bullet.borrow().removed);
+
+        {
+            let player = self.player.borrow();
+            for laser in self.player_lasers.iter_mut().flatten() {
+                let origin = player.get_orb_pos(laser.orb).unwrap_or(player.pos);
+                laser.update(origin);
+            }
+        }
+        for laser in self.player_lasers.iter_mut() {
+            if laser.as_ref().is_some_and(|laser| laser.removed) {
+                *laser = None;
+            }
+        }
+
+        let player = self.player.clone();
+        player.borrow_mut().update(keystate, self);
+
+        self.check_enemy_collisions();
+
+        let target = player.borrow().pos;
+        for bullet in self.bullets.iter() {
+            bullet.borrow_mut().update(target);
+        }
+
+        self.check_player_collisions();
+        self.bullets.retain(|bullet| !bullet.borrow().removed);
+
+        for laser in self.lasers.iter() {
+            laser.borrow_mut().update();
+        }
+        self.lasers.retain(|laser| !laser.borrow().removed);
+    }
+
+    /// Check the enemies against the bullets and lasers of the player, which damage them, and
+    /// against the player itself.
+    fn check_enemy_collisions(&mut self) {
+        let mut player = self.player.borrow_mut();
+        let player_half_size = [player.hitbox, player.hitbox];
+
+        for enemy in self.enemies.iter() {
+            let mut enemy = enemy.borrow_mut();
+            // Like in the original game, untouchable enemies don’t collide with anything.
+            if enemy.removed || !enemy.touchable {
+                continue;
+            }
+            let half_size = enemy.hitbox_half_size;
+            let mut damages = 0;
+
+            for bullet in self.player_bullets.iter() {
+                let mut bullet = bullet.borrow_mut();
+                if bullet.state != BulletState::Launched {
+                    continue;
+                }
+                if boxes_overlap(bullet.pos, bullet.hitbox, enemy.pos, half_size) {
+                    bullet.cancel();
+                    damages += bullet.damage as u32;
+                }
+            }
+
+            // Lasers hurt the enemy on each frame it touches them.
+            for laser in self.player_lasers.iter().flatten() {
+                if laser.check_collision(enemy.pos, half_size) {
+                    damages += laser.damage as u32;
+                }
+            }
+
+            // Only two thirds of the hitbox of an enemy hurt the player.
+            // TODO: box-box or point-in-box?
+            let body_half_size = [half_size[0] * 2. / 3., half_size[1] * 2. / 3.];
+            if enemy.collidable && boxes_overlap(enemy.pos, body_half_size, player.pos, player_half_size) {
+                if !enemy.boss {
+                    damages += 10;
+                }
+                player.collide();
+            }
+
+            let damages = damages.min(MAX_DAMAGES);
+            player.score += damages / 5 * 10;
+
+            if enemy.damageable {
+                let damages = if self.spellcard.is_none() {
+                    damages
+                } else if damages <= 7 {
+                    // TODO: there is a division by 3 somewhere, find where.
+                    damages.min(1)
+                } else {
+                    damages / 7
+                };
+                enemy.life = enemy.life.saturating_sub(damages);
+            }
+        }
+    }
+
+    /// Check the player against enemy lasers and bullets, getting hit by them or grazing them.
+    fn check_player_collisions(&mut self) {
+        let mut player = self.player.borrow_mut();
+        if !player.touchable {
+            return;
+        }
+        let pos = player.pos;
+        let half_size = [player.hitbox, player.hitbox];
+        let graze_half_size = [player.graze_hitbox, player.graze_hitbox];
+
+        for laser in self.lasers.iter() {
+            let laser = laser.borrow();
+            if laser.check_collision(pos) {
+                player.collide();
+            } else if laser.check_grazing(pos) {
+                // TODO: raise the difficulty by 6.
+                player.graze += 1;
+                player.score += GRAZE_SCORE;
+            }
+        }
+
+        for bullet in self.bullets.iter() {
+            let mut bullet = bullet.borrow_mut();
+            if bullet.state != BulletState::Launched {
+                continue;
+            }
+            if boxes_overlap(bullet.pos, bullet.hitbox, pos, half_size) {
+                bullet.cancel();
+                player.collide();
+            } else if !bullet.grazed && boxes_overlap(bullet.pos, bullet.hitbox, pos, graze_half_size) {
+                // Each bullet can only be grazed once.
+                // TODO: raise the difficulty by 6.
+                bullet.grazed = true;
+                player.graze += 1;
+                player.score += GRAZE_SCORE;
+            }
+        }
+    }
+
+    /// Set the ANMs containing the scripts of the bullet and laser types, indexed by
+    /// `BulletType::anm` and `LaserType::anm`.
+    ///
+    /// Until then, bullets and lasers still move but don’t have any sprite.
+    pub fn set_bullet_anms(&mut self, anms: Vec<Anm0>) {
+        self.bullet_anms = anms.into_iter()
+            .map(|anm0| Rc::new(RefCell::new([anm0])) as Rc<RefCell<[Anm0]>>)
+            .collect();
+    }
+
+    /// Set the unfocused and focused shots of the player.
+    pub fn set_player_shts(&mut self, sht: Sht, focused_sht: Sht) {
+        self.player.borrow_mut().set_shts(sht, focused_sht);
+    }
+
+    /// Set the ANM of the player, containing its animations and those of its bullets.
+    ///
+    /// Until then, the player and its bullets don’t have any sprite.
+    pub fn set_player_anm(&mut self, anm: Anm0) {
+        self.player.borrow_mut().set_anm(anm);
+    }
+
+    /// Whether the player ran out of lives and continues.
+    pub fn is_game_over(&self) -> bool {
+        self.player.borrow().is_game_over()
+    }
+
+    /// Cancel all enemy bullets and lasers.
+    pub(crate) fn cancel_bullets(&mut self) {
+        for bullet in self.bullets.iter() {
+            bullet.borrow_mut().cancel();
+        }
+        for laser in self.lasers.iter() {
+            laser.borrow_mut().cancel();
+        }
+    }
+
+    /// Cancel the lasers of the player, once it dies.
+    pub(crate) fn cancel_player_lasers(&mut self) {
+        for laser in self.player_lasers.iter_mut().flatten() {
+            laser.cancel();
+        }
     }
 
     /// Returns a list of all sprites currently being displayed on screen.
@@ -150,6 +339,53 @@ impl Game {
         sprites
     }
 
+    /// Returns a list of the sprites of all lasers and bullets whose type uses this bullet ANM.
+    pub fn get_bullet_sprites(&self, anm: usize) -> Vec<(f32, f32, f32, Rc<RefCell<Sprite>>)> {
+        let mut sprites = vec![];
+        for laser in self.lasers.iter() {
+            let laser = laser.borrow();
+            if laser.laser_type.anm != anm {
+                continue;
+            }
+            if let Some(sprite) = laser.get_sprite() {
+                let pos = laser.get_pos();
+                sprites.push((pos.x, pos.y, 0., sprite));
+            }
+            if let Some((pos, sprite)) = laser.get_launch_sprite() {
+                sprites.push((pos.x, pos.y, 0., sprite));
+            }
+        }
+        for bullet in self.bullets.iter() {
+            let bullet = bullet.borrow();
+            if bullet.bullet_type.anm != anm {
+                continue;
+            }
+            if let Some(sprite) = bullet.get_sprite() {
+                sprites.push((bullet.pos.x, bullet.pos.y, 0., sprite));
+            }
+        }
+        sprites
+    }
+
+    /// Returns a list of the sprites of the player, its options, its lasers and its bullets, all
+    /// using the ANM of the player.
+    pub fn get_player_sprites(&self) -> Vec<(f32, f32, f32, Rc<RefCell<Sprite>>)> {
+        let mut sprites = self.player.borrow().get_sprites();
+        for laser in self.player_lasers.iter().flatten() {
+            if let Some(sprite) = laser.get_sprite() {
+                let pos = laser.get_pos();
+                sprites.push((pos.x, pos.y, 0., sprite));
+            }
+        }
+        for bullet in self.player_bullets.iter() {
+            let bullet = bullet.borrow();
+            if let Some(sprite) = bullet.get_sprite() {
+                sprites.push((bullet.pos.x, bullet.pos.y, 0., sprite));
+            }
+        }
+        sprites
+    }
+
     // TODO: Fix this function so we can stop making Game::bullets pub.
     /*
     /// Apply a function on all bullets.
@@ -219,8 +455,81 @@ pub(crate) struct BulletAttributes {
 
 impl BulletAttributes {
     /// Fire!
-    pub fn fire(&mut self) {
-        println!("PAN!");
+    ///
+    /// Spawns `number_of_shots` shots of `bullets_per_shot` bullets each from `pos`, spread
+    /// according to the aim mode in `bullet_type`, which is the opcode of the SetBulletAttributes
+    /// instruction minus 67:
+    /// - 0: fan aimed at the player,
+    /// - 1: fan,
+    /// - 2: circle aimed at the player, offset by half the spacing for even numbers of bullets,
+    /// - 3: circle, offset by half the spacing for even numbers of bullets,
+    /// - 4: circle aimed at the player, offset by half the spacing for odd numbers of bullets,
+    /// - 7: circle with random speeds,
+    /// - 8: bullets with random angles between `angle` and `launch_angle`, and random speeds.
+    ///
+    /// Every shot after the first one gets faster, towards `speed2`.
+    pub fn fire(&self, game: &mut Game) {
+        let opcode = self.bullet_type + 67;
+        let bullets_per_shot = self.bullets_per_shot.max(1);
+        let number_of_shots = self.number_of_shots.max(1);
+
+        let mut speed = self.speed;
+        if speed < 0.3 && speed != 0. {
+            speed = 0.3;
+        }
+        let speed2 = self.speed2.max(0.3);
+        let angle = self.angle;
+
+        let mut launch_angle = self.launch_angle;
+        if let 67 | 69 | 71 = opcode {
+            let offset = self.pos - game.player.borrow().pos;
+            launch_angle += offset.dy.atan2(offset.dx);
+        }
+        if (opcode == 71 && bullets_per_shot % 2 == 1) || ((opcode == 69 || opcode == 70) && bullets_per_shot % 2 == 0) {
+            launch_angle += PI / bullets_per_shot as f32;
+        }
+        if opcode != 75 {
+            launch_angle -= angle * (bullets_per_shot - 1) as f32 / 2.;
+        }
+        let is_circle = matches!(opcode, 69 | 70 | 71 | 74);
+
+        for shot in 0..number_of_shots {
+            let mut shot_speed = if shot == 0 {
+                speed
+            } else {
+                speed + (speed2 - speed) * shot as f32 / number_of_shots as f32
+            };
+            let mut bullet_angle = launch_angle;
+            if is_circle {
+                launch_angle += angle;
+            }
+            for _ in 0..bullets_per_shot {
+                if game.bullets.len() >= MAX_BULLETS {
+                    return;
+                }
+
+                // 102h.exe@0x4138cf
+                if opcode == 75 {
+                    bullet_angle = game.prng.borrow_mut().get_f64() as f32 * (launch_angle - angle) + angle;
+                }
+                if opcode == 74 || opcode == 75 {
+                    shot_speed = game.prng.borrow_mut().get_f64() as f32 * (speed - speed2) + speed2;
+                }
+
+                // Bullets of an unknown type are never fired.
+                let bullet = match Bullet::new(self, bullet_angle, shot_speed, game) {
+                    Some(bullet) => bullet,
+                    None => return,
+                };
+                game.bullets.push(Rc::new(RefCell::new(bullet)));
+
+                if is_circle {
+                    bullet_angle += 2. * PI / bullets_per_shot as f32;
+                } else {
+                    bullet_angle += angle;
+                }
+            }
+        }
     }
 }
 
@@ -297,7 +606,7 @@ pub struct Enemy {
     pub(crate) timeout_callback: Option<Callback>,
 
     // Laser.
-    pub(crate) laser_by_id: HashMap<u32, Laser>,
+    pub(crate) laser_by_id: HashMap<u32, Rc<RefCell<Laser>>>,
 
     // Options.
     // TODO: actually a 8 element array.
@@ -365,6 +674,8 @@ impl Enemy {
     }
 
     /// Defines the attributes for the next bullet fired, and fire it if delay_attack isn’t set!
+    // Each argument comes straight from the ECL instructions 67 to 75.
+    #[allow(clippy::too_many_arguments)]
     pub fn set_bullet_attributes(&mut self, opcode: u16, anim: i16, sprite_index_offset: i16,
                                  bullets_per_shot: i16, number_of_shots: i16, speed: f32,
                                  speed2: f32, launch_angle: f32, angle: f32, flags: u32) {
@@ -374,11 +685,10 @@ impl Enemy {
         let coeff_shots = self.difficulty_coeffs.shots_a + (self.difficulty_coeffs.shots_b - self.difficulty_coeffs.shots_a) * difficulty / 32;
         let coeff_speed = self.difficulty_coeffs.speed_a + (self.difficulty_coeffs.speed_b - self.difficulty_coeffs.speed_a) * difficulty as f32 / 32.;
 
-        let opcode = 67;
-        let mut bullet = &mut self.bullet_attributes;
+        let bullet = &mut self.bullet_attributes;
 
         bullet.anim = anim;
-        bullet.bullet_type = opcode - 67;
+        bullet.bullet_type = opcode as i16 - 67;
         bullet.sprite_index_offset = sprite_index_offset;
 
         bullet.bullets_per_shot = bullets_per_shot + coeff_nb;
@@ -394,7 +704,7 @@ impl Enemy {
         bullet.pos = self.pos + self.bullet_offset;
 
         bullet.speed = speed + coeff_speed;
-        if bullet.speed < 0.3 {
+        if bullet.speed < 0.3 && bullet.speed != 0. {
             bullet.speed = 0.3;
         }
 
@@ -403,15 +713,47 @@ impl Enemy {
             bullet.speed2 = 0.3;
         }
 
-        bullet.launch_angle = launch_angle.atan2(0.);
+        bullet.launch_angle = launch_angle;
         bullet.angle = angle;
         bullet.flags = flags;
 
         if !self.delay_attack {
-            bullet.fire();
+            self.fire();
         }
     }
 
+    /// Fire the current bullet attributes from the launch offset of this enemy.
+    pub fn fire(&mut self) {
+        let pos = self.pos + self.bullet_offset;
+        self.fire_from(pos);
+    }
+
+    /// Fire the current bullet attributes from this position.
+    pub(crate) fn fire_from(&mut self, pos: Position) {
+        self.bullet_attributes.pos = pos;
+        self.bullet_launch_timer = 0;
+        let game = self.game.upgrade().unwrap();
+        self.bullet_attributes.fire(&mut game.borrow_mut());
+    }
+
+    /// Fire a laser from the launch offset of this enemy, aimed at the player if `aimed` is set,
+    /// and remember it under the current laser id.
+    pub(crate) fn new_laser(&mut self, aimed: bool, mut attributes: LaserAttributes) {
+        let pos = self.pos + self.bullet_offset;
+        let game = self.game.upgrade().unwrap();
+        let mut game = game.borrow_mut();
+        if aimed {
+            let offset = pos - game.player.borrow().pos;
+            attributes.angle += offset.dy.atan2(offset.dx);
+        }
+        let laser = match Laser::new(pos, &attributes, &game) {
+            Some(laser) => Rc::new(RefCell::new(laser)),
+            None => return,
+        };
+        game.lasers.push(laser.clone());
+        self.laser_by_id.insert(self.current_laser_id, laser);
+    }
+
     /// Sets the bullet launch interval.
     pub(crate) fn set_bullet_launch_interval(&mut self, rand_start: u32, interval: i32) {
         let coeff_interval = interval / 5;
@@ -489,12 +831,10 @@ impl Enemy {
         self.pos = Position { x, y };
 
         if self.bullet_launch_interval != 0 {
-            if self.bullet_launch_timer == 0 {
-                self.bullet_attributes.fire();
-                self.bullet_launch_timer = self.bullet_launch_interval;
-            }
             self.bullet_launch_timer += 1;
-            self.bullet_launch_timer %= self.bullet_launch_interval;
+            if self.bullet_launch_timer == self.bullet_launch_interval {
+                self.fire();
+            }
         }
 
         self.frame += 1;
@@ -548,21 +888,21 @@ mod tests {
         let mut file = io::BufReader::new(file);
         let mut buf = vec![];
         file.read_to_end(&mut buf).unwrap();
-        let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
+        let mut anms = Anm0::from_slice(&buf).unwrap();
         let anm0 = anms.pop().unwrap();
 
         let file = File::open("EoSD/ST/stg1enm2.anm").unwrap();
         let mut file = io::BufReader::new(file);
         let mut buf = vec![];
         file.read_to_end(&mut buf).unwrap();
-        let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
+        let mut anms = Anm0::from_slice(&buf).unwrap();
         let anm0_bis = anms.pop().unwrap();
 
         let anm0 = Rc::new(RefCell::new([anm0, anm0_bis]));
         let prng = Rc::new(RefCell::new(Prng::new(0)));
         let game = Game::new(prng, Rank::EASY);
         let game = Rc::new(RefCell::new(game));
-        let enemy = Enemy::new(Position::new(0., 0.), 500, 0, 640, Rc::downgrade(&anm0), Rc::downgrade(&game));
+        let enemy = Enemy::new(Position::new(0., 0.), 500, 0, 640, false, Rc::downgrade(&anm0), Rc::downgrade(&game));
         let mut enemy = enemy.borrow_mut();
         assert!(enemy.anmrunner.upgrade().is_none());
         enemy.set_anim(0);
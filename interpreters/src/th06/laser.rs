@@ -0,0 +1,574 @@
+//! Module providing the Laser and PlayerLaser structs, for the lasers fired by enemies and by the
+//! player.
+
+use touhou_formats::th06::anm0::Anm0;
+use touhou_formats::th06::sht::Shot;
+use crate::th06::anm0::{Sprite, AnmRunner};
+use crate::th06::enemy::{Game, Position};
+use touhou_utils::prng::Prng;
+use std::cell::RefCell;
+use std::f32::consts::PI;
+use std::rc::{Rc, Weak};
+
+/// The animation of a kind of laser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaserType {
+    /// Index of the bullet ANM containing the script of this type.
+    pub anm: usize,
+
+    /// Script used for the body of the laser.
+    pub anim_index: u8,
+
+    /// Sprite shown at the origin of the laser while it grows.
+    pub launch_sprite_index: u32,
+
+    /// Offset added to `launch_sprite_index`, for each sprite index offset of the laser.
+    pub launch_anim_offsets: &'static [u32],
+}
+
+/// Laser types of EoSD, both coming from etama3.anm.
+pub const EOSD_LASER_TYPES: [LaserType; 2] = [
+    LaserType {
+        anm: 0,
+        anim_index: 9,
+        launch_sprite_index: 140,
+        launch_anim_offsets: &[0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4, 0],
+    },
+    LaserType {
+        anm: 0,
+        anim_index: 10,
+        launch_sprite_index: 140,
+        launch_anim_offsets: &[0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4, 0],
+    },
+];
+
+/// The parameters of a new laser, as given by the NewLaser instructions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LaserAttributes {
+    /// Index of the type of this laser in `Game::laser_types`.
+    pub laser_type: usize,
+
+    /// Added to the sprite indices of its animation, usually to select a colour.
+    pub sprite_index_offset: u32,
+
+    /// Direction in which the laser gets fired.
+    pub angle: f32,
+
+    /// Speed at which the end of the laser moves away from its origin.
+    pub speed: f32,
+
+    /// Distance between the origin and the start of the laser.
+    pub start_offset: f32,
+
+    /// Distance between the origin and the end of the laser.
+    pub end_offset: f32,
+
+    /// Maximal length of the laser, once it has grown.
+    pub max_length: f32,
+
+    /// Width of the laser, once it has started.
+    pub width: f32,
+
+    /// Number of frames during which the laser widens, without hurting the player.
+    pub start_duration: u32,
+
+    /// Number of frames during which the laser hurts the player.
+    pub duration: u32,
+
+    /// Number of frames during which the laser narrows down, without hurting the player.
+    pub end_duration: u32,
+
+    /// Number of frames after the start before the laser can be grazed.
+    pub grazing_delay: u32,
+
+    /// Number of frames after the end during which the laser can still be grazed.
+    pub grazing_extra_duration: u32,
+}
+
+/// The lifecycle of a laser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaserState {
+    /// Widening, the laser can’t hurt the player yet.
+    Starting,
+
+    /// At its full width, the laser hurts the player.
+    Started,
+
+    /// Narrowing down, after which it gets removed.
+    Stopping,
+}
+
+/// Distance between two bullets sampled along a laser.
+const BULLET_SPACING: f32 = 48.;
+
+/// Struct representing an enemy laser.
+pub struct Laser {
+    /// Point from which the laser has been fired.
+    pub base_pos: Position,
+
+    /// Direction of the laser.
+    pub angle: f32,
+
+    /// Current state of the laser.
+    pub state: LaserState,
+
+    /// Number of frames spent in the current state.
+    pub frame: u32,
+
+    pub(crate) speed: f32,
+    pub(crate) start_offset: f32,
+    pub(crate) end_offset: f32,
+    pub(crate) max_length: f32,
+    pub(crate) width: f32,
+    start_duration: u32,
+    duration: u32,
+    end_duration: u32,
+    grazing_delay: u32,
+    grazing_extra_duration: u32,
+    pub(crate) removed: bool,
+    pub(crate) laser_type: LaserType,
+    pos: Position,
+    sprite: Option<Rc<RefCell<Sprite>>>,
+    anmrunner: Option<AnmRunner>,
+    launch_pos: Position,
+    launch_sprite: Option<Rc<RefCell<Sprite>>>,
+}
+
+impl Laser {
+    /// Create a laser fired from this position, or None if its type doesn’t exist.
+    pub fn new(base_pos: Position, attributes: &LaserAttributes, game: &Game) -> Option<Laser> {
+        let laser_type = game.laser_types.get(attributes.laser_type)?.clone();
+
+        let mut laser = Laser {
+            base_pos,
+            angle: attributes.angle,
+            state: LaserState::Starting,
+            frame: 0,
+            speed: attributes.speed,
+            start_offset: attributes.start_offset,
+            end_offset: attributes.end_offset,
+            max_length: attributes.max_length,
+            width: attributes.width,
+            start_duration: attributes.start_duration,
+            duration: attributes.duration,
+            end_duration: attributes.end_duration,
+            grazing_delay: attributes.grazing_delay,
+            grazing_extra_duration: attributes.grazing_extra_duration,
+            removed: false,
+            laser_type,
+            pos: base_pos,
+            sprite: None,
+            anmrunner: None,
+            launch_pos: base_pos,
+            launch_sprite: None,
+        };
+
+        if let Some(anms) = game.bullet_anms.get(laser.laser_type.anm) {
+            let mut sprite = Sprite::new();
+            sprite.set_angle(laser.angle);
+            let sprite = Rc::new(RefCell::new(sprite));
+            let anmrunner = AnmRunner::new(anms.clone(), laser.laser_type.anim_index, sprite.clone(),
+                                           Rc::downgrade(&game.prng), attributes.sprite_index_offset);
+            laser.sprite = Some(sprite);
+            laser.anmrunner = Some(anmrunner);
+
+            let offsets = laser.laser_type.launch_anim_offsets;
+            let offset = offsets.get(attributes.sprite_index_offset as usize).copied().unwrap_or(0);
+            let mut launch_sprite = Sprite::new();
+            launch_sprite.load_sprite(anms.clone(), (laser.laser_type.launch_sprite_index + offset) as u8);
+            launch_sprite.set_blendfunc(1);
+            laser.launch_sprite = Some(Rc::new(RefCell::new(launch_sprite)));
+        }
+        Some(laser)
+    }
+
+    /// Move the origin of this laser.
+    pub fn set_base_pos(&mut self, x: f32, y: f32) {
+        self.base_pos = Position::new(x, y);
+    }
+
+    /// Get the position of the middle of this laser, where its sprite is drawn.
+    pub fn get_pos(&self) -> Position {
+        self.pos
+    }
+
+    /// Get the sprite of this laser, if it has got one.
+    pub fn get_sprite(&self) -> Option<Rc<RefCell<Sprite>>> {
+        self.sprite.clone()
+    }
+
+    /// Get the sprite shown at the start of this laser while it gets launched, with its position.
+    pub fn get_launch_sprite(&self) -> Option<(Position, Rc<RefCell<Sprite>>)> {
+        self.launch_sprite.clone().map(|sprite| (self.launch_pos, sprite))
+    }
+
+    fn length(&self) -> f32 {
+        (self.end_offset - self.start_offset).min(self.max_length)
+    }
+
+    fn check_rectangle(&self, point: Position, border_size: f32) -> bool {
+        let x = point.x - self.base_pos.x;
+        let y = point.y - self.base_pos.y;
+        let (dx, dy) = (self.angle.cos(), self.angle.sin());
+        let (dx2, dy2) = (-dy, dx);
+
+        let offset = self.end_offset - self.length() - border_size / 2.;
+        let end_offset = self.end_offset + border_size / 2.;
+        let half_width = self.width / 4. + border_size / 2.;
+
+        let c1 = [dx * offset - dx2 * half_width, dy * offset - dy2 * half_width];
+        let c2 = [dx * offset + dx2 * half_width, dy * offset + dy2 * half_width];
+        let c3 = [dx * end_offset + dx2 * half_width, dy * end_offset + dy2 * half_width];
+        let (vx, vy) = (x - c2[0], y - c2[1]);
+        let (v1x, v1y) = (c1[0] - c2[0], c1[1] - c2[1]);
+        let (v2x, v2y) = (c3[0] - c2[0], c3[1] - c2[1]);
+
+        let dot1 = vx * v1x + vy * v1y;
+        let dot2 = vx * v2x + vy * v2y;
+        0. <= dot1 && dot1 <= v1x * v1x + v1y * v1y && 0. <= dot2 && dot2 <= v2x * v2x + v2y * v2y
+    }
+
+    /// Whether this point is hit by the laser, which only happens once it has fully started.
+    pub fn check_collision(&self, point: Position) -> bool {
+        if self.state != LaserState::Started {
+            return false;
+        }
+        self.check_rectangle(point, 2.5)
+    }
+
+    /// Whether this point grazes the laser, which can only happen every 12 frames.
+    pub fn check_grazing(&self, point: Position) -> bool {
+        // TODO: quadruple check!
+        if self.state == LaserState::Stopping && self.frame >= self.grazing_extra_duration {
+            return false;
+        }
+        if self.state == LaserState::Starting && self.frame <= self.grazing_delay {
+            return false;
+        }
+        if !self.frame.is_multiple_of(12) {
+            return false;
+        }
+        self.check_rectangle(point, 96. + 2.5)
+    }
+
+    /// Sample points along the laser, every 48 pixels from its start.
+    pub fn get_bullets_pos(&self) -> Vec<Position> {
+        // TODO: check
+        let (dx, dy) = (self.angle.cos(), self.angle.sin());
+        let mut offset = self.end_offset - self.length();
+        let mut positions = vec![];
+        while self.start_offset <= offset && offset < self.end_offset {
+            positions.push(Position::new(self.base_pos.x + offset * dx, self.base_pos.y + offset * dy));
+            offset += BULLET_SPACING;
+        }
+        positions
+    }
+
+    /// Stop this laser, it will narrow down then get removed.
+    pub fn cancel(&mut self) {
+        self.grazing_extra_duration = 0;
+        if self.state != LaserState::Stopping {
+            self.frame = 0;
+            self.state = LaserState::Stopping;
+        }
+    }
+
+    /// Run the animation of the laser, make it grow and go through its phases for a single frame.
+    pub fn update(&mut self) {
+        if let Some(anmrunner) = &mut self.anmrunner {
+            if !anmrunner.run_frame() {
+                self.anmrunner = None;
+            }
+        }
+
+        self.end_offset += self.speed;
+
+        let length = self.length();
+        let mut width = 0.;
+        match self.state {
+            LaserState::Starting => {
+                if self.frame == self.start_duration {
+                    self.frame = 0;
+                    self.state = LaserState::Started;
+                } else {
+                    width = self.width * self.frame as f32 / self.start_duration as f32;
+                }
+            }
+            LaserState::Started => {
+                width = self.width;
+                if self.frame == self.duration {
+                    self.frame = 0;
+                    self.state = LaserState::Stopping;
+                }
+            }
+            LaserState::Stopping => {
+                if self.frame == self.end_duration {
+                    self.removed = true;
+                } else {
+                    width = self.width * (1. - self.frame as f32 / self.end_duration as f32);
+                }
+            }
+        }
+
+        let offset = self.end_offset - length / 2.;
+        self.pos = Position::new(self.base_pos.x + offset * self.angle.cos(),
+                                 self.base_pos.y + offset * self.angle.sin());
+        if let Some(sprite) = &self.sprite {
+            let mut sprite = sprite.borrow_mut();
+            sprite.set_visible(width > 0. && length > 0.);
+            sprite.set_size_override(width, length);
+            sprite.set_rotation(PI / 2. - self.angle);
+        }
+
+        // The launch sprite shrinks as the start of the laser moves away from its start offset.
+        let start = self.end_offset - length;
+        let scale = self.width / 10. - (start - self.start_offset);
+        if self.removed || scale <= 0. {
+            self.launch_sprite = None;
+        } else if let Some(sprite) = &self.launch_sprite {
+            self.launch_pos = Position::new(self.base_pos.x + start * self.angle.cos(),
+                                            self.base_pos.y + start * self.angle.sin());
+            sprite.borrow_mut().set_rescale(scale, scale);
+        }
+
+        self.frame += 1;
+    }
+}
+
+/// Struct representing a laser fired by the player, going from its origin up to the top of the
+/// screen.
+pub struct PlayerLaser {
+    /// Index of the option firing this laser, or 0 for the player itself.
+    pub orb: u8,
+
+    /// Half of the width of the hitbox of this laser, its height isn’t used.
+    pub hitbox: [f32; 2],
+
+    /// Damages dealt to an enemy, for each frame it touches this laser.
+    pub damage: u16,
+
+    pub(crate) removed: bool,
+    angle: f32,
+    offset: f32,
+    duration: u32,
+    frame: u32,
+    pos: Position,
+    sprite: Option<Rc<RefCell<Sprite>>>,
+    anmrunner: Option<AnmRunner>,
+}
+
+impl PlayerLaser {
+    /// Create a laser from a shot of type 3, whose speed is its offset from its origin and whose
+    /// interval is its duration.
+    pub(crate) fn new(orb: u8, shot: &Shot, anms: Option<Rc<RefCell<[Anm0]>>>, prng: Weak<RefCell<Prng>>) -> PlayerLaser {
+        let mut laser = PlayerLaser {
+            orb,
+            hitbox: [shot.hitbox.0, shot.hitbox.1],
+            damage: shot.damage,
+            removed: false,
+            angle: shot.angle,
+            offset: shot.speed,
+            duration: shot.interval as u32,
+            frame: 0,
+            pos: Position::default(),
+            sprite: None,
+            anmrunner: None,
+        };
+        if let Some(anms) = anms {
+            let sprite = Rc::new(RefCell::new(Sprite::new()));
+            let anim_index = shot.sprite.rem_euclid(256) as u8;
+            laser.anmrunner = Some(AnmRunner::new(anms, anim_index, sprite.clone(), prng, 0));
+            laser.sprite = Some(sprite);
+        }
+        laser
+    }
+
+    /// Get the position of this laser, its y coordinate being the middle of its sprite.
+    pub fn get_pos(&self) -> Position {
+        self.pos
+    }
+
+    /// Get the sprite of this laser, if it has got one.
+    pub fn get_sprite(&self) -> Option<Rc<RefCell<Sprite>>> {
+        self.sprite.clone()
+    }
+
+    /// Whether this laser touches this box, which it does anywhere below the top of the laser.
+    pub(crate) fn check_collision(&self, pos: Position, half_size: [f32; 2]) -> bool {
+        let (x, y) = (self.pos.x, self.pos.y * 2.);
+        let half_width = self.hitbox[0];
+        !(x + half_width < pos.x - half_size[0] || x - half_width > pos.x + half_size[0] || y < pos.y - half_size[1])
+    }
+
+    /// Stop this laser, it will get removed once its animation ends.
+    pub fn cancel(&mut self) {
+        match &mut self.anmrunner {
+            Some(anmrunner) => {
+                anmrunner.interrupt(1);
+            }
+            // Without any animation to end, just remove it.
+            None => self.removed = true,
+        }
+    }
+
+    /// Run the animation of the laser and stretch it from its origin to the top of the screen,
+    /// for a single frame.
+    pub fn update(&mut self, origin: Position) {
+        if let Some(anmrunner) = &mut self.anmrunner {
+            if !anmrunner.run_frame() {
+                self.anmrunner = None;
+                self.removed = true;
+            }
+        }
+
+        if self.frame == self.duration {
+            self.cancel();
+        }
+
+        let length = origin.y;
+        if let Some(sprite) = &self.sprite {
+            let mut sprite = sprite.borrow_mut();
+            sprite.set_visible(length > 0.);
+            sprite.set_size_override(0., length);
+        }
+
+        self.pos = Position::new(origin.x + self.offset * self.angle.cos(),
+                                 origin.y / 2. + self.offset * self.angle.sin());
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use touhou_formats::th06::anm0::{self, Call, Instruction, Script};
+    use touhou_formats::th06::ecl::Rank;
+    use touhou_formats::th06::thtx::Format;
+    use touhou_utils::prng::Prng;
+    use std::collections::BTreeMap;
+
+    fn laser_anm() -> Anm0 {
+        let mut scripts = BTreeMap::new();
+        scripts.insert(9, Script::new(vec![Call { time: 0, instr: Instruction::Wait() }]));
+        Anm0 {
+            size: (256, 256),
+            format: Format::Argb8888,
+            color_key: 0,
+            png_filename: String::new(),
+            alpha_filename: None,
+            sprites: (140..145).map(|index| anm0::Sprite { index, x: 0., y: 0., width: 16., height: 16. }).collect(),
+            scripts,
+            texture: None,
+        }
+    }
+
+    fn new_laser() -> Laser {
+        new_laser_with_anms(vec![])
+    }
+
+    fn new_laser_with_anms(anms: Vec<Anm0>) -> Laser {
+        let prng = Rc::new(RefCell::new(Prng::new(0)));
+        let mut game = Game::new(prng, Rank::EASY);
+        game.set_bullet_anms(anms);
+        let attributes = LaserAttributes {
+            angle: PI / 2.,
+            speed: 8.,
+            end_offset: 0.,
+            max_length: 100.,
+            width: 16.,
+            start_duration: 10,
+            duration: 20,
+            end_duration: 5,
+            grazing_delay: 5,
+            grazing_extra_duration: 3,
+            ..Default::default()
+        };
+        Laser::new(Position::new(100., 0.), &attributes, &game).unwrap()
+    }
+
+    #[test]
+    fn phases() {
+        let mut laser = new_laser();
+        for _ in 0..10 {
+            laser.update();
+            assert_eq!(laser.state, LaserState::Starting);
+        }
+        laser.update();
+        assert_eq!(laser.state, LaserState::Started);
+        for _ in 0..20 {
+            laser.update();
+        }
+        assert_eq!(laser.state, LaserState::Stopping);
+        for _ in 0..4 {
+            laser.update();
+        }
+        assert!(!laser.removed);
+        laser.update();
+        assert!(laser.removed);
+
+        // Its length is capped.
+        assert_eq!(laser.length(), 100.);
+    }
+
+    #[test]
+    fn launch_sprite() {
+        assert!(new_laser().get_launch_sprite().is_none());
+
+        // It stays at the origin while the laser grows, then disappears once it moves away.
+        let mut laser = new_laser_with_anms(vec![laser_anm()]);
+        for _ in 0..10 {
+            laser.update();
+        }
+        let (pos, _) = laser.get_launch_sprite().unwrap();
+        assert!((pos.x - 100.).abs() < 1e-3 && pos.y.abs() < 1e-3);
+        for _ in 0..10 {
+            laser.update();
+        }
+        assert!(laser.get_launch_sprite().is_none());
+    }
+
+    #[test]
+    fn collision() {
+        let mut laser = new_laser();
+        for _ in 0..11 {
+            laser.update();
+        }
+        // The laser now goes from (100, 0) to (100, 88).
+        assert!(laser.check_collision(Position::new(100., 50.)));
+        assert!(laser.check_collision(Position::new(105., 50.)));
+        assert!(!laser.check_collision(Position::new(110., 50.)));
+        assert!(!laser.check_collision(Position::new(100., 120.)));
+
+        laser.cancel();
+        assert!(!laser.check_collision(Position::new(100., 50.)));
+    }
+
+    #[test]
+    fn grazing() {
+        let mut laser = new_laser();
+        for _ in 0..11 {
+            laser.update();
+        }
+        assert_eq!(laser.frame, 1);
+        assert!(!laser.check_grazing(Position::new(140., 50.)));
+        for _ in 0..11 {
+            laser.update();
+        }
+        assert!(laser.check_grazing(Position::new(140., 50.)));
+        assert!(!laser.check_grazing(Position::new(200., 50.)));
+    }
+
+    #[test]
+    fn bullets_pos() {
+        let mut laser = new_laser();
+        for _ in 0..20 {
+            laser.update();
+        }
+        // The laser goes from (100, 60) to (100, 160).
+        let positions = laser.get_bullets_pos();
+        assert_eq!(positions.len(), 3);
+        for (pos, y) in positions.iter().zip([60., 108., 156.].iter()) {
+            assert!((pos.x - 100.).abs() < 1e-3 && (pos.y - y).abs() < 1e-3);
+        }
+    }
+}
@@ -4,5 +4,8 @@ pub mod anm0;
 pub mod ecl;
 pub mod std;
 
+pub mod bullet;
 pub mod enemy;
 pub mod interpolator;
+pub mod laser;
+pub mod player;
@@ -0,0 +1,823 @@
+//! Module providing a Player struct, driven by the keys held during each frame.
+
+use touhou_formats::th06::anm0::Anm0;
+use touhou_formats::th06::sht::Sht;
+use crate::th06::anm0::{Sprite, AnmRunner};
+use crate::th06::bullet::Bullet;
+use crate::th06::enemy::{Direction, Game, Offset, Position, GAME_WIDTH, GAME_HEIGHT, MAX_BULLETS};
+use crate::th06::interpolator::{Interpolator1, Formula};
+use crate::th06::laser::PlayerLaser;
+use touhou_utils::prng::Prng;
+use std::cell::RefCell;
+use std::f32::consts::SQRT_2;
+use std::rc::{Rc, Weak};
+
+/// Shoot, firing for 30 frames.
+pub const KEY_SHOOT: u16 = 1;
+
+/// Use a bomb, also during the few frames following a hit.
+pub const KEY_BOMB: u16 = 2;
+
+/// Focus, moving slower and using the focused shots.
+pub const KEY_FOCUS: u16 = 4;
+
+/// Move up.
+pub const KEY_UP: u16 = 16;
+
+/// Move down.
+pub const KEY_DOWN: u16 = 32;
+
+/// Move left.
+pub const KEY_LEFT: u16 = 64;
+
+/// Move right.
+pub const KEY_RIGHT: u16 = 128;
+
+const KEYS_MOVE: u16 = KEY_UP | KEY_DOWN | KEY_LEFT | KEY_RIGHT;
+
+/// Position at which the player starts, and respawns after dying.
+const SPAWN_POS: Position = Position { x: GAME_WIDTH / 2., y: GAME_WIDTH };
+
+/// Number of frames during which the player can’t get hit, after spawning or bombing.
+const INVULNERABLE_TIME: u32 = 240;
+
+/// Number of frames after a hit during which a bomb still saves the player.
+const DEATHBOMB_WINDOW: u32 = 6;
+
+/// Minimal power required for the options to be present.
+const OPTIONS_POWER: u32 = 8;
+
+/// An option orb, following the player around and firing some of its shots.
+struct Orb {
+    offset: Offset,
+    sprite: Option<Rc<RefCell<Sprite>>>,
+    anmrunner: Option<AnmRunner>,
+}
+
+impl Orb {
+    fn new(dx: f32) -> Orb {
+        Orb {
+            offset: Offset::new(dx, 0.),
+            sprite: None,
+            anmrunner: None,
+        }
+    }
+}
+
+/// Struct representing the player.
+pub struct Player {
+    pub(crate) pos: Position,
+
+    /// Current power, which selects the shots being fired.
+    pub power: u32,
+
+    /// Lives left, the game is over once it goes below zero without any continue left.
+    pub lives: i32,
+
+    /// Bombs left.
+    pub bombs: u32,
+
+    /// Continues left, or -1 for infinite.
+    pub continues: i32,
+
+    /// Number of continues used so far.
+    pub continues_used: u32,
+
+    /// Number of times the player died.
+    pub miss: u32,
+
+    /// Number of bombs used.
+    pub bombs_used: u32,
+
+    /// Number of bullets grazed.
+    pub graze: u32,
+
+    /// Current score.
+    pub score: u32,
+
+    /// Remaining frames during which the player can’t get hit.
+    pub invulnerable_time: u32,
+
+    /// Whether the player is focused.
+    pub focused: bool,
+
+    pub(crate) touchable: bool,
+    pub(crate) game_over: bool,
+    pub(crate) hitbox: f32,
+    pub(crate) graze_hitbox: f32,
+    frame: u32,
+    fire_time: u32,
+    bomb_time: u32,
+    death_frame: Option<u32>,
+    direction: Direction,
+    speeds: [f32; 4],
+    shts: Option<[Sht; 2]>,
+    orbs: [Orb; 2],
+    orb_dx_interpolator: Option<Interpolator1<f32>>,
+    orb_dy_interpolator: Option<Interpolator1<f32>>,
+    sprite: Option<Rc<RefCell<Sprite>>>,
+    anmrunner: Option<AnmRunner>,
+    anms: Option<Rc<RefCell<[Anm0]>>>,
+    prng: Weak<RefCell<Prng>>,
+}
+
+impl Player {
+    /// Create the player at its spawn position, without any ANM nor shots yet.
+    ///
+    /// Until its SHTs get set, it moves at the speeds of Reimu, uses the default hitboxes and
+    /// doesn’t fire anything.
+    pub fn new(prng: Weak<RefCell<Prng>>) -> Player {
+        Player {
+            pos: SPAWN_POS,
+            power: 0,
+            lives: 2,
+            bombs: 3,
+            continues: 0,
+            continues_used: 0,
+            miss: 0,
+            bombs_used: 0,
+            graze: 0,
+            score: 0,
+            invulnerable_time: INVULNERABLE_TIME,
+            focused: false,
+            touchable: true,
+            game_over: false,
+            hitbox: 2.,
+            graze_hitbox: 21.,
+            frame: 0,
+            fire_time: 0,
+            bomb_time: 0,
+            death_frame: None,
+            direction: Direction::Center,
+            speeds: [4., 4. / SQRT_2, 2., 2. / SQRT_2],
+            shts: None,
+            orbs: [Orb::new(-24.), Orb::new(24.)],
+            orb_dx_interpolator: None,
+            orb_dy_interpolator: None,
+            sprite: None,
+            anmrunner: None,
+            anms: None,
+            prng,
+        }
+    }
+
+    /// Set the unfocused and focused shots of the player, which also contain its speeds and the
+    /// sizes of its hitboxes.
+    pub fn set_shts(&mut self, sht: Sht, focused_sht: Sht) {
+        self.hitbox = sht.hitbox;
+        self.graze_hitbox = sht.graze_hitbox;
+        self.speeds = [sht.horizontal_vertical_speed, sht.diagonal_speed,
+                       sht.horizontal_vertical_focused_speed, sht.diagonal_focused_speed];
+        self.shts = Some([sht, focused_sht]);
+    }
+
+    /// Set the ANM of the player, containing its animations, those of its options and of its
+    /// bullets.
+    pub fn set_anm(&mut self, anm: Anm0) {
+        let anms = Rc::new(RefCell::new([anm])) as Rc<RefCell<[Anm0]>>;
+        for (orb, script) in self.orbs.iter_mut().zip([128, 129].iter()) {
+            let sprite = Rc::new(RefCell::new(Sprite::new()));
+            orb.anmrunner = Some(AnmRunner::new(anms.clone(), *script, sprite.clone(), self.prng.clone(), 0));
+            orb.sprite = Some(sprite);
+        }
+        self.anms = Some(anms);
+        self.set_anim(0);
+    }
+
+    fn set_anim(&mut self, script: u8) {
+        let anms = match &self.anms {
+            Some(anms) => anms.clone(),
+            None => return,
+        };
+        let sprite = Rc::new(RefCell::new(Sprite::new()));
+        self.anmrunner = Some(AnmRunner::new(anms, script, sprite.clone(), self.prng.clone(), 0));
+        self.sprite = Some(sprite);
+    }
+
+    /// Get the position of the player.
+    pub fn get_pos(&self) -> Position {
+        self.pos
+    }
+
+    /// Get the position of the player if `orb` is 0, or of this option otherwise.
+    pub(crate) fn get_orb_pos(&self, orb: u8) -> Option<Position> {
+        match orb {
+            0 => Some(self.pos),
+            orb => self.orbs.get(orb as usize - 1).map(|orb| self.pos + orb.offset),
+        }
+    }
+
+    /// Whether the player ran out of lives and continues.
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
+    }
+
+    /// Whether the player can currently get hit.
+    pub(crate) fn is_vulnerable(&self) -> bool {
+        self.invulnerable_time == 0 && self.death_frame.is_none() && self.touchable
+    }
+
+    /// Get the sprites of the player and of its options, with their positions.
+    pub fn get_sprites(&self) -> Vec<(f32, f32, f32, Rc<RefCell<Sprite>>)> {
+        let mut sprites = vec![];
+        if let Some(sprite) = &self.sprite {
+            sprites.push((self.pos.x, self.pos.y, 0., sprite.clone()));
+        }
+        if self.power >= OPTIONS_POWER {
+            for orb in self.orbs.iter() {
+                if let Some(sprite) = &orb.sprite {
+                    let pos = self.pos + orb.offset;
+                    sprites.push((pos.x, pos.y, 0., sprite.clone()));
+                }
+            }
+        }
+        sprites
+    }
+
+    /// Get hit, which kills the player unless it bombs in the next few frames.
+    pub(crate) fn collide(&mut self) {
+        // Border Between Life and Death.
+        if self.is_vulnerable() {
+            self.death_frame = Some(self.frame);
+            // TODO: lower the difficulty by 1600 and spawn the death effect.
+        }
+    }
+
+    fn start_focusing(&mut self) {
+        let frame = self.frame;
+        self.orb_dx_interpolator = Some(Interpolator1::new([24.], frame, [8.], frame + 8, Formula::Power2));
+        self.orb_dy_interpolator = Some(Interpolator1::new([0.], frame, [-32.], frame + 8, Formula::Linear));
+        self.focused = true;
+    }
+
+    fn stop_focusing(&mut self) {
+        let frame = self.frame;
+        self.orb_dx_interpolator = Some(Interpolator1::new([8.], frame, [24.], frame + 8, Formula::Power2));
+        self.orb_dy_interpolator = Some(Interpolator1::new([-32.], frame, [0.], frame + 8, Formula::Linear));
+        self.focused = false;
+    }
+
+    fn fire(&mut self, game: &mut Game) {
+        let shts = match &self.shts {
+            Some(shts) => shts,
+            None => return,
+        };
+        let sht = &shts[self.focused as usize];
+
+        for shot in sht.shots_for_power(self.power) {
+            let origin = match self.get_orb_pos(shot.orb) {
+                Some(origin) => origin,
+                None => continue,
+            };
+
+            if shot.type_ == 3 {
+                // Lasers are only fired at the start of a shooting sequence, and last until
+                // their own duration.
+                if self.fire_time != 30 {
+                    continue;
+                }
+
+                // TODO: the delay is used as the number of the laser, check what other values
+                // than 0 and 1 do.
+                if let Some(laser @ None) = game.player_lasers.get_mut(shot.delay as usize) {
+                    *laser = Some(PlayerLaser::new(shot.orb, shot, self.anms.clone(), self.prng.clone()));
+                }
+                continue;
+            }
+
+            if !(self.fire_time + shot.delay as u32).is_multiple_of((shot.interval as u32).max(1)) {
+                continue;
+            }
+
+            if game.player_bullets.len() >= MAX_BULLETS {
+                break;
+            }
+
+            let pos = Position::new(origin.x + shot.pos.0, origin.y + shot.pos.1);
+            let bullet = Bullet::new_player_shot(pos, shot, self.anms.clone(), self.prng.clone());
+            game.player_bullets.push(Rc::new(RefCell::new(bullet)));
+        }
+    }
+
+    fn update_movement(&mut self, keystate: u16) {
+        let (speed, diag_speed) = if self.focused {
+            (self.speeds[2], self.speeds[3])
+        } else {
+            (self.speeds[0], self.speeds[1])
+        };
+        let (dx, dy) = match keystate & KEYS_MOVE {
+            KEY_UP => (0., -speed),
+            KEY_DOWN => (0., speed),
+            KEY_LEFT => (-speed, 0.),
+            KEY_RIGHT => (speed, 0.),
+            k if k == KEY_UP | KEY_LEFT => (-diag_speed, -diag_speed),
+            k if k == KEY_UP | KEY_RIGHT => (diag_speed, -diag_speed),
+            k if k == KEY_DOWN | KEY_LEFT => (-diag_speed, diag_speed),
+            k if k == KEY_DOWN | KEY_RIGHT => (diag_speed, diag_speed),
+            _ => (0., 0.),
+        };
+
+        if dx < 0. && self.direction != Direction::Left {
+            self.set_anim(1);
+            self.direction = Direction::Left;
+        } else if dx > 0. && self.direction != Direction::Right {
+            self.set_anim(3);
+            self.direction = Direction::Right;
+        } else if dx == 0. && self.direction != Direction::Center {
+            self.set_anim(if self.direction == Direction::Left { 2 } else { 4 });
+            self.direction = Direction::Center;
+        }
+
+        self.pos.x = (self.pos.x + dx).clamp(8., GAME_WIDTH - 8.);
+        self.pos.y = (self.pos.y + dy).clamp(16., GAME_HEIGHT - 16.);
+    }
+
+    fn update_invulnerability(&mut self) {
+        if self.invulnerable_time == 0 {
+            return;
+        }
+        self.invulnerable_time -= 1;
+
+        // Blink while invulnerable.
+        if let Some(sprite) = &self.sprite {
+            let mut sprite = sprite.borrow_mut();
+            match self.invulnerable_time % 8 {
+                _ if self.invulnerable_time == 0 => sprite.set_color(255, 255, 255),
+                7 => sprite.set_color(255, 255, 255),
+                1 => sprite.set_color(64, 64, 64),
+                _ => (),
+            }
+        }
+    }
+
+    fn die(&mut self) {
+        self.touchable = false;
+        self.power = self.power.saturating_sub(16);
+        // TODO: use the right default.
+        self.bombs = 3;
+
+        self.miss += 1;
+        self.lives -= 1;
+        if self.lives < 0 {
+            // TODO: ask the player whether they want to continue.
+            if self.continues == 0 {
+                self.game_over = true;
+                return;
+            }
+
+            // Don’t decrement if it’s infinite.
+            if self.continues > 0 {
+                self.continues -= 1;
+            }
+            self.continues_used += 1;
+
+            // TODO: drop the power items.
+            self.score = self.continues_used.min(9);
+            // TODO: use the right defaults.
+            self.lives = 2;
+            self.bombs = 3;
+            self.power = 0;
+            self.graze = 0;
+        }
+        // TODO: drop the power items.
+    }
+
+    fn update_death(&mut self, time: u32, game: &mut Game) {
+        match time {
+            // Too late, you are dead. :(
+            6 => {
+                game.cancel_player_lasers();
+                self.die();
+            }
+            7 => {
+                if let Some(sprite) = &self.sprite {
+                    let mut sprite = sprite.borrow_mut();
+                    sprite.set_blendfunc(0);
+                    sprite.set_rescale(0.75, 1.5);
+                    sprite.fade(26, 96);
+                    sprite.scale_in(26, 0., 2.5);
+                }
+            }
+            31 => game.cancel_bullets(),
+            32 => {
+                self.pos = SPAWN_POS;
+                self.direction = Direction::Center;
+                self.set_anim(0);
+                if let Some(sprite) = &self.sprite {
+                    let mut sprite = sprite.borrow_mut();
+                    sprite.set_alpha(128);
+                    sprite.set_rescale(0., 2.5);
+                    sprite.fade(30, 255);
+                    sprite.set_blendfunc(1);
+                    sprite.scale_in(30, 1., 1.);
+                }
+            }
+            // Respawned.
+            61 => {
+                self.touchable = true;
+                self.invulnerable_time = INVULNERABLE_TIME;
+                if let Some(sprite) = &self.sprite {
+                    sprite.borrow_mut().set_blendfunc(0);
+                }
+            }
+            // Start the bullet hell again.
+            91 => self.death_frame = None,
+            _ => (),
+        }
+    }
+
+    /// Run a single frame of the player, using these keys, see the `KEY_*` constants.
+    pub(crate) fn update(&mut self, keystate: u16, game: &mut Game) {
+        self.frame += 1;
+        let death_time = self.death_frame.map(|death_frame| self.frame - death_frame);
+
+        if death_time.is_none_or(|time| time > 60) {
+            self.update_movement(keystate);
+
+            if !self.focused && keystate & KEY_FOCUS != 0 {
+                self.start_focusing();
+            } else if self.focused && keystate & KEY_FOCUS == 0 {
+                self.stop_focusing();
+            }
+
+            self.update_invulnerability();
+
+            if keystate & KEY_SHOOT != 0 && self.fire_time == 0 {
+                self.fire_time = 30;
+            }
+            if self.fire_time > 0 {
+                self.fire(game);
+                self.fire_time -= 1;
+            }
+        }
+
+        // TODO: check whether the window includes its last frame.
+        if death_time.is_none_or(|time| time < DEATHBOMB_WINDOW) {
+            if keystate & KEY_BOMB != 0 && self.bombs > 0 && self.bomb_time == 0 {
+                // TODO: actually bomb, this only saves the player for now.
+                self.bomb_time = INVULNERABLE_TIME;
+                self.bombs -= 1;
+                self.bombs_used += 1;
+                // TODO: check the duration of bombs.
+                self.invulnerable_time = INVULNERABLE_TIME;
+                // Deathbomb.
+                self.death_frame = None;
+            }
+            if self.bomb_time > 0 {
+                self.bomb_time -= 1;
+            }
+        }
+
+        // Once the game is over, the player doesn’t respawn anymore.
+        if let Some(death_frame) = self.death_frame.filter(|_| !self.game_over) {
+            self.update_death(self.frame - death_frame, game);
+        }
+
+        if self.death_frame.is_none_or(|death_frame| self.frame - death_frame > 60) {
+            let frame = self.frame;
+            if let Some(interpolator) = &self.orb_dx_interpolator {
+                let dx = interpolator.values(frame)[0];
+                self.orbs[0].offset.dx = -dx;
+                self.orbs[1].offset.dx = dx;
+            }
+            if let Some(interpolator) = &self.orb_dy_interpolator {
+                let dy = interpolator.values(frame)[0];
+                self.orbs[0].offset.dy = dy;
+                self.orbs[1].offset.dy = dy;
+            }
+        }
+
+        if let Some(anmrunner) = &mut self.anmrunner {
+            anmrunner.run_frame();
+        }
+        for orb in self.orbs.iter_mut() {
+            if let Some(anmrunner) = &mut orb.anmrunner {
+                anmrunner.run_frame();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::th06::enemy::{BulletAttributes, Enemy};
+    use touhou_formats::th06::ecl::Rank;
+    use touhou_formats::th06::sht::Shot;
+    use std::collections::BTreeMap;
+
+    fn shot(orb: u8, interval: u16) -> Shot {
+        Shot {
+            interval,
+            delay: 0,
+            pos: (0., -16.),
+            hitbox: (12., 12.),
+            angle: -std::f32::consts::FRAC_PI_2,
+            speed: 10.,
+            damage: 12,
+            orb,
+            type_: 0,
+            sprite: 64,
+            sound: -1,
+            unknown1: 0,
+            unknown2: 0,
+            unknown3: 0,
+            unknown4: 0,
+        }
+    }
+
+    fn sht(speed: f32) -> Sht {
+        let mut shots = BTreeMap::new();
+        shots.insert(8, vec![shot(0, 5)]);
+        shots.insert(999, vec![shot(0, 5), shot(1, 10), shot(2, 10)]);
+        Sht {
+            unknown1: 0,
+            bombs: 3.,
+            unknown2: 0,
+            hitbox: 2.,
+            graze_hitbox: 21.,
+            autocollection_speed: 8.,
+            item_hitbox: 19.,
+            percentage_of_cherry_loss_on_die: 0.,
+            point_of_collection: 128.,
+            horizontal_vertical_speed: speed,
+            horizontal_vertical_focused_speed: speed / 2.,
+            diagonal_speed: speed / SQRT_2,
+            diagonal_focused_speed: speed / 2. / SQRT_2,
+            shots,
+        }
+    }
+
+    fn laser_sht() -> Sht {
+        let mut laser = shot(0, 40);
+        laser.type_ = 3;
+        laser.speed = 0.;
+        let mut sht = sht(4.);
+        sht.shots = BTreeMap::new();
+        sht.shots.insert(999, vec![laser]);
+        sht
+    }
+
+    fn new_game() -> Game {
+        let prng = Rc::new(RefCell::new(Prng::new(0)));
+        let mut game = Game::new(prng, Rank::EASY);
+        game.set_player_shts(sht(4.), sht(4.));
+        game
+    }
+
+    fn player_pos(game: &Game) -> (f32, f32) {
+        let pos = game.get_player().borrow().pos;
+        (pos.x, pos.y)
+    }
+
+    #[test]
+    fn movement() {
+        let mut game = new_game();
+        assert_eq!(player_pos(&game), (192., 384.));
+        game.run_frame(KEY_LEFT);
+        assert_eq!(player_pos(&game), (188., 384.));
+        // Focusing only slows the player down from the next frame.
+        game.run_frame(KEY_LEFT | KEY_FOCUS);
+        assert_eq!(player_pos(&game), (184., 384.));
+        game.run_frame(KEY_LEFT | KEY_FOCUS);
+        assert_eq!(player_pos(&game), (182., 384.));
+        game.run_frame(KEY_UP | KEY_RIGHT);
+        let (x, y) = player_pos(&game);
+        assert!((x - (182. + 2. / SQRT_2)).abs() < 1e-4);
+        assert!((y - (384. - 2. / SQRT_2)).abs() < 1e-4);
+
+        // Opposite directions cancel each other.
+        game.run_frame(KEY_UP | KEY_DOWN);
+        assert_eq!(player_pos(&game), (x, y));
+
+        for _ in 0..100 {
+            game.run_frame(KEY_DOWN | KEY_RIGHT);
+        }
+        assert_eq!(player_pos(&game), (GAME_WIDTH - 8., GAME_HEIGHT - 16.));
+        for _ in 0..200 {
+            game.run_frame(KEY_UP | KEY_LEFT);
+        }
+        assert_eq!(player_pos(&game), (8., 16.));
+    }
+
+    #[test]
+    fn shooting() {
+        let mut game = new_game();
+        game.run_frame(KEY_SHOOT);
+        assert_eq!(game.player_bullets.len(), 1);
+        {
+            let bullet = game.player_bullets[0].borrow();
+            assert_eq!(bullet.damage, 12);
+            assert_eq!(bullet.pos, Position::new(192., 368.));
+        }
+
+        // Shots keep being fired for 30 frames once started, every 5 frames.
+        for _ in 0..29 {
+            game.run_frame(0);
+        }
+        assert_eq!(game.player_bullets.len(), 6);
+
+        // More power fires from the options too.
+        game.get_player().borrow_mut().power = 8;
+        game.run_frame(KEY_SHOOT);
+        assert_eq!(game.player_bullets.len(), 9);
+        assert_eq!(game.get_player().borrow().get_sprites().len(), 0);
+    }
+
+    #[test]
+    fn death() {
+        let mut game = new_game();
+        for _ in 0..INVULNERABLE_TIME {
+            game.run_frame(0);
+        }
+        let player = game.get_player();
+        assert!(player.borrow().is_vulnerable());
+        player.borrow_mut().power = 20;
+        player.borrow_mut().collide();
+        for _ in 0..40 {
+            game.run_frame(KEY_LEFT);
+        }
+        {
+            let player = player.borrow();
+            assert_eq!(player.lives, 1);
+            assert_eq!(player.miss, 1);
+            assert_eq!(player.power, 4);
+            assert_eq!(player.pos, SPAWN_POS);
+            assert!(!player.is_vulnerable());
+        }
+
+        // The player respawns and can move again.
+        for _ in 0..60 {
+            game.run_frame(KEY_LEFT);
+        }
+        {
+            let player = player.borrow();
+            assert!(player.touchable);
+            assert!(player.pos.x < SPAWN_POS.x);
+        }
+
+        // Two more deaths end the game.
+        for _ in 0..2 {
+            for _ in 0..INVULNERABLE_TIME + 100 {
+                game.run_frame(0);
+            }
+            player.borrow_mut().collide();
+        }
+        for _ in 0..10 {
+            game.run_frame(0);
+        }
+        assert!(player.borrow().is_game_over());
+
+        // And the player doesn’t respawn afterwards.
+        for _ in 0..100 {
+            game.run_frame(0);
+        }
+        assert!(!player.borrow().touchable);
+        assert_eq!(player.borrow().invulnerable_time, 0);
+    }
+
+    #[test]
+    fn deathbomb() {
+        let mut game = new_game();
+        for _ in 0..INVULNERABLE_TIME {
+            game.run_frame(0);
+        }
+        let player = game.get_player();
+        player.borrow_mut().collide();
+        for _ in 0..4 {
+            game.run_frame(0);
+        }
+        game.run_frame(KEY_BOMB);
+        for _ in 0..10 {
+            game.run_frame(0);
+        }
+        let player = player.borrow();
+        assert_eq!(player.lives, 2);
+        assert_eq!(player.bombs, 2);
+        assert!(player.invulnerable_time > 0);
+    }
+
+    #[test]
+    fn lasers() {
+        let mut game = new_game();
+        game.set_player_shts(laser_sht(), laser_sht());
+
+        // A laser is fired only once, and lasts for its interval.
+        game.run_frame(KEY_SHOOT);
+        for _ in 0..39 {
+            game.run_frame(KEY_SHOOT);
+        }
+        assert!(game.player_bullets.is_empty());
+        {
+            let laser = game.player_lasers[0].as_ref().unwrap();
+            assert_eq!(laser.get_pos(), Position::new(192., 192.));
+        }
+        assert!(game.player_lasers[1].is_none());
+        for _ in 0..30 {
+            game.run_frame(0);
+        }
+        assert!(game.player_lasers[0].is_none());
+
+        // Dying cancels it.
+        game.run_frame(KEY_SHOOT);
+        assert!(game.player_lasers[0].is_some());
+        let player = game.get_player();
+        player.borrow_mut().invulnerable_time = 0;
+        player.borrow_mut().collide();
+        for _ in 0..10 {
+            game.run_frame(0);
+        }
+        assert!(game.player_lasers[0].is_none());
+    }
+
+    fn fire_bullet_at(game: &mut Game, pos: Position) {
+        let attributes = BulletAttributes {
+            pos,
+            ..Default::default()
+        };
+        let bullet = Bullet::new(&attributes, 0., 0., game).unwrap();
+        game.bullets.push(Rc::new(RefCell::new(bullet)));
+    }
+
+    #[test]
+    fn bullets() {
+        let mut game = new_game();
+        for _ in 0..INVULNERABLE_TIME {
+            game.run_frame(0);
+        }
+
+        // Bullets can only be grazed once.
+        fire_bullet_at(&mut game, Position::new(192., 364.));
+        game.run_frame(0);
+        game.run_frame(0);
+        {
+            let player = game.get_player();
+            let player = player.borrow();
+            assert_eq!(player.graze, 1);
+            assert_eq!(player.score, 500);
+            assert!(player.is_vulnerable());
+        }
+
+        fire_bullet_at(&mut game, Position::new(195., 386.));
+        game.run_frame(0);
+        assert_eq!(game.bullets.len(), 1);
+        assert!(!game.get_player().borrow().is_vulnerable());
+        for _ in 0..10 {
+            game.run_frame(0);
+        }
+        assert_eq!(game.get_player().borrow().lives, 1);
+    }
+
+    #[test]
+    fn enemies() {
+        let game = Rc::new(RefCell::new(new_game()));
+        let enemy = Enemy::new(Position::new(192., 100.), 500, 0, 0, false, Weak::new(), Rc::downgrade(&game));
+        enemy.borrow_mut().hitbox_half_size = [16., 16.];
+
+        // Shots damage the enemy they hit, only once.
+        for _ in 0..40 {
+            game.borrow_mut().run_frame(KEY_SHOOT);
+        }
+        for _ in 0..60 {
+            game.borrow_mut().run_frame(0);
+        }
+        assert!(game.borrow().player_bullets.is_empty());
+        let life = enemy.borrow().life;
+        assert!(life < 500);
+        assert_eq!((500 - life) % 12, 0);
+        assert_eq!(game.borrow().get_player().borrow().score, (500 - life) / 12 * 20);
+
+        // Touching an enemy hurts both, once the player isn’t invulnerable anymore.
+        enemy.borrow_mut().pos = Position::new(192., 384.);
+        game.borrow_mut().run_frame(0);
+        assert_eq!(enemy.borrow().life, life - 10);
+        assert!(game.borrow().get_player().borrow().invulnerable_time > 0);
+        assert_eq!(game.borrow().get_player().borrow().lives, 2);
+        game.borrow().get_player().borrow_mut().invulnerable_time = 1;
+        game.borrow_mut().run_frame(0);
+        assert_eq!(enemy.borrow().life, life - 20);
+        assert!(!game.borrow().get_player().borrow().is_vulnerable());
+    }
+
+    #[test]
+    fn laser_damages() {
+        let game = Rc::new(RefCell::new(new_game()));
+        game.borrow_mut().set_player_shts(laser_sht(), laser_sht());
+        let enemy = Enemy::new(Position::new(192., 100.), 500, 0, 0, false, Weak::new(), Rc::downgrade(&game));
+        enemy.borrow_mut().hitbox_half_size = [16., 16.];
+
+        // The laser only gets placed on the frame after it got fired, then hurts on every frame.
+        game.borrow_mut().run_frame(KEY_SHOOT);
+        for _ in 0..10 {
+            game.borrow_mut().run_frame(0);
+        }
+        assert_eq!(enemy.borrow().life, 500 - 10 * 12);
+
+        // Damages get divided by seven during spellcards.
+        game.borrow_mut().spellcard = Some((0, String::new(), 0));
+        for _ in 0..10 {
+            game.borrow_mut().run_frame(0);
+        }
+        assert_eq!(enemy.borrow().life, 500 - 10 * 12 - 10);
+    }
+}
@@ -79,6 +79,9 @@ impl StageRunner {
                 }
                 Instruction::Unknown(_, _, _) => {
                 }
+                Instruction::UnknownOpcode { .. } => {
+                    // Unknown to us, kept by the parser but skipped here.
+                }
             }
         }
 
@@ -106,7 +106,7 @@ fn main() {
 
     // Open the ANM file.
     let buf = load_file_into_vec(anm_filename).unwrap();
-    let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
+    let mut anms = Anm0::from_slice(&buf).unwrap();
     let anm0 = anms.pop().unwrap();
 
     if !anm0.scripts.contains_key(&script) {
@@ -8,8 +8,8 @@ use luminance::tess::{Mode, TessBuilder};
 use luminance::texture::Dim2;
 use luminance_derive::{Semantics, Vertex, UniformInterface};
 use luminance_glfw::{Action, Key, WindowEvent, GlfwSurface, Surface, WindowDim, WindowOpt};
-use touhou_formats::th06::anm0::Anm0;
-use touhou_formats::th06::ecl::{Ecl, Rank};
+use touhou_formats::th06::ecl::Rank;
+use touhou_formats::th06::loader::ResourceLoader;
 use touhou_interpreters::th06::anm0::{Sprite, Vertex as FakeVertex};
 use touhou_interpreters::th06::ecl::EclRunner;
 use touhou_interpreters::th06::enemy::{Enemy, Game, Position};
@@ -20,7 +20,7 @@ use std::rc::Rc;
 use std::env;
 use std::path::Path;
 
-use touhou_runners::common::{load_file_into_vec, load_anm_image, LoadedTexture};
+use touhou_runners::common::{load_anm_image_from_loader, LoadedTexture};
 
 const VS: &str = r#"
 in ivec3 in_position;
@@ -89,23 +89,24 @@ struct ShaderInterface {
 fn main() {
     // Parse arguments.
     let args: Vec<_> = env::args().collect();
-    if args.len() != 5 {
-        eprintln!("Usage: {} <ECL file> <ANM file> <easy|normal|hard|lunatic> <sub number>", args[0]);
+    if args.len() != 6 {
+        eprintln!("Usage: {} <game or unarchived directory> <ECL file> <ANM file> <easy|normal|hard|lunatic> <sub number>", args[0]);
         return;
     }
-    let ecl_filename = Path::new(&args[1]);
-    let anm_filename = Path::new(&args[2]);
-    let rank: Rank = args[3].parse().expect("rank");
-    let sub: u16 = args[4].parse().expect("number");
+    let directory = Path::new(&args[1]);
+    let ecl_filename = &args[2];
+    let anm_filename = &args[3];
+    let rank: Rank = args[4].parse().expect("rank");
+    let sub: u16 = args[5].parse().expect("number");
+
+    // Mount the archives of the game, or the files extracted from them.
+    let mut loader = ResourceLoader::from_game_dir(directory).expect("game directory");
 
     // Open the ECL file.
-    let buf = load_file_into_vec(ecl_filename).unwrap();
-    let (_, ecl) = Ecl::from_slice(&buf).unwrap();
+    let ecl = loader.get_ecl(ecl_filename).unwrap();
 
     // Open the ANM file.
-    let buf = load_file_into_vec(anm_filename).unwrap();
-    let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
-    let anm0 = anms.pop().unwrap();
+    let anm0 = loader.get_anm(anm_filename).unwrap().last().unwrap().clone();
     let anm0 = Rc::new(RefCell::new([anm0.clone(), anm0]));
 
     if ecl.subs.len() < sub as usize {
@@ -113,12 +114,18 @@ fn main() {
         return;
     }
 
+    // Bullets use etama3.anm, when the game provides it.
+    let etama = loader.get_anm("etama3.anm").ok().map(|anms| anms[0].clone());
+
     // Get the time since January 1970 as a seed for the PRNG.
     let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap();
     let prng = Rc::new(RefCell::new(Prng::new(time.subsec_micros() as u16)));
 
     // Create the Game god object.
-    let game = Game::new(prng, rank);
+    let mut game = Game::new(prng, rank);
+    if let Some(etama) = &etama {
+        game.set_bullet_anms(vec![etama.clone()]);
+    }
     let game = Rc::new(RefCell::new(game));
 
     // And the enemy object.
@@ -134,7 +141,8 @@ fn main() {
     let mut surface = GlfwSurface::new(WindowDim::Windowed(384, 448), "Touhou", WindowOpt::default()).unwrap();
 
     // Open the image atlas matching this ANM.
-    let tex = load_anm_image(&mut surface, &anm0.borrow()[0], &anm_filename).expect("image loading");
+    let tex = load_anm_image_from_loader(&mut surface, &anm0.borrow()[0], &loader).expect("image loading");
+    let bullet_tex = etama.as_ref().map(|etama| load_anm_image_from_loader(&mut surface, etama, &loader).expect("image loading"));
 
     // set the uniform interface to our type so that we can read textures from the shader
     let program =
@@ -182,7 +190,7 @@ fn main() {
                 enemy.update();
             }
             let mut game = game.borrow_mut();
-            game.run_frame();
+            game.run_frame(0);
             let sprites = game.get_sprites();
             fill_vertices_ptr(sprites, slice.as_mut_ptr());
         }
@@ -198,6 +206,11 @@ fn main() {
                     LoadedTexture::Rgba(tex) => pipeline.bind_texture(tex),
                     LoadedTexture::RgbaArray(tex) => unreachable!(),
                 };
+                let bound_bullet_tex = bullet_tex.as_ref().map(|tex| match tex {
+                    LoadedTexture::Rgb(tex) => pipeline.bind_texture(tex),
+                    LoadedTexture::Rgba(tex) => pipeline.bind_texture(tex),
+                    LoadedTexture::RgbaArray(_) => unreachable!(),
+                });
 
                 shd_gate.shade(&program, |iface, mut rdr_gate| {
                     // update the texture; strictly speaking, this update doesn’t do much: it just tells the GPU
@@ -219,6 +232,17 @@ fn main() {
                         // magic do the rest!
                         tess_gate.render(&tess);
                     });
+
+                    if let Some(bound_tex) = &bound_bullet_tex {
+                        iface.color_map.update(bound_tex);
+                        rdr_gate.render(&render_state, |mut tess_gate| {
+                            let game = game.borrow();
+                            for sprite in game.get_bullet_sprites(0) {
+                                fill_vertices_ptr(vec![sprite], tess.as_slice_mut().unwrap().as_mut_ptr());
+                                tess_gate.render(&tess);
+                            }
+                        });
+                    }
                 });
             });
 
@@ -1,4 +1,3 @@
-use ears::{Music, AudioController};
 use luminance::blending::{Equation, Factor};
 use luminance::context::GraphicsContext;
 use luminance::pipeline::{BoundTexture, PipelineState};
@@ -9,8 +8,9 @@ use luminance::tess::{Mode, TessBuilder};
 use luminance::texture::Dim2;
 use luminance_derive::{Semantics, Vertex, UniformInterface};
 use luminance_glfw::{Action, Key, WindowEvent, GlfwSurface, Surface, WindowDim, WindowOpt};
-use touhou_formats::th06::pbg3;
+use touhou_formats::th06::loader::ResourceLoader;
 use touhou_formats::th06::anm0::Anm0;
+use touhou_formats::th06::music::Track;
 use touhou_interpreters::th06::anm0::{AnmRunner, Sprite, Vertex as FakeVertex};
 use touhou_utils::math::{perspective, setup_camera, ortho_2d};
 use touhou_utils::prng::Prng;
@@ -19,7 +19,7 @@ use std::rc::Rc;
 use std::env;
 use std::path::Path;
 
-use touhou_runners::common::{self, LoadedTexture};
+use touhou_runners::common::{self, LoadedTexture, LoopingMusic};
 
 const VS: &str = r#"
 in ivec3 in_position;
@@ -97,26 +97,33 @@ fn main() {
     // Parse arguments.
     let args: Vec<_> = env::args().collect();
     if args.len() != 2 {
-        eprintln!("Usage: {} <unarchived directory>", args[0]);
+        eprintln!("Usage: {} <game directory>", args[0]);
         return;
     }
     let directory = Path::new(&args[1]);
+    let loader = ResourceLoader::from_game_dir(directory).expect("game directory");
 
-    let in_dat = directory.join("IN.DAT");
     // Since GLFW can be slow to create its window, let’s decode the splash screen in another
     // thread in the meantime.
-    let jpeg_thread = std::thread::spawn(|| {
-        let mut in_pbg3 = pbg3::from_path_buffered(in_dat).expect("IN.DAT present");
-        let jpeg = in_pbg3.get_file("th06logo.jpg", true).expect("th06logo.jpg in IN.DAT");
+    let in_loader = loader.clone();
+    let jpeg_thread = std::thread::spawn(move || {
+        let jpeg = in_loader.get_file("th06logo.jpg").expect("th06logo.jpg in IN.DAT");
         let image = common::load_from_data(&jpeg).expect("th06logo.jpg decodable");
         image
     });
 
+    // The loop points are stored alongside the other music data.
+    let track = loader.get_file("th06_01.pos")
+        .ok()
+        .and_then(|data| Track::from_slice(&data).ok());
+    if track.is_none() {
+        eprintln!("Music description “th06_01.pos” not found, continuing without looping data.");
+    }
+
     let music_filename = directory.join("bgm").join("th06_01.wav");
     let music_filename = music_filename.to_str().expect("non-UTF-8 music filename");
-    let music = match Music::new(music_filename) {
+    let mut music = match LoopingMusic::new(music_filename, track) {
         Ok(mut music) => {
-            music.set_looping(true);
             music.play();
             music
         }
@@ -147,9 +154,6 @@ fn main() {
         .build()
         .unwrap();
 
-    let tl_dat = directory.join("TL.DAT");
-    let mut tl_pbg3 = pbg3::from_path_buffered(tl_dat).expect("TL.DAT present");
-
     let mut back_buffer = surface.back_buffer().unwrap();
     let mut resize = false;
     let mut frame = 0;
@@ -177,15 +181,19 @@ fn main() {
             resize = false;
         }
 
+        if let Err(err) = music.update() {
+            eprintln!("Impossible to keep playing the music: {}", err);
+        }
+
         frame += 1;
         if frame == 60 {
-            let jpeg = tl_pbg3.get_file("title00.jpg", true).expect("title00.jpg in TL.DAT");
+            let jpeg = loader.get_file("title00.jpg").expect("title00.jpg in TL.DAT");
             let image = common::load_from_data(&jpeg).expect("th06logo.jpg decodable");
             common::reupload_texture_from_rgb_image(&mut background, image).expect("upload data to texture");
         }
 
         if frame >= 60 && z_pressed {
-            let jpeg = tl_pbg3.get_file("select00.jpg", true).expect("select00.jpg in TL.DAT");
+            let jpeg = loader.get_file("select00.jpg").expect("select00.jpg in TL.DAT");
             let image = common::load_from_data(&jpeg).expect("select00.jpg decodable");
             common::reupload_texture_from_rgb_image(&mut background, image).expect("upload data to texture");
         }
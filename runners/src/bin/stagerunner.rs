@@ -9,18 +9,24 @@ use luminance::texture::Dim2Array;
 use luminance_derive::{Semantics, Vertex, UniformInterface};
 use luminance_glfw::{Action, Key, WindowEvent, GlfwSurface, Surface, WindowDim, WindowOpt};
 use touhou_formats::th06::anm0::Anm0;
-use touhou_formats::th06::ecl::{Ecl, Rank, MainInstruction};
+use touhou_formats::th06::ecl::{Rank, MainInstruction};
+use touhou_formats::th06::exe::read_characters;
+use touhou_formats::th06::loader::ResourceLoader;
 use touhou_interpreters::th06::anm0::Vertex as FakeVertex;
 use touhou_interpreters::th06::ecl::EclRunner;
 use touhou_interpreters::th06::enemy::{Enemy, Game, Position};
+use touhou_interpreters::th06::player::{KEY_SHOOT, KEY_BOMB, KEY_FOCUS, KEY_UP, KEY_DOWN, KEY_LEFT, KEY_RIGHT};
 use touhou_utils::math::{perspective, setup_camera};
 use touhou_utils::prng::Prng;
 use std::cell::RefCell;
+use std::convert::TryInto;
 use std::rc::Rc;
 use std::env;
+use std::fs::File;
+use std::io;
 use std::path::Path;
 
-use touhou_runners::common::{load_file_into_vec, load_multiple_anm_images, LoadedTexture};
+use touhou_runners::common::{load_multiple_anm_images_from_loader, LoadedTexture};
 
 const VS: &str = r#"
 in ivec3 in_position;
@@ -99,39 +105,51 @@ fn main() {
     // Parse arguments.
     let args: Vec<_> = env::args().collect();
     if args.len() != 4 {
-        eprintln!("Usage: {} <unarchived ST.DAT directory> <stage number> <easy|normal|hard|lunatic>", args[0]);
+        eprintln!("Usage: {} <game or unarchived directory> <stage number> <easy|normal|hard|lunatic>", args[0]);
         return;
     }
     let directory = Path::new(&args[1]);
     let stage_number: u8 = args[2].parse().expect("stage");
     let rank: Rank = args[3].parse().expect("rank");
 
+    // Mount the archives of the game, or the files extracted from them.
+    let mut loader = ResourceLoader::from_game_dir(directory).expect("game directory");
+
     // Open the ECL file.
-    let buf = load_file_into_vec(directory.join(format!("ecldata{}.ecl", stage_number))).unwrap();
-    let (_, ecl) = Ecl::from_slice(&buf).unwrap();
+    let ecl = loader.get_stage_ecl(stage_number).unwrap();
     assert_eq!(ecl.mains.len(), 1);
     let main = ecl.mains[0].clone();
 
-    // Open the ANM file.
-    let anm_filename = directory.join(format!("stg{}enm.anm", stage_number));
-    let buf = load_file_into_vec(&anm_filename).unwrap();
-    let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
-    let anm0 = anms.pop().unwrap();
+    // Open both ANM files.
+    let anms: [Anm0; 2] = loader.get_stage_enemy_anms(stage_number).unwrap().try_into().expect("two enemy ANMs");
 
-    // Open the second ANM file.
-    let anm2_filename = directory.join(format!("stg{}enm2.anm", stage_number));
-    let buf = load_file_into_vec(&anm2_filename).unwrap();
-    let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
-    let anm0_bis = anms.pop().unwrap();
+    // Open the bullet ANMs, in the order expected by the bullet types.
+    let bullet_anms: Vec<Anm0> = ["etama3.anm", "etama4.anm"].iter()
+        .map(|name| loader.get_anm(name).unwrap()[0].clone())
+        .collect();
 
-    let anms = [anm0, anm0_bis];
+    // Open the ANM of Reimu, containing her animations and those of her bullets.
+    let player_anm = loader.get_anm("player00.anm").unwrap()[0].clone();
 
     // Get the time since January 1970 as a seed for the PRNG.
     let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap();
     let prng = Rc::new(RefCell::new(Prng::new(time.subsec_micros() as u16)));
 
     // Create the Game god object.
-    let game = Game::new(prng, rank);
+    let mut game = Game::new(prng, rank);
+    game.set_bullet_anms(bullet_anms.clone());
+    game.set_player_anm(player_anm.clone());
+
+    // Read the shots of Reimu A from the executable, if it is there.
+    let characters = File::open(directory.join("東方紅魔郷.exe"))
+        .and_then(|file| read_characters(io::BufReader::new(file)));
+    match characters {
+        Ok(mut characters) => {
+            let (sht, focused_sht) = characters.swap_remove(0);
+            game.set_player_shts(sht, focused_sht);
+        }
+        Err(err) => eprintln!("Couldn’t read the player shots, she won’t be able to shoot: {}", err),
+    }
     let game = Rc::new(RefCell::new(game));
 
     assert_eq!(std::mem::size_of::<Vertex>(), std::mem::size_of::<FakeVertex>());
@@ -143,8 +161,12 @@ fn main() {
     let mut surface = GlfwSurface::new(WindowDim::Windowed(384, 448), "Touhou", WindowOpt::default()).unwrap();
 
     // Open the image atlas matching this ANM.
-    let tex = load_multiple_anm_images(&mut surface, &anms, &anm_filename).expect("image loading");
+    let tex = load_multiple_anm_images_from_loader(&mut surface, &anms, &loader).expect("image loading");
     let anms = Rc::new(RefCell::new(anms));
+    let bullet_texs: Vec<_> = bullet_anms.iter()
+        .map(|anm0| load_multiple_anm_images_from_loader(&mut surface, std::slice::from_ref(anm0), &loader).expect("image loading"))
+        .collect();
+    let player_tex = load_multiple_anm_images_from_loader(&mut surface, std::slice::from_ref(&player_anm), &loader).expect("image loading");
 
     // set the uniform interface to our type so that we can read textures from the shader
     let program =
@@ -160,6 +182,7 @@ fn main() {
     let mut resize = false;
     let mut frame = 0;
     let mut ecl_runners = vec![];
+    let mut keystate = 0;
 
     'app: loop {
         for event in surface.poll_events() {
@@ -170,6 +193,24 @@ fn main() {
                     resize = true;
                 }
 
+                WindowEvent::Key(key, _, action, _) => {
+                    let mask = match key {
+                        Key::Z => KEY_SHOOT,
+                        Key::X => KEY_BOMB,
+                        Key::LeftShift => KEY_FOCUS,
+                        Key::Up => KEY_UP,
+                        Key::Down => KEY_DOWN,
+                        Key::Left => KEY_LEFT,
+                        Key::Right => KEY_RIGHT,
+                        _ => 0,
+                    };
+                    match action {
+                        Action::Press => keystate |= mask,
+                        Action::Release => keystate &= !mask,
+                        Action::Repeat => (),
+                    }
+                }
+
                 _ => (),
             }
         }
@@ -182,7 +223,7 @@ fn main() {
         for call in main.instructions.iter() {
             if call.time == frame {
                 let sub = call.sub;
-                let instr = call.instr;
+                let instr = call.instr.clone();
                 let (x, y, _z, life, bonus, score, mirror) = match instr {
                     MainInstruction::SpawnEnemy(x, y, z, life, bonus, score) => (x, y, z, life, bonus, score, false),
                     MainInstruction::SpawnEnemyMirrored(x, y, z, life, bonus, score) => (x, y, z, life, bonus, score, true),
@@ -213,6 +254,14 @@ fn main() {
                     LoadedTexture::Rgba(tex) => unreachable!(),
                     LoadedTexture::RgbaArray(tex) => pipeline.bind_texture(tex),
                 };
+                let bound_player_tex = match &player_tex {
+                    LoadedTexture::RgbaArray(tex) => pipeline.bind_texture(tex),
+                    _ => unreachable!(),
+                };
+                let bound_bullet_texs: Vec<_> = bullet_texs.iter().map(|tex| match tex {
+                    LoadedTexture::RgbaArray(tex) => pipeline.bind_texture(tex),
+                    _ => unreachable!(),
+                }).collect();
 
                 shd_gate.shade(&program, |iface, mut rdr_gate| {
                     // update the texture; strictly speaking, this update doesn’t do much: it just tells the GPU
@@ -232,7 +281,7 @@ fn main() {
 
                     rdr_gate.render(&render_state, |mut tess_gate| {
                         let mut game = game.borrow_mut();
-                        game.run_frame();
+                        game.run_frame(keystate);
 
                         for (x, y, z, sprite) in game.get_sprites() {
                             {
@@ -250,6 +299,44 @@ fn main() {
                             tess_gate.render(&tess);
                         }
                     });
+
+                    // The player, its options and its bullets all come from its own ANM.
+                    iface.color_map.update(&bound_player_tex);
+                    rdr_gate.render(&render_state, |mut tess_gate| {
+                        let game = game.borrow();
+                        for (x, y, z, sprite) in game.get_player_sprites() {
+                            {
+                                let mut slice = tess
+                                    .as_slice_mut()
+                                    .unwrap();
+
+                                let sprite = sprite.borrow();
+                                let fake_vertices = unsafe { std::mem::transmute::<*mut Vertex, &mut [FakeVertex; 4]>(slice.as_mut_ptr()) };
+                                sprite.fill_vertices(fake_vertices, x, y, z);
+                            }
+                            tess_gate.render(&tess);
+                        }
+                    });
+
+                    // Bullets are drawn on top of enemies, one pass per bullet ANM.
+                    for (anm, bound_tex) in bound_bullet_texs.iter().enumerate() {
+                        iface.color_map.update(bound_tex);
+                        rdr_gate.render(&render_state, |mut tess_gate| {
+                            let game = game.borrow();
+                            for (x, y, z, sprite) in game.get_bullet_sprites(anm) {
+                                {
+                                    let mut slice = tess
+                                        .as_slice_mut()
+                                        .unwrap();
+
+                                    let sprite = sprite.borrow();
+                                    let fake_vertices = unsafe { std::mem::transmute::<*mut Vertex, &mut [FakeVertex; 4]>(slice.as_mut_ptr()) };
+                                    sprite.fill_vertices(fake_vertices, x, y, z);
+                                }
+                                tess_gate.render(&tess);
+                            }
+                        });
+                    }
                 });
             });
 
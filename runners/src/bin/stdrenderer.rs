@@ -117,11 +117,11 @@ fn main() {
 
     // Open the STD file.
     let buf = load_file_into_vec(std_filename).unwrap();
-    let (_, stage) = Stage::from_slice(&buf).unwrap();
+    let stage = Stage::from_slice(&buf).unwrap();
 
     // Open the ANM file.
     let buf = load_file_into_vec(anm_filename).unwrap();
-    let (_, mut anms) = Anm0::from_slice(&buf).unwrap();
+    let mut anms = Anm0::from_slice(&buf).unwrap();
     let anm0 = anms.pop().unwrap();
 
     // TODO: seed this PRNG with a valid seed.
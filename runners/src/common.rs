@@ -3,8 +3,11 @@ use luminance::pixel::{NormRGB8UI, NormRGBA8UI};
 use luminance::texture::{Dim2, Dim2Array, Sampler, Texture, GenMipmaps};
 use luminance_glfw::GlfwSurface;
 use touhou_formats::th06::anm0::Anm0;
+use touhou_formats::th06::loader::ResourceLoader;
+use touhou_formats::th06::music::Track;
+use crate::openal as al;
 use std::fs::File;
-use std::io::{self, BufReader, Read};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
 use std::path::Path;
 
 pub fn load_file_into_vec<P: AsRef<Path>>(filename: P) -> io::Result<Vec<u8>> {
@@ -26,6 +29,7 @@ pub enum TextureLoadError {
     CannotOpenRgb(String, ImageError),
     CannotOpenAlpha(String, ImageError),
     AlphaToGrayscale(String),
+    CannotRead(String, io::Error),
 }
 
 fn open_rgb_png(path: &Path) -> Result<DynamicImage, TextureLoadError> {
@@ -82,17 +86,21 @@ pub fn load_rgb_texture(surface: &mut GlfwSurface, path: &Path) -> Result<Loaded
 }
 
 fn load_rgb_a_pngs(surface: &mut GlfwSurface, rgb: &Path, alpha: &Path) -> Result<LoadedTexture, TextureLoadError> {
-    let img = open_alpha_png(&alpha)?;
-    let alpha = match img.grayscale() {
+    let alpha_img = open_alpha_png(&alpha)?;
+    let rgb_img = open_rgb_png(&rgb)?;
+    upload_rgb_a_images(surface, rgb_img, alpha_img, alpha.to_str().unwrap())
+}
+
+fn upload_rgb_a_images(surface: &mut GlfwSurface, rgb: DynamicImage, alpha: DynamicImage, alpha_name: &str) -> Result<LoadedTexture, TextureLoadError> {
+    let (width, height) = alpha.dimensions();
+    let alpha = match alpha.grayscale() {
         DynamicImage::ImageLuma8(img) => img,
         _ => {
-            return Err(TextureLoadError::AlphaToGrayscale(alpha.to_str().unwrap().to_owned()))
+            return Err(TextureLoadError::AlphaToGrayscale(alpha_name.to_owned()))
         }
     };
-    let (width, height) = img.dimensions();
-    let img = open_rgb_png(&rgb)?;
-    assert_eq!((width, height), img.dimensions());
-    let texels = merge_rgb_alpha(&img, &alpha);
+    assert_eq!((width, height), rgb.dimensions());
+    let texels = merge_rgb_alpha(&rgb, &alpha);
 
     // create the luminance texture; the third argument is the number of mipmaps we want (leave it
     // to 0 for now) and the latest is a the sampler to use when sampling the texels in the
@@ -106,7 +114,22 @@ fn load_rgb_a_pngs(surface: &mut GlfwSurface, rgb: &Path, alpha: &Path) -> Resul
     Ok(LoadedTexture::Rgba(tex))
 }
 
+fn load_embedded_texture(surface: &mut GlfwSurface, texture: &touhou_formats::th06::thtx::Texture) -> Option<LoadedTexture> {
+    let texels: Vec<_> = texture.to_rgba8()?
+        .chunks_exact(4)
+        .map(|pixel| (pixel[0], pixel[1], pixel[2], pixel[3]))
+        .collect();
+    let tex =
+        Texture::new(surface, [texture.width as u32, texture.height as u32], 0, Sampler::default()).expect("luminance texture creation");
+    tex.upload(GenMipmaps::No, &texels).unwrap();
+    Some(LoadedTexture::Rgba(tex))
+}
+
 pub fn load_anm_image<P: AsRef<Path>>(mut surface: &mut GlfwSurface, anm0: &Anm0, anm_filename: P) -> Result<LoadedTexture, TextureLoadError> {
+    // Textures embedded in the ANM take precedence over external images.
+    if let Some(tex) = anm0.texture.as_ref().and_then(|texture| load_embedded_texture(surface, texture)) {
+        return Ok(tex);
+    }
     let anm_filename = anm_filename.as_ref();
     let png_filename = anm_filename.with_file_name(Path::new(&anm0.png_filename).file_name().unwrap());
     match anm0.alpha_filename {
@@ -122,19 +145,27 @@ pub fn load_anm_image<P: AsRef<Path>>(mut surface: &mut GlfwSurface, anm0: &Anm0
 
 fn load_array_texture(surface: &mut GlfwSurface, images: &[(&Path, &Path)]) -> Result<LoadedTexture, TextureLoadError> {
     let mut decoded = vec![];
-    let dimensions = (256, 256);
     for (rgb, alpha) in images {
-        let img = open_alpha_png(&alpha)?;
-        assert_eq!(dimensions, img.dimensions());
-        let alpha = match img.grayscale() {
+        let alpha_name = alpha.to_str().unwrap().to_owned();
+        decoded.push((open_rgb_png(&rgb)?, open_alpha_png(&alpha)?, alpha_name));
+    }
+    upload_array_texture(surface, decoded)
+}
+
+fn upload_array_texture(surface: &mut GlfwSurface, images: Vec<(DynamicImage, DynamicImage, String)>) -> Result<LoadedTexture, TextureLoadError> {
+    let mut decoded = vec![];
+    let dimensions = (256, 256);
+    let nb_images = images.len() as u32;
+    for (rgb, alpha, alpha_name) in images {
+        assert_eq!(dimensions, alpha.dimensions());
+        let alpha = match alpha.grayscale() {
             DynamicImage::ImageLuma8(img) => img,
             _ => {
-                return Err(TextureLoadError::AlphaToGrayscale(alpha.to_str().unwrap().to_owned()))
+                return Err(TextureLoadError::AlphaToGrayscale(alpha_name))
             }
         };
-        let img = open_rgb_png(&rgb)?;
-        assert_eq!(dimensions, img.dimensions());
-        let texels = merge_rgb_alpha(&img, &alpha);
+        assert_eq!(dimensions, rgb.dimensions());
+        let texels = merge_rgb_alpha(&rgb, &alpha);
         decoded.push(texels);
     }
 
@@ -142,7 +173,7 @@ fn load_array_texture(surface: &mut GlfwSurface, images: &[(&Path, &Path)]) -> R
     // to 0 for now) and the latest is a the sampler to use when sampling the texels in the
     // shader (we’ll just use the default one)
     let tex =
-        Texture::new(surface, ([dimensions.0, dimensions.1], images.len() as u32), 0, Sampler::default()).expect("luminance texture creation");
+        Texture::new(surface, ([dimensions.0, dimensions.1], nb_images), 0, Sampler::default()).expect("luminance texture creation");
 
     // the first argument disables mipmap generation (we don’t care so far)
     tex.upload(GenMipmaps::No, &decoded.into_iter().flatten().collect::<Vec<_>>()).unwrap();
@@ -162,3 +193,238 @@ pub fn load_multiple_anm_images<P: AsRef<Path>>(mut surface: &mut GlfwSurface, a
     let paths: Vec<_> = paths.iter().map(|(rgb, alpha)| (rgb.as_ref(), alpha.as_ref())).collect();
     load_array_texture(&mut surface, paths.as_slice())
 }
+
+/// Read an image referenced by an ANM from the loader, ignoring the directory it was in.
+fn open_png_from_loader(loader: &ResourceLoader, filename: &str, error: fn(String, ImageError) -> TextureLoadError) -> Result<DynamicImage, TextureLoadError> {
+    let name = Path::new(filename).file_name().and_then(|name| name.to_str()).unwrap_or(filename);
+    let data = loader.get_file(name).map_err(|e| TextureLoadError::CannotRead(name.to_owned(), e))?;
+    image::load_from_memory(&data).map_err(|e| error(name.to_owned(), e))
+}
+
+/// Like `load_anm_image`, but reading the images from the loader.
+pub fn load_anm_image_from_loader(surface: &mut GlfwSurface, anm0: &Anm0, loader: &ResourceLoader) -> Result<LoadedTexture, TextureLoadError> {
+    // Textures embedded in the ANM take precedence over external images.
+    if let Some(tex) = anm0.texture.as_ref().and_then(|texture| load_embedded_texture(surface, texture)) {
+        return Ok(tex);
+    }
+    let rgb = open_png_from_loader(loader, &anm0.png_filename, TextureLoadError::CannotOpenRgb)?;
+    match anm0.alpha_filename {
+        Some(ref filename) => {
+            let alpha = open_png_from_loader(loader, filename, TextureLoadError::CannotOpenAlpha)?;
+            upload_rgb_a_images(surface, rgb, alpha, filename)
+        }
+        None => upload_texture_from_rgb_image(surface, rgb),
+    }
+}
+
+pub fn load_multiple_anm_images_from_loader(surface: &mut GlfwSurface, anms: &[Anm0], loader: &ResourceLoader) -> Result<LoadedTexture, TextureLoadError> {
+    let mut images = vec![];
+    for anm0 in anms.iter() {
+        let rgb = open_png_from_loader(loader, &anm0.png_filename, TextureLoadError::CannotOpenRgb)?;
+        let alpha_filename = anm0.alpha_filename.as_ref().expect("Can’t not have alpha here!");
+        let alpha = open_png_from_loader(loader, alpha_filename, TextureLoadError::CannotOpenAlpha)?;
+        images.push((rgb, alpha, alpha_filename.clone()));
+    }
+    upload_array_texture(surface, images)
+}
+
+/// Number of buffers queued at once.
+const NB_BUFFERS: usize = 4;
+
+/// Size of each buffer, in samples, so that a few frames taking too long don’t starve the source.
+const BUFFER_SAMPLES: u32 = 8192;
+
+/// What we need to know to stream the PCM data of a WAV file.
+struct WavInfo {
+    format: al::ALenum,
+    frequency: al::ALsizei,
+    block_align: u32,
+
+    /// Offset of the PCM data in the file.
+    data_offset: u64,
+
+    /// Number of samples in the file, across all channels.
+    nb_samples: u32,
+}
+
+fn read_wav_header<R: Read + Seek>(file: &mut R) -> io::Result<WavInfo> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid WAV file");
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(invalid());
+    }
+    let mut fmt = None;
+    let mut data = None;
+    while fmt.is_none() || data.is_none() {
+        let mut chunk = [0u8; 8];
+        file.read_exact(&mut chunk)?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        match &chunk[..4] {
+            b"fmt " => {
+                let mut buf = vec![0u8; size as usize];
+                file.read_exact(&mut buf)?;
+                fmt = Some(buf);
+            }
+            b"data" => {
+                data = Some((file.seek(SeekFrom::Current(0))?, size));
+                file.seek(SeekFrom::Current(size as i64))?;
+            }
+            _ => {
+                file.seek(SeekFrom::Current(size as i64))?;
+            }
+        }
+        // Chunks are padded to an even size.
+        if size % 2 != 0 {
+            file.seek(SeekFrom::Current(1))?;
+        }
+    }
+    let (fmt, (data_offset, data_size)) = match (fmt, data) {
+        (Some(fmt), Some(data)) if fmt.len() >= 16 => (fmt, data),
+        _ => return Err(invalid()),
+    };
+
+    let channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+    let frequency = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+    let block_align = u16::from_le_bytes([fmt[12], fmt[13]]) as u32;
+    let bits = u16::from_le_bytes([fmt[14], fmt[15]]);
+    let format = match (u16::from_le_bytes([fmt[0], fmt[1]]), channels, bits) {
+        (1, 1, 8) => al::AL_FORMAT_MONO8,
+        (1, 1, 16) => al::AL_FORMAT_MONO16,
+        (1, 2, 8) => al::AL_FORMAT_STEREO8,
+        (1, 2, 16) => al::AL_FORMAT_STEREO16,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unsupported WAV format")),
+    };
+    if block_align == 0 {
+        return Err(invalid());
+    }
+    Ok(WavInfo {
+        format,
+        frequency: frequency as al::ALsizei,
+        block_align,
+        data_offset,
+        nb_samples: data_size / block_align,
+    })
+}
+
+/// Background music honouring the loop points of its .pos file, instead of restarting from the
+/// intro once it ends.
+///
+/// The file is streamed through a few OpenAL buffers, and the samples queued go back to the
+/// start of the loop right after its end, so that playback wraps exactly there whenever the
+/// buffers get refilled.
+pub struct LoopingMusic {
+    file: BufReader<File>,
+    wav: WavInfo,
+    track: Track,
+
+    /// Next sample to be queued.
+    position: u32,
+
+    source: al::ALuint,
+    buffers: [al::ALuint; NB_BUFFERS],
+    playing: bool,
+}
+
+impl LoopingMusic {
+    pub fn new(filename: &str, track: Option<Track>) -> io::Result<LoopingMusic> {
+        ears::init().map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        let mut file = BufReader::new(File::open(filename)?);
+        let wav = read_wav_header(&mut file)?;
+        let track = match track {
+            Some(track) if track.start < track.end && track.end <= wav.nb_samples => track,
+            // Without any usable loop points, the best we can do is to loop over the whole file.
+            _ if wav.nb_samples > 0 => Track { start: 0, end: wav.nb_samples },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty WAV file")),
+        };
+
+        let mut source = 0;
+        let mut buffers = [0; NB_BUFFERS];
+        unsafe {
+            al::alGenSources(1, &mut source);
+            al::alGenBuffers(NB_BUFFERS as al::ALsizei, buffers.as_mut_ptr());
+        }
+        let mut music = LoopingMusic {
+            file,
+            wav,
+            track,
+            position: 0,
+            source,
+            buffers,
+            playing: false,
+        };
+        music.seek(0)?;
+        for &buffer in buffers.iter() {
+            music.queue(buffer)?;
+        }
+        Ok(music)
+    }
+
+    fn seek(&mut self, sample: u32) -> io::Result<()> {
+        let offset = self.wav.data_offset + sample as u64 * self.wav.block_align as u64;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.position = sample;
+        Ok(())
+    }
+
+    /// Fill this buffer with the next samples and queue it, going back to the start of the loop
+    /// once its end has been reached.
+    fn queue(&mut self, buffer: al::ALuint) -> io::Result<()> {
+        let nb_samples = BUFFER_SAMPLES.min(self.track.end - self.position);
+        let mut data = vec![0u8; (nb_samples * self.wav.block_align) as usize];
+        self.file.read_exact(&mut data)?;
+        self.position += nb_samples;
+        if let Some(sample) = self.track.loop_offset(self.position) {
+            self.seek(sample)?;
+        }
+        unsafe {
+            al::alBufferData(buffer, self.wav.format, data.as_ptr() as *const _, data.len() as al::ALsizei, self.wav.frequency);
+            al::alSourceQueueBuffers(self.source, 1, &buffer);
+        }
+        Ok(())
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+        unsafe { al::alSourcePlay(self.source) };
+    }
+
+    /// Must be called regularly, for instance every frame, to queue the next samples in place of
+    /// those which have been played.
+    ///
+    /// After an error the music stops, and this does nothing anymore.
+    pub fn update(&mut self) -> io::Result<()> {
+        if !self.playing {
+            return Ok(());
+        }
+        let mut processed = 0;
+        unsafe { al::alGetSourcei(self.source, al::AL_BUFFERS_PROCESSED, &mut processed) };
+        for _ in 0..processed {
+            let mut buffer = 0;
+            unsafe { al::alSourceUnqueueBuffers(self.source, 1, &mut buffer) };
+            if let Err(err) = self.queue(buffer) {
+                self.playing = false;
+                unsafe { al::alSourceStop(self.source) };
+                return Err(err);
+            }
+        }
+
+        // The source stops by itself when it runs out of buffers, if we were called too late.
+        let mut state = 0;
+        unsafe { al::alGetSourcei(self.source, al::AL_SOURCE_STATE, &mut state) };
+        if state != al::AL_PLAYING {
+            unsafe { al::alSourcePlay(self.source) };
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LoopingMusic {
+    fn drop(&mut self) {
+        unsafe {
+            al::alSourceStop(self.source);
+            al::alDeleteSources(1, &self.source);
+            al::alDeleteBuffers(NB_BUFFERS as al::ALsizei, self.buffers.as_ptr());
+        }
+    }
+}
@@ -1 +1,2 @@
 pub mod common;
+mod openal;
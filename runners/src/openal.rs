@@ -0,0 +1,34 @@
+//! The few OpenAL functions ears doesn’t expose, to stream our own buffers.
+//!
+//! ears already links against OpenAL and creates its context, these are only called once
+//! `ears::init()` succeeded.
+
+use std::os::raw::{c_int, c_uint, c_void};
+
+pub type ALuint = c_uint;
+pub type ALint = c_int;
+pub type ALsizei = c_int;
+pub type ALenum = c_int;
+
+pub const AL_SOURCE_STATE: ALenum = 0x1010;
+pub const AL_PLAYING: ALint = 0x1012;
+pub const AL_BUFFERS_PROCESSED: ALenum = 0x1016;
+pub const AL_FORMAT_MONO8: ALenum = 0x1100;
+pub const AL_FORMAT_MONO16: ALenum = 0x1101;
+pub const AL_FORMAT_STEREO8: ALenum = 0x1102;
+pub const AL_FORMAT_STEREO16: ALenum = 0x1103;
+
+#[cfg_attr(target_os = "macos", link(name = "OpenAL", kind = "framework"))]
+#[cfg_attr(not(target_os = "macos"), link(name = "openal"))]
+extern "C" {
+    pub fn alGenSources(n: ALsizei, sources: *mut ALuint);
+    pub fn alDeleteSources(n: ALsizei, sources: *const ALuint);
+    pub fn alGenBuffers(n: ALsizei, buffers: *mut ALuint);
+    pub fn alDeleteBuffers(n: ALsizei, buffers: *const ALuint);
+    pub fn alBufferData(buffer: ALuint, format: ALenum, data: *const c_void, size: ALsizei, frequency: ALsizei);
+    pub fn alSourceQueueBuffers(source: ALuint, n: ALsizei, buffers: *const ALuint);
+    pub fn alSourceUnqueueBuffers(source: ALuint, n: ALsizei, buffers: *mut ALuint);
+    pub fn alGetSourcei(source: ALuint, param: ALenum, value: *mut ALint);
+    pub fn alSourcePlay(source: ALuint);
+    pub fn alSourceStop(source: ALuint);
+}
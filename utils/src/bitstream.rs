@@ -3,13 +3,22 @@
 use std::io;
 
 /// Wrapper around any `Read` trait, to allow bit operations.
-pub struct BitStream<R: io::Read + io::Seek> {
+pub struct BitStream<R: io::Read> {
     io: R,
     remaining_bits: usize,
     byte: u8,
 }
 
 impl<R: io::Read + io::Seek> BitStream<R> {
+    /// Seek inside the bitstream, ditching any unused data read.
+    pub fn seek(&mut self, seek_from: io::SeekFrom) -> io::Result<u64> {
+        self.remaining_bits = 0;
+        self.byte = 0;
+        self.io.seek(seek_from)
+    }
+}
+
+impl<R: io::Read> BitStream<R> {
     /// Create a new bitstream.
     pub fn new(io: R) -> BitStream<R> {
         BitStream {
@@ -19,11 +28,15 @@ impl<R: io::Read + io::Seek> BitStream<R> {
         }
     }
 
-    /// Seek inside the bitstream, ditching any unused data read.
-    pub fn seek(&mut self, seek_from: io::SeekFrom) -> io::Result<u64> {
-        self.remaining_bits = 0;
-        self.byte = 0;
-        self.io.seek(seek_from)
+    /// Return a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.io
+    }
+
+    /// Return a mutable reference to the underlying reader, reading from it directly skips any
+    /// bit not read yet.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.io
     }
 
     fn fill_byte(&mut self) -> io::Result<()> {
@@ -69,6 +82,91 @@ impl<R: io::Read + io::Seek> BitStream<R> {
     }
 }
 
+/// Wrapper around any `Write` trait, to allow bit operations.
+///
+/// This is the writing counterpart of `BitStream`, bits are packed starting
+/// from the most significant one of each byte.
+pub struct BitWriter<W: io::Write> {
+    io: W,
+    nb_bits: usize,
+    byte: u8,
+    written: u64,
+}
+
+impl<W: io::Write> BitWriter<W> {
+    /// Create a new bit writer.
+    pub fn new(io: W) -> BitWriter<W> {
+        BitWriter {
+            io,
+            nb_bits: 0,
+            byte: 0,
+            written: 0,
+        }
+    }
+
+    fn write_byte(&mut self) -> io::Result<()> {
+        self.io.write_all(&[self.byte])?;
+        self.written += 1;
+        self.nb_bits = 0;
+        self.byte = 0;
+        Ok(())
+    }
+
+    /// Write only one bit to the stream.
+    pub fn write_bit(&mut self, bit: bool) -> io::Result<()> {
+        if bit {
+            self.byte |= 0x80 >> self.nb_bits;
+        }
+        self.nb_bits += 1;
+        if self.nb_bits == 8 {
+            self.write_byte()?;
+        }
+        Ok(())
+    }
+
+    /// Write the `nb_bits` lowest bits of `value` to the stream.
+    pub fn write(&mut self, value: usize, nb_bits: usize) -> io::Result<()> {
+        for i in (0..nb_bits).rev() {
+            self.write_bit((value >> i) & 0x01 != 0)?;
+        }
+        Ok(())
+    }
+
+    /// Write a given amount of bytes, not necessarily aligned.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.nb_bits == 0 {
+            self.io.write_all(bytes)?;
+            self.written += bytes.len() as u64;
+            return Ok(());
+        }
+        for &byte in bytes {
+            self.write(byte as usize, 8)?;
+        }
+        Ok(())
+    }
+
+    /// Return the number of bytes started so far, including the current
+    /// incomplete one.
+    pub fn tell(&self) -> u64 {
+        self.written + if self.nb_bits > 0 { 1 } else { 0 }
+    }
+
+    /// Pad the current byte with zeroes and write it, so that the stream is
+    /// byte-aligned again.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.nb_bits > 0 {
+            self.write_byte()?;
+        }
+        self.io.flush()
+    }
+
+    /// Flush the stream and return the inner writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.io)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -140,4 +238,40 @@ mod tests {
         // Can’t read after the end.
         bitstream.read(1).unwrap_err();
     }
+
+    #[test]
+    fn write_bit_by_bit() {
+        let mut bitwriter = BitWriter::new(Vec::new());
+        for &bit in &[false, false, false, false, false, false, false, true,
+                      false, false, false, false, false, false, true, false] {
+            bitwriter.write_bit(bit).unwrap();
+        }
+        bitwriter.write_bit(true).unwrap();
+        assert_eq!(bitwriter.tell(), 3);
+        assert_eq!(bitwriter.into_inner().unwrap(), vec![1, 2, 128]);
+    }
+
+    #[test]
+    fn write_unaligned_bytes() {
+        let mut bitwriter = BitWriter::new(Vec::new());
+        bitwriter.write_bit(false).unwrap();
+        bitwriter.write(1, 8).unwrap();
+        bitwriter.write_bytes(&[2, 3]).unwrap();
+        bitwriter.write(0, 7).unwrap();
+        assert_eq!(bitwriter.into_inner().unwrap(), vec![0, 129, 1, 128]);
+    }
+
+    #[test]
+    fn write_then_read() {
+        let mut bitwriter = BitWriter::new(Vec::new());
+        bitwriter.write(0x5, 3).unwrap();
+        bitwriter.write(0x1234, 13).unwrap();
+        bitwriter.write(0xf, 4).unwrap();
+        let data = bitwriter.into_inner().unwrap();
+
+        let mut bitstream = BitStream::new(Cursor::new(data));
+        assert_eq!(bitstream.read(3).unwrap(), 0x5);
+        assert_eq!(bitstream.read(13).unwrap(), 0x1234);
+        assert_eq!(bitstream.read(4).unwrap(), 0xf);
+    }
 }
@@ -3,4 +3,5 @@
 pub mod bitstream;
 pub mod lzss;
 pub mod math;
+pub mod pe;
 pub mod prng;
@@ -1,10 +1,11 @@
 //! LZSS implementation.
 
 use std::io;
-use crate::bitstream::BitStream;
+use std::collections::HashMap;
+use crate::bitstream::{BitStream, BitWriter};
 
 /// Decompresses a LZSS-compressed file.
-pub fn decompress<R: io::Read + io::Seek>(bitstream: &mut BitStream<R>, size: usize, dictionary_size: usize, offset_size: usize, length_size: usize, minimum_match_length: usize) -> io::Result<Vec<u8>> {
+pub fn decompress<R: io::Read>(bitstream: &mut BitStream<R>, size: usize, dictionary_size: usize, offset_size: usize, length_size: usize, minimum_match_length: usize) -> io::Result<Vec<u8>> {
     let mut data = vec![0; size];
     let mut dictionary = vec![0; dictionary_size];
     let mut dictionary_head = 1;
@@ -41,10 +42,164 @@ pub fn decompress<R: io::Read + io::Seek>(bitstream: &mut BitStream<R>, size: us
     Ok(data)
 }
 
+/// Streaming counterpart of `decompress`, yielding the data as it gets read.
+pub struct Decompressor<R: io::Read> {
+    bitstream: BitStream<R>,
+    remaining: usize,
+    dictionary: Vec<u8>,
+    dictionary_head: usize,
+    offset_size: usize,
+    length_size: usize,
+    minimum_match_length: usize,
+
+    /// Position in the dictionary and remaining length of the match being copied.
+    copy: (usize, usize),
+}
+
+impl<R: io::Read> Decompressor<R> {
+    /// Create a decompressor reading `size` bytes of data compressed in `io`.
+    pub fn new(io: R, size: usize, dictionary_size: usize, offset_size: usize, length_size: usize, minimum_match_length: usize) -> Decompressor<R> {
+        Decompressor {
+            bitstream: BitStream::new(io),
+            remaining: size,
+            dictionary: vec![0; dictionary_size],
+            dictionary_head: 1,
+            offset_size,
+            length_size,
+            minimum_match_length,
+            copy: (0, 0),
+        }
+    }
+
+    /// Return a reference to the reader of compressed data.
+    pub fn get_ref(&self) -> &R {
+        self.bitstream.get_ref()
+    }
+
+    /// Return a mutable reference to the reader of compressed data.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.bitstream.get_mut()
+    }
+
+    /// Whether all of the data has been read.
+    pub fn is_finished(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.dictionary[self.dictionary_head] = byte;
+        self.dictionary_head = (self.dictionary_head + 1) % self.dictionary.len();
+    }
+
+    fn next_byte(&mut self) -> io::Result<u8> {
+        loop {
+            let (offset, length) = self.copy;
+            if length > 0 {
+                let byte = self.dictionary[offset % self.dictionary.len()];
+                self.copy = (offset + 1, length - 1);
+                self.push(byte);
+                return Ok(byte);
+            }
+            if self.bitstream.read_bit()? {
+                // A literal, see `decompress`.
+                let byte = self.bitstream.read(8)? as u8;
+                self.push(byte);
+                return Ok(byte);
+            }
+            let offset = self.bitstream.read(self.offset_size)?;
+            let length = self.bitstream.read(self.length_size)? + self.minimum_match_length;
+            if length > self.remaining {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Match longer than the remaining data"));
+            }
+            self.copy = (offset, length);
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.remaining);
+        for byte in buf[..len].iter_mut() {
+            *byte = self.next_byte()?;
+            self.remaining -= 1;
+        }
+        Ok(len)
+    }
+}
+
+/// Compresses a file using LZSS, in a way `decompress` can read back.
+///
+/// Matches are searched greedily in the sliding dictionary, and the stream
+/// is terminated by a null (offset, length) tuple like the original files.
+pub fn compress<W: io::Write>(bitstream: &mut BitWriter<W>, data: &[u8], dictionary_size: usize, offset_size: usize, length_size: usize, minimum_match_length: usize) -> io::Result<()> {
+    let maximum_match_length = (1 << length_size) - 1 + minimum_match_length;
+
+    // For each position, the previous position starting with the same bytes.
+    let mut previous = vec![usize::MAX; data.len()];
+    let mut heads = HashMap::new();
+
+    let mut ptr = 0;
+    while ptr < data.len() {
+        let mut match_length = 0;
+        let mut match_ptr = 0;
+
+        if ptr + minimum_match_length <= data.len() {
+            let key = &data[ptr..ptr + minimum_match_length];
+            let mut candidate = heads.get(key).cloned().unwrap_or(usize::MAX);
+            while candidate != usize::MAX && ptr - candidate < dictionary_size {
+                // An offset of zero is kept for the end marker, like in the original files.
+                if (candidate + 1) % dictionary_size != 0 {
+                    let length = data[candidate..].iter()
+                        .zip(&data[ptr..])
+                        .take(maximum_match_length)
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    if length > match_length {
+                        match_length = length;
+                        match_ptr = candidate;
+                        if length == maximum_match_length {
+                            break;
+                        }
+                    }
+                }
+                candidate = previous[candidate];
+            }
+        }
+
+        let length = if match_length >= minimum_match_length {
+            // Data at position `ptr` is stored at `ptr + 1` in the dictionary.
+            bitstream.write_bit(false)?;
+            bitstream.write((match_ptr + 1) % dictionary_size, offset_size)?;
+            bitstream.write(match_length - minimum_match_length, length_size)?;
+            match_length
+        } else {
+            bitstream.write_bit(true)?;
+            bitstream.write(data[ptr] as usize, 8)?;
+            1
+        };
+
+        for i in ptr..ptr + length {
+            if i + minimum_match_length <= data.len() {
+                let key = &data[i..i + minimum_match_length];
+                if let Some(head) = heads.insert(key, i) {
+                    previous[i] = head;
+                }
+            }
+        }
+        ptr += length;
+    }
+
+    // Terminate the stream.
+    bitstream.write_bit(false)?;
+    bitstream.write(0, offset_size)?;
+    bitstream.write(0, length_size)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Cursor;
+    use std::io::{Cursor, Read};
 
     #[test]
     #[ignore]
@@ -54,4 +209,63 @@ mod tests {
         let mut bitstream = BitStream::new(data);
         decompress(&mut bitstream, 3, 0x2000, 13, 4, 3).unwrap();
     }
+
+    #[test]
+    fn compress_roundtrip() {
+        let mut data = b"Hello world! Hello world! Hello hello hello world!".to_vec();
+        data.extend(vec![0u8; 100]);
+        data.extend((0..20000).map(|i| (i * 7 % 251) as u8));
+        data.extend(b"Hello world!");
+
+        let mut bitwriter = BitWriter::new(Vec::new());
+        compress(&mut bitwriter, &data, 0x2000, 13, 4, 3).unwrap();
+        let compressed = bitwriter.into_inner().unwrap();
+        assert!(compressed.len() < data.len());
+
+        let mut bitstream = BitStream::new(Cursor::new(compressed.clone()));
+        let decompressed = decompress(&mut bitstream, data.len(), 0x2000, 13, 4, 3).unwrap();
+        assert_eq!(decompressed, data);
+
+        // Streaming the same data in small reads must give the same result.
+        let mut decompressor = Decompressor::new(Cursor::new(compressed), data.len(), 0x2000, 13, 4, 3);
+        let mut streamed = vec![];
+        let mut buf = [0u8; 7];
+        loop {
+            let len = decompressor.read(&mut buf).unwrap();
+            if len == 0 {
+                break;
+            }
+            streamed.extend_from_slice(&buf[..len]);
+        }
+        assert!(decompressor.is_finished());
+        assert_eq!(streamed, data);
+    }
+
+    #[test]
+    fn compress_empty() {
+        let mut bitwriter = BitWriter::new(Vec::new());
+        compress(&mut bitwriter, b"", 0x2000, 13, 4, 3).unwrap();
+        assert_eq!(bitwriter.into_inner().unwrap(), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn zero_offset() {
+        // An offset of zero doesn’t end the stream, it reads the dictionary from its start.
+        let mut bitwriter = BitWriter::new(Vec::new());
+        for &byte in b"abc" {
+            bitwriter.write_bit(true).unwrap();
+            bitwriter.write(byte as usize, 8).unwrap();
+        }
+        bitwriter.write_bit(false).unwrap();
+        bitwriter.write(0, 13).unwrap();
+        bitwriter.write(0, 4).unwrap();
+        let compressed = bitwriter.into_inner().unwrap();
+
+        let mut bitstream = BitStream::new(Cursor::new(compressed.clone()));
+        assert_eq!(decompress(&mut bitstream, 6, 0x2000, 13, 4, 3).unwrap(), b"abc\0ab");
+        let mut decompressor = Decompressor::new(Cursor::new(compressed), 6, 0x2000, 13, 4, 3);
+        let mut data = vec![];
+        decompressor.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"abc\0ab");
+    }
 }
@@ -0,0 +1,230 @@
+//! PE executable module, only supporting what is needed to find data in the game’s binary.
+
+use std::io;
+
+/// A section header of a PE file.
+#[derive(Debug, Clone)]
+pub struct Section {
+    /// Name of this section, padded with NUL bytes.
+    pub name: [u8; 8],
+
+    /// Size of this section once loaded in memory.
+    pub virtual_size: u32,
+
+    /// Address of this section once loaded in memory, relative to the image base.
+    pub virtual_address: u32,
+
+    /// Size of this section in the file.
+    pub size_of_raw_data: u32,
+
+    /// Offset of this section in the file.
+    pub pointer_to_raw_data: u32,
+
+    /// Flags of this section.
+    pub characteristics: u32,
+}
+
+impl Section {
+    /// Return whether this section’s name starts with this prefix.
+    pub fn name_starts_with(&self, prefix: &[u8]) -> bool {
+        self.name.starts_with(prefix)
+    }
+}
+
+/// Wrapper around any `Read` trait, exposing the sections of a PE file.
+pub struct PEFile<R: io::Read + io::Seek> {
+    file: R,
+
+    /// Address at which this image expects to be loaded.
+    pub image_base: u32,
+
+    /// All sections of this image.
+    pub sections: Vec<Section>,
+}
+
+fn read_u16<R: io::Read>(file: &mut R) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    file.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32<R: io::Read>(file: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    file.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn out_of_sections() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Address outside of any section")
+}
+
+impl<R: io::Read + io::Seek> PEFile<R> {
+    /// Parse the headers of a PE file.
+    pub fn from_file(mut file: R) -> io::Result<PEFile<R>> {
+        file.seek(io::SeekFrom::Start(0x3c))?;
+        let pe_offset = read_u32(&mut file)?;
+
+        file.seek(io::SeekFrom::Start(pe_offset as u64))?;
+        let mut pe_sig = [0u8; 4];
+        file.read_exact(&mut pe_sig)?;
+        if &pe_sig != b"PE\0\0" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid PE signature"));
+        }
+
+        // File header, of which we only need the number of sections and the optional header size.
+        let _machine = read_u16(&mut file)?;
+        let number_of_sections = read_u16(&mut file)?;
+        file.seek(io::SeekFrom::Current(12))?;
+        let size_of_optional_header = read_u16(&mut file)?;
+        let _characteristics = read_u16(&mut file)?;
+
+        // Optional header, of which we only need the image base.
+        let optional_header_offset = file.stream_position()?;
+        file.seek(io::SeekFrom::Current(28))?;
+        let image_base = read_u32(&mut file)?;
+        file.seek(io::SeekFrom::Start(optional_header_offset + size_of_optional_header as u64))?;
+
+        let mut sections = Vec::with_capacity(number_of_sections as usize);
+        for _ in 0..number_of_sections {
+            let mut name = [0u8; 8];
+            file.read_exact(&mut name)?;
+            let virtual_size = read_u32(&mut file)?;
+            let virtual_address = read_u32(&mut file)?;
+            let size_of_raw_data = read_u32(&mut file)?;
+            let pointer_to_raw_data = read_u32(&mut file)?;
+            // Relocations and line numbers are useless for us.
+            file.seek(io::SeekFrom::Current(12))?;
+            let characteristics = read_u32(&mut file)?;
+            sections.push(Section {
+                name,
+                virtual_size,
+                virtual_address,
+                size_of_raw_data,
+                pointer_to_raw_data,
+                characteristics,
+            });
+        }
+
+        Ok(PEFile {
+            file,
+            image_base,
+            sections,
+        })
+    }
+
+    /// Return the first section whose name starts with this prefix.
+    pub fn find_section(&self, prefix: &[u8]) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name_starts_with(prefix))
+    }
+
+    /// Convert a file offset into an address relative to the image base.
+    pub fn offset_to_rva(&self, offset: u32) -> io::Result<u32> {
+        for section in self.sections.iter() {
+            if offset.wrapping_sub(section.pointer_to_raw_data) < section.size_of_raw_data {
+                return Ok(offset - section.pointer_to_raw_data + section.virtual_address);
+            }
+        }
+        Err(out_of_sections())
+    }
+
+    /// Convert a file offset into a virtual address.
+    pub fn offset_to_va(&self, offset: u32) -> io::Result<u32> {
+        Ok(self.offset_to_rva(offset)?.wrapping_add(self.image_base))
+    }
+
+    /// Convert an address relative to the image base into a file offset.
+    pub fn rva_to_offset(&self, rva: u32) -> io::Result<u32> {
+        for section in self.sections.iter() {
+            if rva.wrapping_sub(section.virtual_address) < section.size_of_raw_data {
+                return Ok(rva - section.virtual_address + section.pointer_to_raw_data);
+            }
+        }
+        Err(out_of_sections())
+    }
+
+    /// Convert a virtual address into a file offset.
+    pub fn va_to_offset(&self, va: u32) -> io::Result<u32> {
+        self.rva_to_offset(va.wrapping_sub(self.image_base))
+    }
+
+    /// Seek the underlying file to this virtual address.
+    pub fn seek_to_va(&mut self, va: u32) -> io::Result<u64> {
+        let offset = self.va_to_offset(va)?;
+        self.file.seek(io::SeekFrom::Start(offset as u64))
+    }
+
+    /// Fill this buffer with the data found at this virtual address.
+    pub fn read_at_va(&mut self, va: u32, buf: &mut [u8]) -> io::Result<()> {
+        self.seek_to_va(va)?;
+        self.file.read_exact(buf)
+    }
+
+    /// Return the underlying file.
+    pub fn into_inner(self) -> R {
+        self.file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Build a minimal PE image, with the given sections mapped at 0x1000 intervals.
+    fn build_test_image(image_base: u32, sections: &[(&[u8; 8], &[u8])]) -> Vec<u8> {
+        let headers_size = 0x40 + 4 + 20 + 96 + 40 * sections.len();
+        let mut raw_offset = (headers_size as u32 + 0x1ff) & !0x1ff;
+
+        let mut data = vec![0u8; 0x40];
+        data[0..2].copy_from_slice(b"MZ");
+        data[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        data.extend(b"PE\0\0");
+        data.extend(&0x14cu16.to_le_bytes());
+        data.extend(&(sections.len() as u16).to_le_bytes());
+        data.extend(&[0; 12]);
+        data.extend(&96u16.to_le_bytes());
+        data.extend(&0u16.to_le_bytes());
+        let mut optional_header = vec![0u8; 96];
+        optional_header[0..2].copy_from_slice(&0x10bu16.to_le_bytes());
+        optional_header[28..32].copy_from_slice(&image_base.to_le_bytes());
+        data.extend(&optional_header);
+
+        let mut contents = vec![];
+        for (i, (name, section)) in sections.iter().enumerate() {
+            data.extend(&name[..]);
+            data.extend(&(section.len() as u32).to_le_bytes());
+            data.extend(&(0x1000 * (i as u32 + 1)).to_le_bytes());
+            data.extend(&(section.len() as u32).to_le_bytes());
+            data.extend(&raw_offset.to_le_bytes());
+            data.extend(&[0; 12]);
+            data.extend(&0u32.to_le_bytes());
+            contents.push(raw_offset);
+            raw_offset += section.len() as u32;
+        }
+        for (offset, (_, section)) in contents.iter().zip(sections.iter()) {
+            data.resize(*offset as usize, 0);
+            data.extend(&section[..]);
+        }
+        data
+    }
+
+    #[test]
+    fn sections() {
+        let data = build_test_image(0x400000, &[(b".text\0\0\0", &[0x90; 16]), (b".data\0\0\0", b"Hello, world!\0")]);
+        let mut pe = PEFile::from_file(Cursor::new(data)).unwrap();
+        assert_eq!(pe.image_base, 0x400000);
+        assert_eq!(pe.sections.len(), 2);
+
+        let section = pe.find_section(b".data").unwrap();
+        assert_eq!(section.virtual_address, 0x2000);
+        let offset = section.pointer_to_raw_data;
+        assert_eq!(pe.offset_to_va(offset + 7).unwrap(), 0x402007);
+        assert_eq!(pe.va_to_offset(0x402007).unwrap(), offset + 7);
+
+        let mut buf = [0u8; 5];
+        pe.read_at_va(0x402007, &mut buf).unwrap();
+        assert_eq!(&buf, b"world");
+        pe.seek_to_va(0x403000).unwrap_err();
+        pe.offset_to_rva(0).unwrap_err();
+    }
+}